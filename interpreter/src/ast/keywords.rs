@@ -3,21 +3,29 @@ use super::tokentype::TokenType;
 pub fn get_keyword_token_type(keyword: &str) -> Option<TokenType> {
     match keyword {
         "and" => Some(TokenType::And),
+        "break" => Some(TokenType::Break),
+        "case" => Some(TokenType::Case),
         "class" => Some(TokenType::Class),
+        "const" => Some(TokenType::Const),
+        "continue" => Some(TokenType::Continue),
+        "default" => Some(TokenType::Default),
         "else" => Some(TokenType::Else),
         "false" => Some(TokenType::False),
         "for" => Some(TokenType::For),
         "fun" => Some(TokenType::Fun),
         "if" => Some(TokenType::If),
+        "in" => Some(TokenType::In),
         "nil" => Some(TokenType::Nil),
         "or" => Some(TokenType::Or),
         "print" => Some(TokenType::Print),
         "return" => Some(TokenType::Return),
         "super" => Some(TokenType::Super),
+        "switch" => Some(TokenType::Switch),
         "this" => Some(TokenType::This),
         "true" => Some(TokenType::True),
         "var" => Some(TokenType::Var),
         "while" => Some(TokenType::While),
+        "xor" => Some(TokenType::Xor),
         _ => None,
     }
 }
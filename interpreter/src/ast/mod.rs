@@ -1,5 +1,7 @@
 pub mod expr;
 pub mod keywords;
+pub mod span;
 pub mod stmt;
+pub mod symbol;
 pub mod token;
 pub mod tokentype;
@@ -1,90 +1,334 @@
+use crate::ast::span::Span;
 use crate::ast::token::Token;
-use crate::error::RuntimeError;
+use crate::error::Signal;
+use std::rc::Rc;
 
 use super::expr::Expr;
 
 pub trait Visitor<T> {
-    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<T, RuntimeError>;
-    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<T, RuntimeError>;
+    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<T, Signal>;
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<T, Signal>;
     fn visit_var_declaration_stmt(
         &mut self,
         identifier: &Token,
         initializer: Option<&Expr>,
-    ) -> Result<T, RuntimeError>;
-    fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<T, RuntimeError>;
+    ) -> Result<T, Signal>;
+    fn visit_const_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: &Expr,
+    ) -> Result<T, Signal>;
+    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<T, Signal>;
     fn visit_if_stmt(
         &mut self,
         expr: &Expr,
         stmt_then: &Stmt,
         stmt_else: &Option<Box<Stmt>>,
-    ) -> Result<T, RuntimeError>;
-    fn visit_while_stmt(&mut self, expr: &Expr, stmt: &Stmt) -> Result<T, RuntimeError>;
+    ) -> Result<T, Signal>;
+    fn visit_while_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt: &Stmt,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<T, Signal>;
     fn visit_function_stmt(
         &mut self,
         identifier: &Token,
-        prameters: &Vec<Token>,
-        body: &Box<Stmt>,
-    ) -> Result<T, RuntimeError>;
-    fn visit_return_stmt(&mut self, token: &Token, expr: &Expr) -> Result<T, RuntimeError>;
+        prameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<T, Signal>;
+    fn visit_return_stmt(&mut self, token: &Token, expr: &Expr) -> Result<T, Signal>;
+    fn visit_break_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<T, Signal>;
+    fn visit_continue_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<T, Signal>;
+    fn visit_switch_stmt(
+        &mut self,
+        expr: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: Option<&[Stmt]>,
+    ) -> Result<T, Signal>;
+    fn visit_for_in_stmt(
+        &mut self,
+        identifier: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+        label: Option<&Token>,
+    ) -> Result<T, Signal>;
 }
 
+/// Every variant ends with the span of the source it was parsed from.
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Print(Expr),
-    Expression(Expr),
-    VarDeclaration(Token, Option<Expr>),
-    Function(Token, Vec<Token>, Box<Stmt>),
-    Block(Vec<Stmt>),
-    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
-    Return(Token, Expr),
+    Print(Expr, Span),
+    Expression(Expr, Span),
+    VarDeclaration(Token, Option<Expr>, Span),
+    ConstDeclaration(Token, Expr, Span),
+    /// Name, parameters, each parameter's default value, and body.
+    Function(Token, Vec<Token>, Rc<[Option<Expr>]>, Rc<Stmt>, Span),
+    Block(Vec<Stmt>, Span),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>, Span),
+    /// Condition, body, the increment of the `for` loop it was parsed from,
+    /// which runs after the body even when it `continue`s, and label.
+    While(Expr, Box<Stmt>, Option<Expr>, Option<Token>, Span),
+    Return(Token, Expr, Span),
+    /// Keyword, and the label of the loop to break out of.
+    Break(Token, Option<Token>, Span),
+    /// Keyword, and the label of the loop to continue.
+    Continue(Token, Option<Token>, Span),
+    /// Scrutinee, each case's value and statements, then the default ones.
+    Switch(Expr, Vec<(Expr, Vec<Stmt>)>, Option<Vec<Stmt>>, Span),
+    /// Loop variable, iterated list, body, and label.
+    ForIn(Token, Expr, Box<Stmt>, Option<Token>, Span),
 }
 
 impl Stmt {
-    pub fn accept<T, U>(&self, visitor: &mut U) -> Result<T, RuntimeError>
+    /// The name of the kind of statement, like `"While"`.
+    pub fn kind(&self) -> &'static str {
+        use Stmt::*;
+
+        match self {
+            Print(..) => "Print",
+            Expression(..) => "Expression",
+            VarDeclaration(..) => "Var",
+            ConstDeclaration(..) => "Const",
+            Function(..) => "Function",
+            Block(..) => "Block",
+            If(..) => "If",
+            While(..) => "While",
+            Return(..) => "Return",
+            Break(..) => "Break",
+            Continue(..) => "Continue",
+            Switch(..) => "Switch",
+            ForIn(..) => "ForIn",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        use Stmt::*;
+
+        match *self {
+            Print(.., span)
+            | Expression(.., span)
+            | VarDeclaration(.., span)
+            | ConstDeclaration(.., span)
+            | Function(.., span)
+            | Block(.., span)
+            | If(.., span)
+            | While(.., span)
+            | Return(.., span)
+            | Break(.., span)
+            | Continue(.., span)
+            | Switch(.., span)
+            | ForIn(.., span) => span,
+        }
+    }
+
+    pub fn accept<T, U>(&self, visitor: &mut U) -> Result<T, Signal>
     where
         U: Visitor<T>,
     {
         use Stmt::*;
         match *self {
-            Print(ref expr) => visitor.visit_print_stmt(expr),
-            Expression(ref expr) => visitor.visit_expression_stmt(expr),
-            VarDeclaration(ref identifier, ref initializer) => {
+            Print(ref expr, _) => visitor.visit_print_stmt(expr),
+            Expression(ref expr, _) => visitor.visit_expression_stmt(expr),
+            VarDeclaration(ref identifier, ref initializer, _) => {
                 visitor.visit_var_declaration_stmt(identifier, initializer.as_ref())
             }
-            Block(ref stmts) => visitor.visit_block_stmt(stmts),
-            If(ref expr, ref stmt_then, ref stmt_else) => {
+            ConstDeclaration(ref identifier, ref initializer, _) => {
+                visitor.visit_const_declaration_stmt(identifier, initializer)
+            }
+            Block(ref stmts, _) => visitor.visit_block_stmt(stmts),
+            If(ref expr, ref stmt_then, ref stmt_else, _) => {
                 visitor.visit_if_stmt(expr, stmt_then, stmt_else)
             }
-            While(ref expr, ref stmt) => visitor.visit_while_stmt(expr, stmt),
-            Function(ref identifier, ref parameters, ref body) => {
-                visitor.visit_function_stmt(identifier, parameters, body)
+            While(ref expr, ref stmt, ref increment, ref label, _) => {
+                visitor.visit_while_stmt(expr, stmt, increment.as_ref(), label.as_ref())
+            }
+            Function(ref identifier, ref parameters, ref defaults, ref body, _) => {
+                visitor.visit_function_stmt(identifier, parameters, defaults, body)
+            }
+            Return(ref token, ref expr, _) => visitor.visit_return_stmt(token, expr),
+            Break(ref keyword, ref label, _) => visitor.visit_break_stmt(keyword, label.as_ref()),
+            Continue(ref keyword, ref label, _) => {
+                visitor.visit_continue_stmt(keyword, label.as_ref())
+            }
+            Switch(ref expr, ref cases, ref default, _) => {
+                visitor.visit_switch_stmt(expr, cases, default.as_deref())
+            }
+            ForIn(ref identifier, ref iterable, ref body, ref label, _) => {
+                visitor.visit_for_in_stmt(identifier, iterable, body, label.as_ref())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Stmt {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        use Stmt::*;
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.kind())?;
+        map.serialize_entry("span", &self.span())?;
+
+        match self {
+            Print(expression, _) => {
+                map.serialize_entry("expression", expression)?;
+            }
+            Expression(expression, _) => {
+                map.serialize_entry("expression", expression)?;
+            }
+            VarDeclaration(name, initializer, _) => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("initializer", initializer)?;
+            }
+            ConstDeclaration(name, initializer, _) => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("initializer", initializer)?;
+            }
+            Function(name, parameters, defaults, body, _) => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("parameters", parameters)?;
+                map.serialize_entry("defaults", defaults)?;
+                map.serialize_entry("body", body)?;
+            }
+            Block(statements, _) => {
+                map.serialize_entry("statements", statements)?;
+            }
+            If(condition, then_branch, else_branch, _) => {
+                map.serialize_entry("condition", condition)?;
+                map.serialize_entry("then_branch", then_branch)?;
+                map.serialize_entry("else_branch", else_branch)?;
+            }
+            While(condition, body, increment, label, _) => {
+                map.serialize_entry("condition", condition)?;
+                map.serialize_entry("body", body)?;
+                map.serialize_entry("increment", increment)?;
+                map.serialize_entry("label", label)?;
+            }
+            Return(keyword, value, _) => {
+                map.serialize_entry("keyword", keyword)?;
+                map.serialize_entry("value", value)?;
+            }
+            Break(keyword, label, _) | Continue(keyword, label, _) => {
+                map.serialize_entry("keyword", keyword)?;
+                map.serialize_entry("label", label)?;
+            }
+            Switch(scrutinee, cases, default, _) => {
+                #[derive(serde::Serialize)]
+                struct Case<'a> {
+                    value: &'a Expr,
+                    statements: &'a [Stmt],
+                }
+
+                let cases = cases
+                    .iter()
+                    .map(|(value, statements)| Case { value, statements })
+                    .collect::<Vec<_>>();
+
+                map.serialize_entry("scrutinee", scrutinee)?;
+                map.serialize_entry("cases", &cases)?;
+                map.serialize_entry("default", default)?;
+            }
+            ForIn(name, iterable, body, label, _) => {
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("iterable", iterable)?;
+                map.serialize_entry("body", body)?;
+                map.serialize_entry("label", label)?;
             }
-            Return(ref token, ref expr) => visitor.visit_return_stmt(token, expr),
         }
+
+        map.end()
     }
 }
 
-pub fn pstmt(expr: Expr) -> Stmt {
-    Stmt::Print(expr)
+pub fn pstmt(expr: Expr, span: Span) -> Stmt {
+    Stmt::Print(expr, span)
 }
 
-pub fn estmt(expr: Expr) -> Stmt {
-    Stmt::Expression(expr)
+pub fn estmt(expr: Expr, span: Span) -> Stmt {
+    Stmt::Expression(expr, span)
 }
 
-pub fn vdstmt(token: Token, initializer: Option<Expr>) -> Stmt {
-    Stmt::VarDeclaration(token, initializer)
+pub fn vdstmt(token: Token, initializer: Option<Expr>, span: Span) -> Stmt {
+    Stmt::VarDeclaration(token, initializer, span)
 }
 
-pub fn ifstmt(expr: Expr, stmt_then: Stmt, stmt_else: Option<Stmt>) -> Stmt {
-    Stmt::If(expr, Box::new(stmt_then), stmt_else.map(Box::new))
+pub fn ifstmt(expr: Expr, stmt_then: Stmt, stmt_else: Option<Stmt>, span: Span) -> Stmt {
+    Stmt::If(expr, Box::new(stmt_then), stmt_else.map(Box::new), span)
 }
 
-pub fn wstmt(expr: Expr, stmt: Stmt) -> Stmt {
-    Stmt::While(expr, Box::new(stmt))
+pub fn wstmt(
+    expr: Expr,
+    stmt: Stmt,
+    increment: Option<Expr>,
+    label: Option<Token>,
+    span: Span,
+) -> Stmt {
+    Stmt::While(expr, Box::new(stmt), increment, label, span)
 }
 
-pub fn fstmt(identifier: Token, parameters: Vec<Token>, body: Stmt) -> Stmt {
-    Stmt::Function(identifier, parameters, Box::new(body))
+pub fn fstmt(
+    identifier: Token,
+    parameters: Vec<Token>,
+    defaults: Vec<Option<Expr>>,
+    body: Stmt,
+    span: Span,
+) -> Stmt {
+    Stmt::Function(
+        identifier,
+        parameters,
+        Rc::from(defaults),
+        Rc::new(body),
+        span,
+    )
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_to_tagged_json() {
+        let statements = Parser::new(Scanner::new("print -x;").scan_tokens()).parse();
+
+        assert_eq!(
+            serde_json::to_value(&statements).unwrap(),
+            json!([{
+                "type": "Print",
+                "span": { "start": 0, "end": 9 },
+                "expression": {
+                    "type": "Unary",
+                    "span": { "start": 6, "end": 8 },
+                    "operator": { "token_type": "Minus", "lexeme": "-", "literal": null, "line": 1, "column": 7, "offset": 6 },
+                    "right": {
+                        "type": "Variable",
+                        "span": { "start": 7, "end": 8 },
+                        "name": { "token_type": "Identifier", "lexeme": "x", "literal": null, "line": 1, "column": 8, "offset": 7 }
+                    }
+                }
+            }])
+        );
+
+        let statements = Parser::new(Scanner::new("1.5;").scan_tokens()).parse();
+        assert_eq!(
+            serde_json::to_value(&statements).unwrap(),
+            json!([{
+                "type": "Expression",
+                "span": { "start": 0, "end": 4 },
+                "expression": {
+                    "type": "Literal",
+                    "span": { "start": 0, "end": 3 },
+                    "value": { "type": "Number", "value": 1.5 }
+                }
+            }])
+        );
+    }
 }
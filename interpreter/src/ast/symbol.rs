@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// An interned identifier. Every symbol for the same name handed out by an
+/// `Interner` shares one allocation, so symbols compare and hash by address
+/// instead of by their characters.
+///
+/// Symbols from different interners never compare equal, even for the same
+/// name, so a mismatch shows up as an undefined variable rather than as a
+/// lookup of some other variable.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn as_rc(&self) -> &Rc<str> {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8 as usize).hash(state);
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Hands out one `Symbol` per distinct name.
+#[derive(Default)]
+pub struct Interner {
+    names: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(name) = self.names.get(name) {
+            return Symbol(Rc::clone(name));
+        }
+
+        let name: Rc<str> = Rc::from(name);
+        self.names.insert(Rc::clone(&name));
+        Symbol(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning_shares_one_symbol_per_name() {
+        let mut interner = Interner::new();
+        let a = interner.intern("count");
+        let b = interner.intern(&String::from("count"));
+
+        assert_eq!(a, b);
+        assert!(Rc::ptr_eq(a.as_rc(), b.as_rc()));
+        assert_ne!(a, interner.intern("total"));
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn symbols_from_different_interners_differ() {
+        let a = Interner::new().intern("count");
+        let b = Interner::new().intern("count");
+
+        assert_ne!(a, b);
+        assert_eq!(a.as_str(), b.as_str());
+    }
+}
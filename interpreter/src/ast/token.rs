@@ -1,12 +1,26 @@
-use super::{tokentype::Literal, tokentype::TokenType};
+use super::{span::Span, symbol::Symbol, tokentype::Literal, tokentype::TokenType};
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    /// Shared so that cloning a token, which the parser does for every AST
+    /// node, doesn't copy the lexeme.
+    pub lexeme: Rc<str>,
     pub literal: Option<Literal>,
     pub line: usize,
+    /// 1-based position of the lexeme's first character in its line, or 0
+    /// for tokens that weren't scanned from source.
+    pub column: usize,
+    /// Position of the lexeme's first character in the source, counted in
+    /// chars.
+    pub offset: usize,
+    /// The interned name of an identifier, set when the `Scanner` was given
+    /// an interner. Without it, the interpreter interns the lexeme on lookup.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub symbol: Option<Symbol>,
 }
 
 impl Token {
@@ -18,9 +32,30 @@ impl Token {
     ) -> Token {
         Token {
             token_type,
-            lexeme: lexeme.to_string(),
+            lexeme: Rc::from(lexeme),
             literal,
             line,
+            column: 0,
+            offset: 0,
+            symbol: None,
+        }
+    }
+
+    /// The part of the source the lexeme was scanned from.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.lexeme.chars().count())
+    }
+
+    /// An identifier token whose lexeme is the interned `symbol`.
+    pub fn identifier(symbol: Symbol, line: usize) -> Token {
+        Token {
+            token_type: TokenType::Identifier,
+            lexeme: Rc::clone(symbol.as_rc()),
+            literal: None,
+            line,
+            column: 0,
+            offset: 0,
+            symbol: Some(symbol),
         }
     }
 }
@@ -1,10 +1,13 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -12,6 +15,11 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens.
     Bang,
@@ -22,6 +30,14 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    StarStar,
+    DotDot,
+    DotDotEqual,
+    LessLess,
+    GreaterGreater,
+    Arrow,
+    PipeGreater,
+    QuestionQuestion,
 
     // Literals.
     Identifier,
@@ -30,26 +46,39 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
     Class,
+    Const,
+    Continue,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
+    Switch,
+    Case,
+    Default,
     This,
     True,
     Var,
     While,
+    Xor,
+
+    // Only scanned with `Scanner::set_preserve_comments`.
+    Comment,
 
     Eof,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum Literal {
     Number(f64),
     String(String),
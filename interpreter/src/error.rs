@@ -1,11 +1,138 @@
-use crate::ast::{token::Token, tokentype::TokenType};
+use crate::ast::{span::Span, token::Token, tokentype::TokenType};
 use crate::interpreter::Object;
-use std::cell::Cell;
-use std::{error::Error, fmt::Display};
+use std::cell::{Cell, RefCell};
+use std::{any::Any, error::Error, fmt::Display};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "Error"),
+            Severity::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+/// A problem found in a program. `column` is 1-based, or 0 when the position
+/// within the line is unknown.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub severity: Severity,
+    /// The node the problem is in, when it is known.
+    pub span: Option<Span>,
+    /// The token the problem is at, like errors show it: " at 'foo'",
+    /// " at end", or empty when there is no token to name.
+    pub place: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {}{}: {}",
+            position(self.line, self.column),
+            self.severity,
+            self.place,
+            self.message
+        )
+    }
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic followed by the offending line of `source`, with
+    /// a caret under the diagnostic's column:
+    ///
+    /// ```text
+    /// [line 3, column 13] Error at ';': Expect ')' after expression.
+    /// 3 | print (1 + 2;
+    ///   |             ^
+    /// ```
+    ///
+    /// When the diagnostic has a span, the part of it on that line is
+    /// underlined instead:
+    ///
+    /// ```text
+    /// [line 1, column 9] Error at '-': operands must be numeric for operation
+    /// 1 | print 1 - nil;
+    ///   |       ^^^^^^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = self.to_string();
+
+        let text = match self.line.checked_sub(1) {
+            Some(index) => source.lines().nth(index),
+            None => None,
+        };
+
+        if let Some(text) = text {
+            let gutter = self.line.to_string();
+            rendered.push_str(&format!("\n{} | {}", gutter, text));
+
+            let length = text.chars().count();
+            let underline = match self.underline(source, length) {
+                Some(underline) => Some(underline),
+                None if self.column > 0 && self.column <= length + 1 => {
+                    Some(self.column - 1..self.column)
+                }
+                None => None,
+            };
+
+            if let Some(underline) = underline {
+                // Keep tabs so the carets line up with the text above them
+                let padding: String = text
+                    .chars()
+                    .take(underline.start)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let blank = " ".repeat(gutter.len());
+                let carets = "^".repeat(underline.len());
+                rendered.push_str(&format!("\n{} | {}{}", blank, padding, carets));
+            }
+        }
+
+        rendered
+    }
+
+    // The columns of the diagnostic's line, `length` chars long, covered by
+    // its span, counted from 0
+    fn underline(&self, source: &str, length: usize) -> Option<std::ops::Range<usize>> {
+        let span = self.span?;
+        let line_start = match self.line {
+            0 | 1 => 0,
+            line => {
+                source
+                    .chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '\n')
+                    .nth(line - 2)?
+                    .0
+                    + 1
+            }
+        };
+
+        let start = span.start.saturating_sub(line_start).min(length);
+        let end = span.end.saturating_sub(line_start).min(length);
+        (start < end).then_some(start..end)
+    }
+}
+
+/// Collects the diagnostics of a run. They are also printed to stderr as they
+/// are reported, unless printing is turned off with `set_print`.
 pub struct ErrorReporter {
     has_error: Cell<bool>,
     has_runtime_error: Cell<bool>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    print: bool,
 }
 
 impl Default for ErrorReporter {
@@ -19,9 +146,15 @@ impl ErrorReporter {
         ErrorReporter {
             has_error: Cell::new(false),
             has_runtime_error: Cell::new(false),
+            diagnostics: RefCell::new(Vec::new()),
+            print: true,
         }
     }
 
+    pub fn set_print(&mut self, print: bool) {
+        self.print = print;
+    }
+
     pub fn has_error(&self) -> bool {
         self.has_error.get()
     }
@@ -30,32 +163,133 @@ impl ErrorReporter {
         self.has_runtime_error.get()
     }
 
+    /// Every diagnostic reported so far, in the order they were reported.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
+    }
+
     pub fn reset(&self) {
         self.has_error.set(false);
+        self.has_runtime_error.set(false);
+    }
+
+    pub fn reset_runtime(&self) {
+        self.has_runtime_error.set(false);
     }
 
     pub fn error(&self, token: &Token, message: &str) {
-        if token.token_type == TokenType::Eof {
-            self.report(token.line, " at end", message);
-        } else {
-            self.report(token.line, &format!("at '{}'", token.lexeme), message);
-        }
+        self.report(token.line, token.column, &location(token), message);
     }
 
-    pub fn runtime_error(&self, token: &Token, message: &str) {
-        eprintln!(
-            "[line {}] Error {}: {}",
+    /// Like `error`, for an error in the node at `span`.
+    pub fn error_in(&self, token: &Token, span: Span, message: &str) {
+        self.report_in(
             token.line,
-            &format!("at '{}'", token.lexeme),
-            message
+            token.column,
+            &location(token),
+            message,
+            Some(span),
         );
+    }
+
+    /// Reports a problem that doesn't stop the program from running.
+    pub fn warning(&self, token: &Token, message: &str) {
+        self.warning_at(token, None, message);
+    }
+
+    /// Like `warning`, for a problem in the node at `span`.
+    pub fn warning_in(&self, token: &Token, span: Span, message: &str) {
+        self.warning_at(token, Some(span), message);
+    }
+
+    fn warning_at(&self, token: &Token, span: Option<Span>, message: &str) {
+        let diagnostic = Diagnostic {
+            line: token.line,
+            column: token.column,
+            message: message.to_string(),
+            severity: Severity::Warning,
+            span,
+            place: String::new(),
+        };
+        if self.print {
+            eprintln!("{}", diagnostic);
+        }
+        self.push(diagnostic);
+    }
+
+    pub fn runtime_error(&self, error: &RuntimeError) {
+        let diagnostic = Diagnostic {
+            line: error.token.line,
+            column: error.token.column,
+            message: error.message.clone(),
+            severity: Severity::Error,
+            span: error.span,
+            place: location(&error.token),
+        };
+        if self.print {
+            eprintln!("{}", diagnostic);
+        }
+        self.push(diagnostic);
         self.has_runtime_error.set(true);
     }
 
-    pub fn report(&self, line: usize, place: &str, message: &str) {
-        eprintln!("[line {}] Error {}: {}", line, place, message);
+    /// Reports an error at `line` and `column`. `place` is empty or, like the
+    /// errors' `Display`, starts with a space: " at 'foo'" or " at end".
+    pub fn report(&self, line: usize, column: usize, place: &str, message: &str) {
+        self.report_in(line, column, place, message, None);
+    }
+
+    fn report_in(
+        &self,
+        line: usize,
+        column: usize,
+        place: &str,
+        message: &str,
+        span: Option<Span>,
+    ) {
+        let diagnostic = Diagnostic {
+            line,
+            column,
+            message: message.to_string(),
+            severity: Severity::Error,
+            span,
+            place: place.to_string(),
+        };
+        if self.print {
+            eprintln!("{}", diagnostic);
+        }
+        self.push(diagnostic);
         self.has_error.set(true);
     }
+
+    fn push(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+}
+
+fn location(token: &Token) -> String {
+    match token.token_type {
+        TokenType::Eof => " at end".to_string(),
+        // Synthetic tokens, like those of native function errors, have no text to show
+        _ if token.lexeme.is_empty() => String::new(),
+        _ => format!(" at '{}'", token.lexeme),
+    }
+}
+
+fn position(line: usize, column: usize) -> String {
+    if column == 0 {
+        format!("line {}", line)
+    } else {
+        format!("line {}, column {}", line, column)
+    }
+}
+
+fn format_error(line: usize, column: usize, place: &str, message: &str) -> String {
+    format!("[{}] Error{}: {}", position(line, column), place, message)
+}
+
+fn format_token_error(token: &Token, message: &str) -> String {
+    format_error(token.line, token.column, &location(token), message)
 }
 
 #[derive(Debug)]
@@ -65,7 +299,7 @@ pub struct ParseError {
 }
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", format_token_error(&self.token, &self.message))
     }
 }
 impl Error for ParseError {}
@@ -74,11 +308,222 @@ impl Error for ParseError {}
 pub struct RuntimeError {
     pub token: Token,
     pub message: String,
-    pub value: Option<Object>,
+    /// The node that was being run, attached by the interpreter.
+    pub span: Option<Span>,
+}
+impl RuntimeError {
+    /// Creates an error without a source location, for use by native
+    /// functions. The interpreter attaches the call site when it is raised.
+    pub fn native(message: &str) -> Self {
+        Self {
+            token: Token::new(TokenType::Identifier, "", None, 0),
+            message: message.to_string(),
+            span: None,
+        }
+    }
+
+    /// Turns the payload of a caught panic into an error, so that a bug in
+    /// the interpreter fails the script instead of the host.
+    pub(crate) fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message,
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => message.as_str(),
+                None => "unknown panic",
+            },
+        };
+        Self::native(&format!("Internal interpreter error: {}.", message))
+    }
+
+    /// Locates an error that has no location yet, like a `native` one, at `token`.
+    pub(crate) fn at(mut self, token: &Token) -> Self {
+        if self.token.lexeme.is_empty() {
+            self.token = token.clone();
+        }
+        self
+    }
+
+    /// Places an error that has no span yet in the node at `span`.
+    pub(crate) fn within(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
 }
+
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "{}", format_token_error(&self.token, &self.message))
     }
 }
 impl Error for RuntimeError {}
+
+/// Why executing a statement stopped before reaching its end: a `return`
+/// unwinding to the function call, a `break` or `continue` unwinding to the
+/// loop it names, or the innermost one without a label, or a runtime error.
+#[derive(Debug)]
+pub enum Signal {
+    Return(Object),
+    Break(Option<Token>),
+    Continue(Option<Token>),
+    RuntimeError(RuntimeError),
+}
+
+impl Signal {
+    /// Whether this is a `break` or `continue` for the loop labeled `label`.
+    pub fn targets(&self, label: Option<&Token>) -> bool {
+        match self {
+            Signal::Break(None) | Signal::Continue(None) => true,
+            Signal::Break(Some(target)) | Signal::Continue(Some(target)) => {
+                label.is_some_and(|label| label.lexeme == target.lexeme)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<RuntimeError> for Signal {
+    fn from(error: RuntimeError) -> Self {
+        Signal::RuntimeError(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_points_at_the_column() {
+        let diagnostic = Diagnostic {
+            line: 3,
+            column: 13,
+            message: "Expect ')' after expression.".to_string(),
+            severity: Severity::Error,
+            span: None,
+            place: " at ';'".to_string(),
+        };
+        let source = "var a = 1;\n\nprint (1 + 2;\n";
+
+        assert_eq!(
+            diagnostic.render(source),
+            "[line 3, column 13] Error at ';': Expect ')' after expression.\n\
+             3 | print (1 + 2;\n  \
+               |             ^"
+        );
+    }
+
+    #[test]
+    fn render_underlines_the_span_on_the_line() {
+        let source = "var a = 1;\nprint a +\n  nil;\n";
+        let diagnostic = Diagnostic {
+            line: 2,
+            column: 9,
+            message: "Operands must be numbers.".to_string(),
+            severity: Severity::Error,
+            span: Some(Span::new(17, 25)),
+            place: String::new(),
+        };
+
+        assert_eq!(
+            diagnostic.render(source),
+            "[line 2, column 9] Error: Operands must be numbers.\n\
+             2 | print a +\n  \
+               |       ^^^"
+        );
+    }
+
+    #[test]
+    fn render_without_a_position_shows_only_the_message() {
+        let diagnostic = Diagnostic {
+            line: 0,
+            column: 0,
+            message: "Stack overflow.".to_string(),
+            severity: Severity::Error,
+            span: None,
+            place: String::new(),
+        };
+
+        assert_eq!(
+            diagnostic.render("print 1;"),
+            "[line 0] Error: Stack overflow."
+        );
+    }
+
+    #[test]
+    fn reported_errors_keep_their_place() {
+        let mut reporter = ErrorReporter::new();
+        reporter.set_print(false);
+        let error = RuntimeError {
+            token: Token::new(TokenType::Minus, "-", None, 3),
+            message: "Operand must be a number.".to_string(),
+            span: None,
+        };
+        reporter.error(
+            &Token::new(TokenType::Semicolon, ";", None, 1),
+            "Expected expression.",
+        );
+        reporter.error(&Token::new(TokenType::Eof, "", None, 2), "Expect ';'.");
+        reporter.runtime_error(&error);
+
+        let diagnostics = reporter.diagnostics();
+        assert_eq!(
+            diagnostics[0].render("var a = ;"),
+            "[line 1] Error at ';': Expected expression.\n1 | var a = ;"
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "[line 2] Error at end: Expect ';'."
+        );
+        assert_eq!(diagnostics[2].to_string(), error.to_string());
+    }
+
+    #[test]
+    fn errors_without_a_lexeme_omit_it() {
+        let error = RuntimeError::native("Argument must be a number.");
+        assert_eq!(
+            error.to_string(),
+            "[line 0] Error: Argument must be a number."
+        );
+
+        let mut error = RuntimeError::native("Argument must be a number.");
+        error.token.line = 2;
+        error.token.column = 5;
+        assert_eq!(
+            error.to_string(),
+            "[line 2, column 5] Error: Argument must be a number."
+        );
+    }
+
+    #[test]
+    fn errors_display_their_location() {
+        let error = RuntimeError {
+            token: Token::new(TokenType::Minus, "-", None, 3),
+            message: "Operand must be a number.".to_string(),
+            span: None,
+        };
+        assert_eq!(
+            error.to_string(),
+            "[line 3] Error at '-': Operand must be a number."
+        );
+
+        let error = ParseError {
+            token: Token::new(TokenType::Eof, "", None, 7),
+            message: "Expect ';' after value.".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "[line 7] Error at end: Expect ';' after value."
+        );
+
+        let mut token = Token::new(TokenType::Slash, "/", None, 1);
+        token.column = 12;
+        let error = RuntimeError {
+            token,
+            message: "Operands must be numbers.".to_string(),
+            span: None,
+        };
+        assert_eq!(
+            error.to_string(),
+            "[line 1, column 12] Error at '/': Operands must be numbers."
+        );
+    }
+}
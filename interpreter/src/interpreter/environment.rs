@@ -1,4 +1,5 @@
 use super::object::Object;
+use crate::ast::symbol::Symbol;
 use crate::ast::token::Token;
 use crate::error::RuntimeError;
 use std::cell::RefCell;
@@ -6,9 +7,20 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::rc::Rc;
 
+/// Globals live in `values` and are looked up by their interned name. Local scopes store
+/// their variables in `slots`, in declaration order, so they can be read by
+/// the (depth, slot) pair the `Resolver` computes for them.
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, Option<Object>>,
+    values: HashMap<Symbol, Global>,
+    slots: Vec<Object>,
+    frozen: bool,
+}
+
+#[derive(Debug)]
+struct Global {
+    value: Object,
+    constant: bool,
 }
 
 impl Environment {
@@ -16,71 +28,179 @@ impl Environment {
         Self {
             enclosing,
             values: HashMap::new(),
+            slots: Vec::new(),
+            frozen: false,
+        }
+    }
+
+    /// Makes the globals read-only: `define`, `define_constant` and `assign`
+    /// fail from now on. Local scopes are not affected.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Defines a global, replacing any previous definition of the same name.
+    /// Fails when the globals are frozen or the name is a constant, with an
+    /// error that has no location.
+    pub fn define(&mut self, symbol: Symbol, value: Object) -> Result<(), RuntimeError> {
+        self.insert(symbol, value, false)
+    }
+
+    /// Defines a global that `assign` refuses to change, and that can't be
+    /// defined again.
+    pub fn define_constant(&mut self, symbol: Symbol, value: Object) -> Result<(), RuntimeError> {
+        self.insert(symbol, value, true)
+    }
+
+    fn insert(
+        &mut self,
+        symbol: Symbol,
+        value: Object,
+        constant: bool,
+    ) -> Result<(), RuntimeError> {
+        if self.frozen {
+            return Err(RuntimeError::native(&format!(
+                "Cannot define '{}', globals are frozen.",
+                symbol
+            )));
         }
+
+        if self
+            .values
+            .get(&symbol)
+            .is_some_and(|global| global.constant)
+        {
+            return Err(RuntimeError::native(&format!(
+                "Cannot redeclare constant '{}'.",
+                symbol
+            )));
+        }
+
+        self.values.insert(symbol, Global { value, constant });
+        Ok(())
     }
 
-    pub fn define(&mut self, identifier: &Token, value: Option<Object>) {
-        self.values.insert(identifier.lexeme.clone(), value);
+    /// Defines the next local in this scope, taking the slot after the last one.
+    pub fn define_slot(&mut self, value: Object) {
+        self.slots.push(value);
     }
 
     pub fn assign(
         &mut self,
+        symbol: &Symbol,
         identifier: &Token,
-        value: Option<Object>,
+        value: Object,
     ) -> Result<Object, RuntimeError> {
-        match self.values.get(&identifier.lexeme) {
-            Some(_old_value) => {
-                self.values.insert(identifier.lexeme.clone(), value.clone());
-                Ok(value.unwrap())
+        match self.values.get_mut(symbol) {
+            Some(_) if self.frozen => Err(RuntimeError {
+                token: identifier.clone(),
+                message: format!(
+                    "Cannot assign to '{}', globals are frozen.",
+                    identifier.lexeme
+                ),
+                span: None,
+            }),
+            Some(global) if global.constant => Err(RuntimeError {
+                token: identifier.clone(),
+                message: format!("Cannot assign to constant '{}'.", identifier.lexeme),
+                span: None,
+            }),
+            Some(global) => {
+                global.value = value.clone();
+                Ok(value)
             }
-            None => {
-                if let Some(ref env) = self.enclosing {
-                    return env.borrow_mut().assign(identifier, value);
-                }
+            None => Err(RuntimeError {
+                token: identifier.clone(),
+                message: format!(
+                    "Cannot assign to undeclared variable '{}'.",
+                    identifier.lexeme
+                ),
+                span: None,
+            }),
+        }
+    }
 
-                self.undefined(identifier.clone())
-            }
+    pub fn get(&self, symbol: &Symbol, identifier: &Token) -> Result<Object, RuntimeError> {
+        match self.values.get(symbol) {
+            Some(global) => Ok(global.value.clone()),
+            None => self.undefined(identifier.clone()),
         }
     }
 
-    pub fn get(&self, identifier: &Token) -> Result<Object, RuntimeError> {
-        match self.values.get(&identifier.lexeme) {
-            Some(value) => {
-                if let Some(value) = value {
-                    return Ok(value.clone());
-                }
-                self.uninitialized(identifier.clone())
-            }
-            None => {
-                if let Some(ref env) = self.enclosing {
-                    return env.borrow().get(identifier);
-                }
-                self.undefined(identifier.clone())
-            }
+    pub fn get_at(&self, depth: usize, slot: usize) -> Object {
+        if depth > 0 {
+            return self.ancestor().borrow().get_at(depth - 1, slot);
         }
+
+        self.slots[slot].clone()
     }
 
-    fn uninitialized(&self, token: Token) -> Result<Object, RuntimeError> {
-        Err(RuntimeError {
-            value: None,
-            message: format!("Uninitialized variable '{}'.", token.lexeme),
-            token,
-        })
+    pub fn set_at(&mut self, depth: usize, slot: usize, value: Object) {
+        if depth > 0 {
+            return self.ancestor().borrow_mut().set_at(depth - 1, slot, value);
+        }
+
+        self.slots[slot] = value;
+    }
+
+    fn ancestor(&self) -> &Rc<RefCell<Environment>> {
+        self.enclosing
+            .as_ref()
+            .expect("[UNREACHABLE] Resolved depth is deeper than the scope chain.")
     }
 
     fn undefined(&self, token: Token) -> Result<Object, RuntimeError> {
         Err(RuntimeError {
-            value: None,
             message: format!("Undefined variable '{}'.", token.lexeme),
             token,
+            span: None,
         })
     }
 }
 
 impl Debug for Environment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let elements = self.values.iter().collect::<Vec<_>>();
+        // By name, so that dumps don't change from run to run
+        let mut elements = self.values.iter().collect::<Vec<_>>();
+        elements.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+        write!(
+            f,
+            "Current: {:?} {:?} - Parent: {:?}",
+            elements, self.slots, self.enclosing
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::symbol::Interner;
+
+    #[test]
+    fn debug_lists_globals_by_name() {
+        let mut interner = Interner::new();
+        let mut env = Environment::new(None);
+        for name in ["zeta", "alpha", "mu", "beta"] {
+            env.define(interner.intern(name), Object::Nil).unwrap();
+        }
+        env.define_constant(interner.intern("pi"), Object::Number(3.0))
+            .unwrap();
 
-        write!(f, "Current: {:?} - Parent: {:?}", elements, self.enclosing)
+        let global = |value: &str, constant: bool| {
+            format!("Global {{ value: {}, constant: {} }}", value, constant)
+        };
+        assert_eq!(
+            format!("{:?}", env),
+            format!(
+                "Current: [(alpha, {none}), (beta, {none}), (mu, {none}), (pi, {pi}), (zeta, {none})] [] - Parent: None",
+                none = global("Nil", false),
+                pi = global("Number(3.0)", true)
+            )
+        );
     }
 }
@@ -4,24 +4,98 @@ use std::{
 };
 
 use crate::{
-    ast::{stmt::Stmt, token::Token},
-    error::RuntimeError,
+    ast::{expr::Expr, stmt::Stmt, token::Token, tokentype::TokenType},
+    error::{RuntimeError, Signal},
     interpreter::environment::Environment,
 };
 
 use super::{object::Object, Interpreter, Scope};
 
-#[derive(Debug, Clone)]
+/// Host function callable from Lox. It receives the running interpreter and
+/// may fail with a `RuntimeError`.
+pub type NativeFn = Rc<dyn Fn(&mut Interpreter, &[Object]) -> Result<Object, RuntimeError>>;
+
+/// How many arguments a function takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    /// Variadic, with a minimum number of arguments.
+    AtLeast(usize),
+    /// Optional trailing arguments, between a minimum and a maximum.
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(arity) => count == arity,
+            Arity::AtLeast(arity) => count >= arity,
+            Arity::Between(min, max) => min <= count && count <= max,
+        }
+    }
+
+    /// The arity left once the first `bound` arguments are given, or `None`
+    /// when no call takes that many.
+    pub fn after(&self, bound: usize) -> Option<Arity> {
+        match *self {
+            Arity::Exact(arity) if arity >= bound => Some(Arity::Exact(arity - bound)),
+            Arity::AtLeast(arity) => Some(Arity::AtLeast(arity.saturating_sub(bound))),
+            Arity::Between(min, max) if max >= bound => {
+                let min = min.saturating_sub(bound);
+                match max - bound {
+                    max if max == min => Some(Arity::Exact(max)),
+                    max => Some(Arity::Between(min, max)),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The arity of a user function, from the default values of its
+    /// parameters: those after the first default one are optional.
+    pub(crate) fn of_parameters(defaults: &[Option<Expr>]) -> Arity {
+        let required = defaults.iter().take_while(|d| d.is_none()).count();
+        if required == defaults.len() {
+            Arity::Exact(required)
+        } else {
+            Arity::Between(required, defaults.len())
+        }
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(arity: usize) -> Self {
+        Arity::Exact(arity)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (prefix, arity) = match *self {
+            Arity::Exact(arity) => (String::new(), arity),
+            Arity::AtLeast(arity) => ("at least ".to_string(), arity),
+            Arity::Between(min, max) => (format!("{} to ", min), max),
+        };
+        let noun = if arity == 1 { "argument" } else { "arguments" };
+        write!(f, "{}{} {}", prefix, arity, noun)
+    }
+}
+
+#[derive(Clone)]
 pub enum Function {
     Native {
         identifier: String,
-        arity: usize,
-        body: fn(&Vec<Object>) -> Object,
+        arity: Arity,
+        body: NativeFn,
     },
     User {
+        /// The name, or the `fun` keyword of an anonymous function.
         identifier: Token,
         parameters: Vec<Token>,
-        body: Box<Stmt>,
+        /// Default value of each parameter, evaluated in `closure` on each
+        /// call that omits it.
+        defaults: Rc<[Option<Expr>]>,
+        body: Rc<Stmt>,
         closure: Scope,
     },
 }
@@ -30,45 +104,234 @@ impl Function {
     pub fn call(
         &self,
         _interpreter: &mut Interpreter,
-        arguments: &Vec<Object>,
+        arguments: &[Object],
     ) -> Result<Object, RuntimeError> {
         use Function::*;
 
         match self {
-            Native { body, .. } => Ok(body(arguments)),
+            Native { body, .. } => body(_interpreter, arguments),
             User {
                 body,
                 identifier,
                 parameters,
                 closure,
+                ..
             } => match **body {
-                Stmt::Block(ref stmts) => {
+                Stmt::Block(ref stmts, _) => {
                     let mut env = Environment::new(Some(Rc::clone(closure)));
 
-                    for (idx, token) in parameters.iter().enumerate() {
-                        env.define(token, arguments.get(idx).cloned());
+                    for idx in 0..parameters.len() {
+                        let argument = match arguments.get(idx) {
+                            Some(argument) => argument.clone(),
+                            None => self.default(_interpreter, idx).unwrap_or(Ok(Object::Nil))?,
+                        };
+                        env.define_slot(argument);
                     }
 
-                    if let Err(err) = _interpreter.execute_block(stmts, env) {
-                        return Ok(err.value.unwrap());
-                    }
+                    _interpreter.enter_call()?;
+                    let result = _interpreter.execute_block(stmts, env);
+                    _interpreter.exit_call();
 
-                    Ok(Object::Nil)
+                    match result {
+                        Ok(()) => Ok(Object::Nil),
+                        Err(Signal::Return(value)) => Ok(value),
+                        Err(Signal::RuntimeError(e)) => Err(e),
+                        Err(Signal::Break(_) | Signal::Continue(_)) => Err(RuntimeError::native(
+                            "[UNREACHABLE] The resolver rejects jumps out of functions.",
+                        )),
+                    }
                 }
                 _ => Err(RuntimeError {
-                    value: None,
                     token: identifier.clone(),
                     message: "[UNREACHABLE] Function statements must be a block.".to_string(),
+                    span: None,
                 }),
             },
         }
     }
 
-    pub fn arity(&self) -> usize {
+    /// Puts named arguments in the position of the parameter they name, after
+    /// the positional ones.
+    pub fn bind(
+        &self,
+        interpreter: &mut Interpreter,
+        positional: Vec<Object>,
+        named: Vec<(&Token, Object)>,
+        paren: &Token,
+    ) -> Result<Vec<Object>, RuntimeError> {
+        let parameters = match self {
+            Function::Native { .. } => {
+                return Err(RuntimeError {
+                    token: paren.clone(),
+                    message: format!("{} doesn't take named arguments.", self),
+                    span: None,
+                })
+            }
+            Function::User { parameters, .. } => parameters,
+        };
+
+        let positional_count = positional.len();
+        let mut bound: Vec<Option<Object>> = positional.into_iter().map(Some).collect();
+        if bound.len() < parameters.len() {
+            bound.resize(parameters.len(), None);
+        }
+
+        for (name, value) in named {
+            let error = |message: String| {
+                Err(RuntimeError {
+                    token: name.clone(),
+                    message,
+                    span: None,
+                })
+            };
+
+            let index = match parameters.iter().position(|p| p.lexeme == name.lexeme) {
+                Some(index) => index,
+                None => {
+                    return error(format!(
+                        "{} has no parameter named '{}'.",
+                        self, name.lexeme
+                    ))
+                }
+            };
+
+            if bound[index].is_some() {
+                return error(if index < positional_count {
+                    format!("Argument '{}' was already passed by position.", name.lexeme)
+                } else {
+                    format!("Duplicate argument '{}'.", name.lexeme)
+                });
+            }
+
+            bound[index] = Some(value);
+        }
+
+        let mut arguments = Vec::with_capacity(bound.len());
+        for (index, argument) in bound.into_iter().enumerate() {
+            arguments.push(match argument {
+                Some(argument) => argument,
+                None => match self.default(interpreter, index) {
+                    Some(default) => default?,
+                    None => {
+                        return Err(RuntimeError {
+                            token: paren.clone(),
+                            message: format!(
+                                "{} is missing argument '{}'.",
+                                self, parameters[index].lexeme
+                            ),
+                            span: None,
+                        })
+                    }
+                },
+            });
+        }
+
+        Ok(arguments)
+    }
+
+    /// Evaluates the default value of the parameter at `index`, if it has one.
+    fn default(
+        &self,
+        interpreter: &mut Interpreter,
+        index: usize,
+    ) -> Option<Result<Object, RuntimeError>> {
+        match self {
+            Function::User {
+                defaults, closure, ..
+            } => match defaults.get(index) {
+                Some(Some(default)) => Some(interpreter.evaluate_in(default, closure)),
+                _ => None,
+            },
+            Function::Native { .. } => None,
+        }
+    }
+
+    /// The name the function was declared or defined with.
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Native { identifier, .. } => identifier,
+            // Anonymous functions have the `fun` keyword instead
+            Function::User { identifier, .. } if identifier.token_type == TokenType::Fun => {
+                "anonymous"
+            }
+            Function::User { identifier, .. } => &identifier.lexeme,
+        }
+    }
+
+    /// This function with `bound` as its first arguments, or `None` when it
+    /// doesn't take that many.
+    pub fn partial(&self, bound: Vec<Object>) -> Option<Function> {
+        let arity = self.arity().after(bound.len())?;
+        let function = self.clone();
+
+        Some(Function::Native {
+            identifier: self.name().to_string(),
+            arity,
+            body: Rc::new(move |interpreter, args| {
+                let arguments = [&bound[..], args].concat();
+                function.call(interpreter, &arguments)
+            }),
+        })
+    }
+
+    pub fn arity(&self) -> Arity {
         use Function::*;
         match self {
             Native { arity, .. } => *arity,
-            User { parameters, .. } => parameters.len(),
+            User { defaults, .. } => Arity::of_parameters(defaults),
+        }
+    }
+}
+
+impl PartialEq for Function {
+    /// Functions are equal when they are the same function: the same native,
+    /// or the same declaration closed over the same scope.
+    fn eq(&self, other: &Self) -> bool {
+        use Function::*;
+        match (self, other) {
+            (Native { body: l, .. }, Native { body: r, .. }) => Rc::ptr_eq(l, r),
+            (
+                User {
+                    body: l,
+                    closure: l_closure,
+                    ..
+                },
+                User {
+                    body: r,
+                    closure: r_closure,
+                    ..
+                },
+            ) => Rc::ptr_eq(l, r) && Rc::ptr_eq(l_closure, r_closure),
+            _ => false,
+        }
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Function::*;
+        match self {
+            Native {
+                identifier, arity, ..
+            } => f
+                .debug_struct("Native")
+                .field("identifier", identifier)
+                .field("arity", arity)
+                .finish_non_exhaustive(),
+            User {
+                identifier,
+                parameters,
+                defaults,
+                body,
+                closure,
+            } => f
+                .debug_struct("User")
+                .field("identifier", identifier)
+                .field("parameters", parameters)
+                .field("defaults", defaults)
+                .field("body", body)
+                .field("closure", closure)
+                .finish(),
         }
     }
 }
@@ -78,7 +341,72 @@ impl Display for Function {
         use Function::*;
         match self {
             Native { identifier, .. } => write!(f, "<native fn {}>", identifier),
-            User { identifier, .. } => write!(f, "<fn {}>", identifier.lexeme),
+            User { .. } => write!(f, "<fn {}>", self.name()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Arity;
+    use crate::eval;
+    use crate::interpreter::interpreter::tests::run_with;
+    use crate::interpreter::Object;
+
+    #[test]
+    fn call_propagates_errors_raised_in_the_body() {
+        let diagnostics = eval("fun f() { return missing; } f();").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Undefined variable 'missing'.");
+        assert_eq!(diagnostics.len(), 1);
+
+        // Through enclosing calls too, without being taken for a return value
+        let source = "
+            fun inner() { -\"a\"; return 1; }
+            fun outer() { var x = inner(); return 2; }
+            outer();
+        ";
+        let diagnostics = eval(source).unwrap_err();
+        assert_eq!(diagnostics[0].line, 2);
+
+        assert_eq!(eval("fun f() {} f();"), Ok(Object::Nil));
+    }
+
+    #[test]
+    fn arity_errors_name_the_callee() {
+        let diagnostics = eval("sqrt();").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "<native fn sqrt> expected 1 argument but got 0."
+        );
+
+        // Through a variable, so that only the call can check it
+        let diagnostics = eval("fun add(a, b) {} var f = add; f(1);").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "<fn add> expected 2 arguments but got 1."
+        );
+    }
+
+    #[test]
+    fn variadic_natives_take_extra_arguments() {
+        let source = "print sum(1, 2, 3); print sum(4); print sum();";
+        let output = run_with(source, |interpreter| {
+            interpreter.define_native("sum", Arity::AtLeast(1), |_, args| {
+                let mut total = 0.0;
+                for arg in args {
+                    total += f64::try_from(arg.clone())?;
+                }
+                Ok(Object::Number(total))
+            })
+        });
+
+        assert_eq!(output, "6\n4\n");
+        assert_eq!(Arity::AtLeast(1).to_string(), "at least 1 argument");
+        assert_eq!(Arity::Between(1, 2).to_string(), "1 to 2 arguments");
+        assert_eq!(Arity::Exact(2).after(1), Some(Arity::Exact(1)));
+        assert_eq!(Arity::Exact(2).after(3), None);
+        assert_eq!(Arity::AtLeast(1).after(2), Some(Arity::AtLeast(0)));
+        assert_eq!(Arity::Between(1, 3).after(1), Some(Arity::Between(0, 2)));
+        assert_eq!(Arity::Between(1, 2).after(2), Some(Arity::Exact(0)));
+    }
+}
@@ -1,23 +1,52 @@
 use super::environment::Environment;
-use super::function::Function;
-use super::object::Object;
+use super::function::{Arity, Function};
+use super::natives;
+use super::object::{safe_integer, NumberFormat, Object, Truthiness, MAX_SAFE_INTEGER};
+use super::profile::Profile;
+use crate::ast::symbol::{Interner, Symbol};
 use crate::ast::token::Token;
 use crate::ast::tokentype::{Literal, TokenType};
 use crate::error::ErrorReporter;
 use crate::{
     ast::expr::{Expr, Visitor as ExprVisitor},
     ast::stmt::{Stmt, Visitor as StmtVisitor},
-    error::RuntimeError,
+    error::{RuntimeError, Signal},
 };
 use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub type Scope = Rc<RefCell<Environment>>;
 
+const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Interpreter<'a> {
     pub globals: Scope,
     env: Scope,
+    out: Box<dyn Write + 'a>,
+    input: Option<Box<dyn BufRead + 'a>>,
+    filesystem: bool,
+    process_exit: bool,
+    args: Vec<String>,
+    random_state: u64,
+    truthiness: Truthiness,
+    number_format: NumberFormat,
+    call_depth: usize,
+    max_call_depth: usize,
+    // Keyed by the address of the variable's token in the resolved AST
+    locals: HashMap<*const Token, (usize, usize)>,
+    interner: Rc<RefCell<Interner>>,
+    profile: Option<Profile>,
+    // Statements and expressions left to run, when limited
+    budget: Option<usize>,
+    cancelled: Arc<AtomicBool>,
     _reporter: Option<&'a ErrorReporter>,
 }
 
@@ -29,55 +58,371 @@ impl Default for Interpreter<'_> {
 
 impl<'a> Interpreter<'a> {
     pub fn new() -> Self {
-        let globals = Rc::new(RefCell::new(Environment::new(None)));
+        Self::with_writer(io::stdout())
+    }
 
-        globals.borrow_mut().define(
-            &Token {
-                line: 0,
-                token_type: TokenType::Identifier,
-                lexeme: "clock".to_string(),
-                literal: None,
-            },
-            Some(Object::Callable(Function::Native {
-                identifier: "clock".to_string(),
-                arity: 0,
-                body: |_| {
-                    let v = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                    Object::Number(v.as_secs_f64())
-                },
-            })),
-        );
+    /// Creates an interpreter that writes `print` output to `out` instead of stdout.
+    pub fn with_writer(out: impl Write + 'a) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new(None)));
 
-        Self {
+        let mut interpreter = Self {
             globals: Rc::clone(&globals),
             env: Rc::clone(&globals),
+            out: Box::new(out),
+            input: None,
+            filesystem: true,
+            process_exit: false,
+            args: Vec::new(),
+            // Hashers are randomly keyed, without needing a clock
+            random_state: RandomState::new().hash_one(0),
+            truthiness: Truthiness::Lox,
+            number_format: NumberFormat::Shortest,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            locals: HashMap::new(),
+            interner: Rc::new(RefCell::new(Interner::new())),
+            profile: None,
+            budget: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
             _reporter: None,
+        };
+
+        interpreter.define_native("clock", 0, |_, _| {
+            let v = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            Ok(Object::Number(v.as_secs_f64()))
+        });
+        natives::register_math(&mut interpreter);
+        natives::register_string(&mut interpreter);
+        natives::register_collections(&mut interpreter);
+        natives::register_functional(&mut interpreter);
+        natives::register_conversion(&mut interpreter);
+        #[cfg(feature = "serde")]
+        natives::register_json(&mut interpreter);
+        natives::register_io(&mut interpreter);
+        natives::register_system(&mut interpreter);
+        natives::register_random(&mut interpreter);
+        natives::register_time(&mut interpreter);
+        natives::register_assert(&mut interpreter);
+
+        interpreter
+    }
+
+    /// Creates an interpreter like `with_writer` that also measures where
+    /// time goes, see `profile`. Other interpreters don't pay for it.
+    pub fn with_profiling(out: impl Write + 'a) -> Self {
+        let mut interpreter = Self::with_writer(out);
+        interpreter.profile = Some(Profile::default());
+        interpreter
+    }
+
+    /// Creates an interpreter that runs at most `budget` statements and
+    /// expressions, see `set_budget`.
+    pub fn with_budget(budget: usize) -> Self {
+        let mut interpreter = Self::new();
+        interpreter.set_budget(Some(budget));
+        interpreter
+    }
+
+    /// Limits how many more statements and expressions run, counting each
+    /// nested one, before an "Execution budget exceeded." runtime error stops
+    /// the program, so untrusted scripts can't run forever. `None` removes
+    /// the limit.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    fn spend(&mut self) -> Result<(), RuntimeError> {
+        match self.budget {
+            Some(0) => Err(RuntimeError::native("Execution budget exceeded.")),
+            Some(ref mut budget) => {
+                *budget -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// A flag that interrupts the running program when set, from any thread,
+    /// with an "Interrupted." runtime error. It is checked on every loop
+    /// iteration and call of a Lox function, so hosts can enforce timeouts.
+    /// It stays set until the host clears it.
+    pub fn cancellation_token(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancelled)
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<(), RuntimeError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(RuntimeError::native("Interrupted."));
         }
+        Ok(())
     }
 
-    pub fn interpret(&mut self, stmts: Vec<Stmt>) {
+    /// What has been measured so far, when created with `with_profiling`.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Registers a host function as a global. The function may capture state
+    /// and fail with a `RuntimeError`, which is reported at the call site.
+    pub fn define_native<F>(&mut self, name: &str, arity: impl Into<Arity>, body: F)
+    where
+        F: Fn(&mut Interpreter, &[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        natives::define(self, name, arity, body);
+    }
+
+    /// Defines `name` as a global holding `value`.
+    ///
+    /// # Panics
+    ///
+    /// If the globals have been frozen with `freeze_globals`, or a script has
+    /// declared `name` as a constant.
+    pub fn define_global(&mut self, name: &str, value: Object) {
+        let symbol = self.interner.borrow_mut().intern(name);
+        if let Err(e) = self.globals.borrow_mut().define(symbol, value) {
+            panic!("{}", e.message);
+        }
+    }
+
+    /// Makes the current globals, such as natives and values given with
+    /// `define_global`, read-only. Scripts run afterwards get a runtime error
+    /// when they define or assign a global, but can still use locals.
+    pub fn freeze_globals(&mut self) {
+        self.globals.borrow_mut().freeze();
+    }
+
+    /// The interner that names globals. Give it to the `Scanner` with
+    /// `set_interner` so identifiers are interned once, while scanning.
+    pub fn interner(&self) -> Rc<RefCell<Interner>> {
+        Rc::clone(&self.interner)
+    }
+
+    fn symbol(&self, identifier: &Token) -> Symbol {
+        match identifier.symbol {
+            Some(ref symbol) => symbol.clone(),
+            None => self.interner.borrow_mut().intern(&identifier.lexeme),
+        }
+    }
+
+    fn define(&mut self, identifier: &Token, value: Object) -> Result<(), RuntimeError> {
+        if Rc::ptr_eq(&self.env, &self.globals) {
+            let symbol = self.symbol(identifier);
+            let result = self.globals.borrow_mut().define(symbol, value);
+            result.map_err(|e| e.at(identifier))
+        } else {
+            self.env.borrow_mut().define_slot(value);
+            Ok(())
+        }
+    }
+
+    /// Runs `stmts`, which must have been resolved with the `Resolver` first,
+    /// stopping at the first runtime error. Returns the value of the last
+    /// statement when it is an expression statement.
+    ///
+    /// A panic while running, which is a bug in the interpreter or in a
+    /// native, is returned as an "Internal interpreter error." instead of
+    /// unwinding into the host.
+    pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<Option<Object>, RuntimeError> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.interpret_stmts(&stmts))) {
+            Ok(result) => result,
+            Err(payload) => {
+                // The panic skipped the bookkeeping of the blocks and calls
+                // it unwound
+                self.env = Rc::clone(&self.globals);
+                self.call_depth = 0;
+                Err(RuntimeError::from_panic(payload))
+            }
+        }
+    }
+
+    fn interpret_stmts(&mut self, stmts: &[Stmt]) -> Result<Option<Object>, RuntimeError> {
+        let mut value = None;
+
         for stmt in stmts {
-            self.execute(&stmt)
-                .inspect_err(|e| {
-                    self.error(&e.token, e.message.as_str());
-                })
-                .unwrap();
+            value = match stmt {
+                Stmt::Expression(expr, _) => {
+                    self.spend()?;
+                    if let Some(ref mut profile) = self.profile {
+                        profile.record(stmt.kind());
+                    }
+                    Some(self.evaluate(expr)?)
+                }
+                _ => match self.execute(stmt) {
+                    Ok(()) => None,
+                    Err(Signal::RuntimeError(e)) => return Err(e),
+                    // The resolver rejects `return` outside of functions
+                    Err(Signal::Return(value)) => return Ok(Some(value)),
+                    Err(Signal::Break(_) | Signal::Continue(_)) => {
+                        return Err(RuntimeError::native(
+                            "[UNREACHABLE] The resolver rejects jumps outside of loops.",
+                        ))
+                    }
+                },
+            };
         }
+
+        Ok(value)
+    }
+
+    /// Runs `stmts` like `interpret`, but reports a runtime error to the error
+    /// reporter, or stderr without one, instead of returning it.
+    pub fn run(&mut self, stmts: Vec<Stmt>) {
+        if let Err(e) = self.interpret(stmts) {
+            self.error(&e);
+        }
+    }
+
+    /// Records that the variable named by `identifier` lives in the local scope
+    /// `depth` levels up from where it is used, at index `slot`.
+    pub(crate) fn resolve(&mut self, identifier: &Token, depth: usize, slot: usize) {
+        self.locals.insert(identifier, (depth, slot));
+    }
+
+    pub(crate) fn resolve_global(&mut self, identifier: &Token) {
+        // The address may belong to a token from an earlier, dropped program
+        self.locals.remove(&(identifier as *const Token));
+    }
+
+    /// Reads `readLine` input from `input` instead of stdin.
+    pub fn set_reader(&mut self, input: impl BufRead + 'a) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// The arguments `args()` returns to the script, none by default.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub(crate) fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Picks which values are false in conditions and logical operators,
+    /// Lox's `false` and `nil` by default.
+    pub fn set_truthiness(&mut self, truthiness: Truthiness) {
+        self.truthiness = truthiness;
+    }
+
+    pub(crate) fn is_truthy(&self, value: &Object) -> bool {
+        value.is_truthy_under(self.truthiness)
+    }
+
+    /// Picks how `print`, string concatenation and `str` write numbers, as
+    /// many digits as it takes to read them back by default.
+    pub fn set_number_format(&mut self, number_format: NumberFormat) {
+        self.number_format = number_format;
+    }
+
+    pub(crate) fn stringify(&self, value: &Object) -> String {
+        value.display_under(self.number_format).to_string()
+    }
+
+    /// Restarts the numbers `random` and `randomInt` return from `seed`, so
+    /// runs seeded alike see the same ones. Each interpreter starts from a
+    /// different seed otherwise.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random_state = seed;
+    }
+
+    /// The next number of a SplitMix64 sequence.
+    pub(crate) fn next_random(&mut self) -> u64 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Lets `readFile` and `writeFile` touch the filesystem, the default.
+    /// Embedders running untrusted scripts can turn it off, then both raise a
+    /// runtime error instead.
+    pub fn set_filesystem_access(&mut self, filesystem: bool) {
+        self.filesystem = filesystem;
+    }
+
+    pub(crate) fn check_filesystem_access(&self) -> Result<(), RuntimeError> {
+        if !self.filesystem {
+            return Err(RuntimeError::native("Filesystem access is disabled."));
+        }
+        Ok(())
+    }
+
+    /// Lets `exit` end the process, as the CLI does. It is off by default, so
+    /// that a script can't take down the program embedding it: `exit` raises
+    /// a runtime error instead.
+    pub fn set_process_exit(&mut self, process_exit: bool) {
+        self.process_exit = process_exit;
+    }
+
+    pub(crate) fn check_process_exit(&self) -> Result<(), RuntimeError> {
+        if !self.process_exit {
+            return Err(RuntimeError::native("Exiting the process is disabled."));
+        }
+        Ok(())
+    }
+
+    /// Reads one line without its line terminator, or `None` at end of input.
+    pub(crate) fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+
+        let read = match self.input {
+            Some(ref mut input) => input.read_line(&mut line)?,
+            None => io::stdin().read_line(&mut line)?,
+        };
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+
+        Ok(Some(line))
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Limits how deeply Lox functions may recurse before a "Stack overflow."
+    /// runtime error is raised.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    pub(crate) fn enter_call(&mut self) -> Result<(), RuntimeError> {
+        self.check_cancelled()?;
+        if self.call_depth >= self.max_call_depth {
+            return Err(RuntimeError::native("Stack overflow."));
+        }
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
     }
 
     pub fn set_error_reporter(&mut self, reporter: &'a ErrorReporter) {
         self._reporter = Some(reporter);
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
-        stmt.accept(self)
+    pub(crate) fn execute(&mut self, stmt: &Stmt) -> Result<(), Signal> {
+        self.spend()?;
+        if let Some(ref mut profile) = self.profile {
+            profile.record(stmt.kind());
+        }
+        stmt.accept(self).map_err(|signal| match signal {
+            Signal::RuntimeError(e) => Signal::RuntimeError(e.within(stmt.span())),
+            signal => signal,
+        })
     }
 
-    pub fn execute_block(
-        &mut self,
-        stmts: &Vec<Stmt>,
-        env: Environment,
-    ) -> Result<(), RuntimeError> {
+    pub fn execute_block(&mut self, stmts: &[Stmt], env: Environment) -> Result<(), Signal> {
         let prev_env = Rc::clone(&self.env);
         let mut this = scopeguard::guard(self, |_self| {
             _self.env = prev_env;
@@ -92,15 +437,29 @@ impl<'a> Interpreter<'a> {
         Ok(())
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Object, RuntimeError> {
-        expr.accept(self)
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Object, RuntimeError> {
+        self.spend()?;
+        if let Some(ref mut profile) = self.profile {
+            profile.record(expr.kind());
+        }
+        expr.accept(self).map_err(|e| e.within(expr.span()))
+    }
+
+    /// Evaluates `expr` with `env` as the current environment.
+    pub(crate) fn evaluate_in(&mut self, expr: &Expr, env: &Scope) -> Result<Object, RuntimeError> {
+        let prev_env = std::mem::replace(&mut self.env, Rc::clone(env));
+        let mut this = scopeguard::guard(self, |_self| {
+            _self.env = prev_env;
+        });
+
+        this.evaluate(expr)
     }
 
     fn non_numeric_operand_error<T>(&self, token: &Token) -> Result<T, RuntimeError> {
         Err(RuntimeError {
-            value: None,
             token: token.clone(),
             message: "operands must be numeric for operation".to_string(),
+            span: None,
         })
     }
 
@@ -116,22 +475,138 @@ impl<'a> Interpreter<'a> {
                 TokenType::Minus => Ok(Object::Number(lvn - rvn)),
                 TokenType::Star => Ok(Object::Number(lvn * rvn)),
                 TokenType::Slash => Ok(Object::Number(lvn / rvn)),
+                TokenType::StarStar => Ok(Object::Number(lvn.powf(rvn))),
                 _ => Err(RuntimeError {
-                    value: None,
                     token: token.clone(),
                     message: "unknown math operation".to_string(),
+                    span: None,
                 }),
             },
             _ => self.non_numeric_operand_error(token),
         }
     }
 
-    fn error(&self, token: &Token, message: &str) {
+    // Bitwise operators work on the integers that numbers hold exactly, see
+    // `MAX_SAFE_INTEGER`, and fail rather than round their operands or result
+    fn bitwise_operation(
+        &self,
+        left_value: Object,
+        right_value: Object,
+        token: &Token,
+    ) -> Result<Object, RuntimeError> {
+        let (Object::Number(left), Object::Number(right)) = (left_value, right_value) else {
+            return self.non_numeric_operand_error(token);
+        };
+        let (left, right) = (
+            self.bitwise_operand(left, token)?,
+            self.bitwise_operand(right, token)?,
+        );
+
+        let result = match token.token_type {
+            TokenType::Ampersand => i128::from(left & right),
+            TokenType::Pipe => i128::from(left | right),
+            TokenType::Caret => i128::from(left ^ right),
+            TokenType::LessLess | TokenType::GreaterGreater if right < 0 => {
+                return Err(RuntimeError {
+                    token: token.clone(),
+                    message: "Shift amount must not be negative.".to_string(),
+                    span: None,
+                })
+            }
+            // Only zero stays safe when shifted this far
+            TokenType::LessLess if right >= 64 => i128::from(left != 0) << 64,
+            TokenType::LessLess => i128::from(left) << right,
+            TokenType::GreaterGreater => i128::from(left >> right.min(63)),
+            _ => {
+                return Err(RuntimeError {
+                    token: token.clone(),
+                    message: "unknown bitwise operation".to_string(),
+                    span: None,
+                })
+            }
+        };
+        self.bitwise_result(result, token)
+    }
+
+    fn bitwise_operand(&self, number: f64, token: &Token) -> Result<i64, RuntimeError> {
+        safe_integer(number).ok_or_else(|| RuntimeError {
+            token: token.clone(),
+            message: "Operand is not a safe integer.".to_string(),
+            span: None,
+        })
+    }
+
+    fn bitwise_result(&self, result: i128, token: &Token) -> Result<Object, RuntimeError> {
+        if result.unsigned_abs() > MAX_SAFE_INTEGER as u128 {
+            return Err(RuntimeError {
+                token: token.clone(),
+                message: "Result is not a safe integer.".to_string(),
+                span: None,
+            });
+        }
+        Ok(Object::Number(result as f64))
+    }
+
+    // Slices of lists are new lists, and slices of strings new strings, see
+    // `Object::to_slice_in` for their bounds
+    fn slice(
+        &mut self,
+        object: Object,
+        start: &Expr,
+        operator: &Token,
+        end: &Expr,
+    ) -> Result<Object, RuntimeError> {
+        let start = self.evaluate(start)?;
+        let end = self.evaluate(end)?;
+        let inclusive = operator.token_type == TokenType::DotDotEqual;
+        let range =
+            |len| Object::to_slice_in(&start, &end, inclusive, len).map_err(|e| e.at(operator));
+
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                Ok(Object::list(list[range(list.len())?].to_vec()))
+            }
+            Object::String(string) => {
+                let range = range(string.chars().count())?;
+                let slice = string.chars().skip(range.start).take(range.len());
+                Ok(Object::String(slice.collect()))
+            }
+            _ => Err(RuntimeError {
+                token: operator.clone(),
+                message: "Only lists and strings can be sliced.".to_string(),
+                span: None,
+            }),
+        }
+    }
+
+    // `start..end` excludes `end`, `start..=end` includes it
+    fn range(&self, start: Object, end: Object, operator: &Token) -> Result<Object, RuntimeError> {
+        let (Object::Number(start), Object::Number(end)) = (start, end) else {
+            return self.non_numeric_operand_error(operator);
+        };
+
+        if start > end {
+            return Err(RuntimeError {
+                token: operator.clone(),
+                message: "Range start must not be greater than its end.".to_string(),
+                span: None,
+            });
+        }
+
+        let end = match operator.token_type {
+            TokenType::DotDotEqual => end + 1.0,
+            _ => end,
+        };
+        Object::range(start, end).map_err(|e| e.at(operator))
+    }
+
+    fn error(&self, error: &RuntimeError) {
         match self._reporter {
-            Some(reporter) => reporter.runtime_error(token, message),
+            Some(reporter) => reporter.runtime_error(error),
 
             // Reporter does not exist, print to stderr
-            None => eprintln!("[Error]: {}", message),
+            None => eprintln!("{}", error),
         }
     }
 }
@@ -151,7 +626,7 @@ impl ExprVisitor<Object> for Interpreter<'_> {
         let right_val = self.evaluate(right)?;
 
         match operator.token_type {
-            TokenType::Minus | TokenType::Star | TokenType::Slash => {
+            TokenType::Minus | TokenType::Star | TokenType::Slash | TokenType::StarStar => {
                 self.math_operation(left_val, right_val, operator)
             }
             TokenType::Plus => match (&left_val, &right_val) {
@@ -161,7 +636,7 @@ impl ExprVisitor<Object> for Interpreter<'_> {
                 _ => {
                     // DECISION #1: convert the operands to string if they are not number
                     Ok(Object::String(
-                        String::from(left_val.clone()) + &String::from(right_val.clone()),
+                        self.stringify(&left_val) + &self.stringify(&right_val),
                     ))
                 }
             },
@@ -189,6 +664,12 @@ impl ExprVisitor<Object> for Interpreter<'_> {
                 }
                 _ => self.non_numeric_operand_error(operator),
             },
+            TokenType::DotDot | TokenType::DotDotEqual => self.range(left_val, right_val, operator),
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater => self.bitwise_operation(left_val, right_val, operator),
             TokenType::BangEqual => Ok(Object::Boolean(left_val != right_val)),
             TokenType::EqualEqual => Ok(Object::Boolean(left_val == right_val)),
             _ => {
@@ -205,11 +686,18 @@ impl ExprVisitor<Object> for Interpreter<'_> {
                 Object::Number(n) => Ok(Object::Number(-n)),
                 _ => self.non_numeric_operand_error(operator),
             },
-            TokenType::Bang => Ok(Object::Boolean(!bool::from(right_expr_value))),
+            TokenType::Bang => Ok(Object::Boolean(!self.is_truthy(&right_expr_value))),
+            TokenType::Tilde => match right_expr_value {
+                Object::Number(n) => {
+                    let n = self.bitwise_operand(n, operator)?;
+                    self.bitwise_result(i128::from(!n), operator)
+                }
+                _ => self.non_numeric_operand_error(operator),
+            },
             _ => Err(RuntimeError {
-                value: None,
                 token: operator.clone(),
                 message: "unexpected token on unary expression".to_string(),
+                span: None,
             }),
         }
     }
@@ -225,7 +713,13 @@ impl ExprVisitor<Object> for Interpreter<'_> {
     }
 
     fn visit_variable_expr(&mut self, identifier: &Token) -> Result<Object, RuntimeError> {
-        self.env.borrow().get(identifier)
+        match self.locals.get(&(identifier as *const Token)) {
+            Some(&(depth, slot)) => Ok(self.env.borrow().get_at(depth, slot)),
+            None => self
+                .globals
+                .borrow()
+                .get(&self.symbol(identifier), identifier),
+        }
     }
 
     fn visit_assign_expr(
@@ -234,7 +728,17 @@ impl ExprVisitor<Object> for Interpreter<'_> {
         value: &Expr,
     ) -> Result<Object, RuntimeError> {
         let val = self.evaluate(value)?;
-        self.env.borrow_mut().assign(identifier, Some(val))
+
+        match self.locals.get(&(identifier as *const Token)) {
+            Some(&(depth, slot)) => {
+                self.env.borrow_mut().set_at(depth, slot, val.clone());
+                Ok(val)
+            }
+            None => {
+                let symbol = self.symbol(identifier);
+                self.globals.borrow_mut().assign(&symbol, identifier, val)
+            }
+        }
     }
 
     fn visit_logical_expr(
@@ -244,7 +748,21 @@ impl ExprVisitor<Object> for Interpreter<'_> {
         right: &Expr,
     ) -> Result<Object, RuntimeError> {
         let left = self.evaluate(left)?;
-        let boolean_value = bool::from(&left);
+
+        if operator.token_type == TokenType::QuestionQuestion {
+            return match left {
+                Object::Nil => self.evaluate(right),
+                left => Ok(left),
+            };
+        }
+
+        let boolean_value = self.is_truthy(&left);
+
+        // There's nothing to short-circuit, and the result is always a boolean
+        if operator.token_type == TokenType::Xor {
+            let right = self.evaluate(right)?;
+            return Ok(Object::Boolean(boolean_value != self.is_truthy(&right)));
+        }
 
         if (operator.token_type == TokenType::Or && boolean_value)
             || (operator.token_type == TokenType::And && !boolean_value)
@@ -259,7 +777,8 @@ impl ExprVisitor<Object> for Interpreter<'_> {
         &mut self,
         callee: &Expr,
         paren: &Token,
-        args: &Vec<Expr>,
+        args: &[Expr],
+        named: &[(Token, Expr)],
     ) -> Result<Object, RuntimeError> {
         let callee_result = self.evaluate(callee)?;
 
@@ -269,40 +788,248 @@ impl ExprVisitor<Object> for Interpreter<'_> {
             args_results.push(self.evaluate(arg)?);
         }
 
+        let mut named_results = vec![];
+
+        for (name, value) in named {
+            named_results.push((name, self.evaluate(value)?));
+        }
+
         match callee_result {
             Object::Callable(ref _fn) => {
-                if args_results.len() != _fn.arity() {
+                if !named_results.is_empty() {
+                    args_results = _fn.bind(self, args_results, named_results, paren)?;
+                }
+
+                if !_fn.arity().accepts(args_results.len()) {
                     return Err(RuntimeError {
-                        value: None,
                         token: paren.clone(),
                         message: format!(
-                            "Expected {} arguments but got {}.",
+                            "{} expected {} but got {}.",
+                            _fn,
                             _fn.arity(),
                             args_results.len()
                         ),
+                        span: None,
                     });
                 }
 
-                _fn.call(self, &args_results)
+                let start = self.profile.is_some().then(Instant::now);
+                let result = _fn.call(self, &args_results);
+                if let (Some(profile), Some(start)) = (&mut self.profile, start) {
+                    profile.calls += 1;
+                    profile.call_time += start.elapsed();
+                }
+
+                // Errors raised by native functions carry no location
+                result.map_err(|e| e.at(paren))
             }
+            _ if paren.token_type == TokenType::PipeGreater => Err(RuntimeError {
+                token: paren.clone(),
+                message: "Can only pipe into functions or classes.".to_string(),
+                span: None,
+            }),
             _ => Err(RuntimeError {
-                value: None,
                 token: paren.clone(),
                 message: "Can only call functions or classes".to_string(),
+                span: None,
+            }),
+        }
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        stmts: &[Stmt],
+        value: Option<&Expr>,
+    ) -> Result<Object, RuntimeError> {
+        let env = Environment::new(Some(Rc::clone(&self.env)));
+        let prev_env = std::mem::replace(&mut self.env, Rc::new(RefCell::new(env)));
+        let mut this = scopeguard::guard(self, |_self| {
+            _self.env = prev_env;
+        });
+
+        for stmt in stmts {
+            match this.execute(stmt) {
+                Ok(()) => (),
+                Err(Signal::RuntimeError(e)) => return Err(e),
+                Err(Signal::Return(_) | Signal::Break(_) | Signal::Continue(_)) => {
+                    return Err(RuntimeError::native(
+                        "[UNREACHABLE] The resolver rejects jumps out of block expressions.",
+                    ))
+                }
+            }
+        }
+
+        match value {
+            Some(value) => this.evaluate(value),
+            None => Ok(Object::Nil),
+        }
+    }
+
+    fn visit_list_expr(
+        &mut self,
+        _bracket: &Token,
+        elements: &[Expr],
+    ) -> Result<Object, RuntimeError> {
+        let mut list = Vec::with_capacity(elements.len());
+        for element in elements {
+            list.push(self.evaluate(element)?);
+        }
+        Ok(Object::list(list))
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<Object, RuntimeError> {
+        let mut map = BTreeMap::new();
+        for (key, value) in entries {
+            let Some(Literal::String(key)) = &key.literal else {
+                return Err(RuntimeError {
+                    token: key.clone(),
+                    message: "Map keys must be strings.".to_string(),
+                    span: None,
+                });
+            };
+            map.insert(key.clone(), self.evaluate(value)?);
+        }
+        Ok(Object::map(map))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+    ) -> Result<Object, RuntimeError> {
+        let object = self.evaluate(object)?;
+
+        // A range as the index, as in `list[1..3]`, is a slice
+        if let Expr::BinaryExpr(start, operator, end, _) = index {
+            if matches!(
+                operator.token_type,
+                TokenType::DotDot | TokenType::DotDotEqual
+            ) {
+                return self.slice(object, start, operator, end);
+            }
+        }
+
+        let index = self.evaluate(index)?;
+
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                let index = index.to_index_in(list.len()).map_err(|e| e.at(bracket))?;
+                Ok(list[index].clone())
+            }
+            Object::String(string) => {
+                let len = string.chars().count();
+                let index = index.to_index_in(len).map_err(|e| e.at(bracket))?;
+                Ok(Object::String(
+                    string.chars().nth(index).unwrap().to_string(),
+                ))
+            }
+            Object::Map(map) => {
+                let key = map_key(index, bracket)?;
+                Ok(map.borrow().get(&key).cloned().unwrap_or(Object::Nil))
+            }
+            _ => Err(RuntimeError {
+                token: bracket.clone(),
+                message: "Only lists, strings and maps can be indexed.".to_string(),
+                span: None,
             }),
         }
     }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<Object, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+
+        match object {
+            Object::List(list) => {
+                let mut list = list.borrow_mut();
+                let index = index.to_index_in(list.len()).map_err(|e| e.at(bracket))?;
+                list[index] = value.clone();
+            }
+            Object::Map(map) => {
+                let key = map_key(index, bracket)?;
+                map.borrow_mut().insert(key, value.clone());
+            }
+            _ => {
+                return Err(RuntimeError {
+                    token: bracket.clone(),
+                    message: "Only list elements and map entries can be assigned.".to_string(),
+                    span: None,
+                })
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// `map.key` is the entry at `key`. Other names are methods: any global
+    /// function is one of the value it's called on, bound as its first
+    /// argument, so `list.len()` is `len(list)`.
+    fn visit_lambda_expr(
+        &mut self,
+        keyword: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<Object, RuntimeError> {
+        Ok(Object::Callable(Function::User {
+            parameters: parameters.to_vec(),
+            defaults: Rc::clone(defaults),
+            identifier: keyword.clone(),
+            body: Rc::clone(body),
+            closure: Rc::clone(&self.env),
+        }))
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<Object, RuntimeError> {
+        let object = self.evaluate(object)?;
+
+        if let Object::Map(ref map) = object {
+            if let Some(value) = map.borrow().get(&*name.lexeme) {
+                return Ok(value.clone());
+            }
+        }
+
+        let method = self.globals.borrow().get(&self.symbol(name), name);
+        if let Ok(Object::Callable(function)) = method {
+            if let Some(method) = function.partial(vec![object]) {
+                return Ok(Object::Callable(method));
+            }
+        }
+
+        Err(RuntimeError {
+            token: name.clone(),
+            message: format!("Undefined property '{}'.", name.lexeme),
+            span: None,
+        })
+    }
 }
 
 impl StmtVisitor<()> for Interpreter<'_> {
-    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<(), Signal> {
         let value = self.evaluate(expr)?;
-        // TODO: implement Display on Object
-        println!("{}", value);
+        let text = self.stringify(&value);
+        writeln!(self.out, "{}", text).map_err(|e| RuntimeError {
+            token: Token::new(TokenType::Print, "print", None, 0),
+            message: format!("Failed to write output: {}.", e),
+            span: None,
+        })?;
         Ok(())
     }
 
-    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<(), Signal> {
         self.evaluate(expr)?;
         Ok(())
     }
@@ -311,19 +1038,38 @@ impl StmtVisitor<()> for Interpreter<'_> {
         &mut self,
         identifier: &Token,
         initializer: Option<&Expr>,
-    ) -> Result<(), RuntimeError> {
-        let mut value = None;
+    ) -> Result<(), Signal> {
+        // Variables declared without an initializer hold nil
+        let value = match initializer {
+            Some(expr) => self.evaluate(expr)?,
+            None => Object::Nil,
+        };
 
-        if let Some(expr) = initializer {
-            value = Some(self.evaluate(expr)?);
-        }
+        self.define(identifier, value)?;
+
+        Ok(())
+    }
 
-        self.env.borrow_mut().define(identifier, value);
+    fn visit_const_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: &Expr,
+    ) -> Result<(), Signal> {
+        let value = self.evaluate(initializer)?;
+
+        if Rc::ptr_eq(&self.env, &self.globals) {
+            let symbol = self.symbol(identifier);
+            let result = self.globals.borrow_mut().define_constant(symbol, value);
+            result.map_err(|e| e.at(identifier))?;
+        } else {
+            // The resolver rejects assignments to local constants
+            self.env.borrow_mut().define_slot(value);
+        }
 
         Ok(())
     }
 
-    fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<(), Signal> {
         let env = Rc::clone(&self.env);
         self.execute_block(stmts, Environment::new(Some(env)))?;
         Ok(())
@@ -334,9 +1080,9 @@ impl StmtVisitor<()> for Interpreter<'_> {
         expr: &Expr,
         stmt_then: &Stmt,
         stmt_else: &Option<Box<Stmt>>,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<(), Signal> {
         let condition_result = self.evaluate(expr)?;
-        let boolean_result = bool::from(condition_result);
+        let boolean_result = self.is_truthy(&condition_result);
 
         if boolean_result {
             self.execute(stmt_then)?;
@@ -347,9 +1093,61 @@ impl StmtVisitor<()> for Interpreter<'_> {
         Ok(())
     }
 
-    fn visit_while_stmt(&mut self, expr: &Expr, stmt: &Stmt) -> Result<(), RuntimeError> {
-        while bool::from(self.evaluate(expr)?) {
-            self.execute(stmt)?;
+    fn visit_while_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt: &Stmt,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        loop {
+            let condition = self.evaluate(expr)?;
+            if !self.is_truthy(&condition) {
+                return Ok(());
+            }
+            match self.execute(stmt) {
+                Err(signal @ Signal::Break(_)) if signal.targets(label) => return Ok(()),
+                Err(signal @ Signal::Continue(_)) if signal.targets(label) => (),
+                result => result?,
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+            self.check_cancelled()?;
+        }
+    }
+
+    /// Loops over the elements the list has when the loop starts, so the body
+    /// can add to or remove from the list without changing the iterations.
+    /// Every iteration has its own loop variable, which closures capture.
+    fn visit_for_in_stmt(
+        &mut self,
+        identifier: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        let elements = match self.evaluate(iterable)? {
+            Object::List(list) => list.borrow().clone(),
+            other => {
+                return Err(RuntimeError {
+                    token: identifier.clone(),
+                    message: format!("Can only iterate over lists, got {}.", other.type_name()),
+                    span: None,
+                }
+                .into())
+            }
+        };
+
+        for element in elements {
+            let mut env = Environment::new(Some(Rc::clone(&self.env)));
+            env.define_slot(element);
+            match self.execute_block(std::slice::from_ref(body), env) {
+                Err(signal @ Signal::Break(_)) if signal.targets(label) => return Ok(()),
+                Err(signal @ Signal::Continue(_)) if signal.targets(label) => (),
+                result => result?,
+            }
+            self.check_cancelled()?;
         }
         Ok(())
     }
@@ -357,28 +1155,649 @@ impl StmtVisitor<()> for Interpreter<'_> {
     fn visit_function_stmt(
         &mut self,
         identifier: &Token,
-        parameters: &Vec<Token>,
-        body: &Box<Stmt>,
-    ) -> Result<(), RuntimeError> {
-        self.env.borrow_mut().define(
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<(), Signal> {
+        self.define(
             identifier,
-            Some(Object::Callable(Function::User {
-                parameters: parameters.clone(),
+            Object::Callable(Function::User {
+                parameters: parameters.to_vec(),
+                defaults: Rc::clone(defaults),
                 identifier: identifier.clone(),
-                body: body.clone(),
+                body: Rc::clone(body),
                 closure: Rc::clone(&self.env),
-            })),
-        );
+            }),
+        )?;
 
         Ok(())
     }
 
-    fn visit_return_stmt(&mut self, token: &Token, expr: &Expr) -> Result<(), RuntimeError> {
+    fn visit_return_stmt(&mut self, _token: &Token, expr: &Expr) -> Result<(), Signal> {
         let result = self.evaluate(expr)?;
-        Err(RuntimeError {
-            token: token.clone(),
-            message: "<fn return>".to_string(),
-            value: Some(result),
+        Err(Signal::Return(result))
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token, label: Option<&Token>) -> Result<(), Signal> {
+        Err(Signal::Break(label.cloned()))
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        Err(Signal::Continue(label.cloned()))
+    }
+
+    fn visit_switch_stmt(
+        &mut self,
+        expr: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: Option<&[Stmt]>,
+    ) -> Result<(), Signal> {
+        let value = self.evaluate(expr)?;
+
+        // Case values are evaluated in order, up to the first match
+        for (case, stmts) in cases {
+            if self.evaluate(case)? == value {
+                return self.visit_block_stmt(stmts);
+            }
+        }
+
+        match default {
+            Some(stmts) => self.visit_block_stmt(stmts),
+            None => Ok(()),
+        }
+    }
+}
+
+fn map_key(index: Object, bracket: &Token) -> Result<String, RuntimeError> {
+    match index {
+        Object::String(key) => Ok(key),
+        _ => Err(RuntimeError {
+            token: bracket.clone(),
+            message: "Map keys must be strings.".to_string(),
+            span: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+
+    // Runs `source` with an interpreter `setup` configures, returning what it
+    // printed. The tests of the other interpreter modules use it too.
+    pub(crate) fn run_with(source: &str, setup: impl FnOnce(&mut Interpreter)) -> String {
+        let tokens = Scanner::new(source).scan_tokens();
+        let statements = Parser::new(tokens).parse();
+
+        let mut out = Vec::new();
+        let mut interpreter = Interpreter::with_writer(&mut out);
+        setup(&mut interpreter);
+        Resolver::new(&mut interpreter).resolve(&statements);
+        interpreter.run(statements);
+        drop(interpreter);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    // Like `run_with`, but returns what `interpret` does and drops the output
+    pub(crate) fn interpret_with(
+        source: &str,
+        setup: impl FnOnce(&mut Interpreter),
+    ) -> Result<Option<Object>, RuntimeError> {
+        let mut interpreter = Interpreter::with_writer(io::sink());
+        setup(&mut interpreter);
+        interpret_in(&mut interpreter, source)
+    }
+
+    // Interprets `source` with an interpreter that may have run other
+    // programs before
+    fn interpret_in(
+        interpreter: &mut Interpreter,
+        source: &str,
+    ) -> Result<Option<Object>, RuntimeError> {
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        Resolver::new(interpreter).resolve(&statements);
+        interpreter.interpret(statements)
+    }
+
+    fn run(source: &str) -> String {
+        run_with(source, |_| {})
+    }
+
+    #[test]
+    fn print_writes_to_configured_writer() {
+        assert_eq!(run("print 1 + 2; print \"a\" + \"b\";"), "3\nab\n");
+    }
+
+    #[test]
+    fn native_functions_capture_host_state() {
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = Rc::clone(&calls);
+
+        let output = run_with("count(); count(); print count();", |interpreter| {
+            interpreter.define_native("count", 0, move |_, _| {
+                counter.set(counter.get() + 1);
+                Ok(Object::Number(counter.get() as f64))
+            });
+        });
+
+        assert_eq!(calls.get(), 3);
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn native_functions_raise_runtime_errors() {
+        let reporter = ErrorReporter::new();
+        let tokens = Scanner::new("fail(); print 1;").scan_tokens();
+
+        let mut interpreter = Interpreter::with_writer(io::sink());
+        interpreter.set_error_reporter(&reporter);
+        interpreter.define_native("fail", 0, |_, _| Err(RuntimeError::native("boom")));
+        let statements = Parser::new(tokens).parse();
+        Resolver::new(&mut interpreter).resolve(&statements);
+        interpreter.run(statements);
+
+        assert!(reporter.has_runtime_error());
+        assert_eq!(reporter.diagnostics()[0].message, "boom");
+    }
+
+    #[test]
+    fn deep_recursion_is_a_runtime_error() {
+        let reporter = ErrorReporter::new();
+        let tokens = Scanner::new("fun f(n) { return f(n + 1); }\nf(0);").scan_tokens();
+
+        let mut interpreter = Interpreter::with_writer(io::sink());
+        interpreter.set_error_reporter(&reporter);
+        interpreter.set_max_call_depth(50);
+        let statements = Parser::new(tokens).parse();
+        Resolver::new(&mut interpreter).resolve(&statements);
+        interpreter.run(statements);
+
+        let diagnostics = reporter.diagnostics();
+        assert_eq!(diagnostics[0].message, "Stack overflow.");
+        assert_eq!(diagnostics[0].line, 1);
+    }
+
+    #[test]
+    fn errors_in_function_bodies_are_not_return_values() {
+        let source = "
+            fun f(n) {
+                if (n > 0) return n;
+                print \"before\";
+                return missing;
+            }
+            print f(1);
+            print f(0);
+            print \"after\";
+        ";
+        let mut reporter = ErrorReporter::new();
+        reporter.set_print(false);
+        let mut out = Vec::new();
+
+        let mut interpreter = Interpreter::with_writer(&mut out);
+        interpreter.set_error_reporter(&reporter);
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        Resolver::new(&mut interpreter).resolve(&statements);
+        interpreter.run(statements);
+        drop(interpreter);
+
+        assert_eq!(String::from_utf8(out).unwrap(), "1\nbefore\n");
+        assert_eq!(
+            reporter.diagnostics()[0].message,
+            "Undefined variable 'missing'."
+        );
+    }
+
+    #[test]
+    fn exponentiation() {
+        assert_eq!(crate::eval("2 ** 3 ** 2;"), Ok(Object::Number(512.0)));
+        assert_eq!(crate::eval("2 * 3 ** 2;"), Ok(Object::Number(18.0)));
+        assert_eq!(crate::eval("2 ** 3 * 2;"), Ok(Object::Number(16.0)));
+        assert_eq!(crate::eval("-2 ** 2;"), Ok(Object::Number(-4.0)));
+        assert_eq!(crate::eval("4 ** -0.5;"), Ok(Object::Number(0.5)));
+
+        let diagnostics = crate::eval("2 ** \"3\";").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "operands must be numeric for operation"
+        );
+    }
+
+    #[test]
+    fn xor_truth_table() {
+        let cases = [
+            ("true xor true", false),
+            ("true xor false", true),
+            ("false xor true", true),
+            ("false xor false", false),
+            // Operands are tested for truthiness
+            ("nil xor 0", true),
+            ("\"\" xor \"a\"", false),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                crate::eval(&format!("{};", source)),
+                Ok(Object::Boolean(expected)),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn xor_evaluates_both_operands() {
+        assert_eq!(
+            run("var n = 0; fun bump() { n = n + 1; return true; } print bump() xor bump(); print n;"),
+            "false\n2\n"
+        );
+    }
+
+    #[test]
+    fn interpret_returns_the_last_expression_value() {
+        let interpret = |source: &str| interpret_with(source, |_| {});
+
+        assert_eq!(
+            interpret("var a = 2; a * 3;").unwrap(),
+            Some(Object::Number(6.0))
+        );
+        assert_eq!(interpret("1; print 2;").unwrap(), None);
+
+        let error = interpret("1; -nil; 2;").unwrap_err();
+        assert_eq!(error.message, "operands must be numeric for operation");
+        assert_eq!(error.token.lexeme.as_ref(), "-");
+    }
+
+    #[test]
+    fn named_arguments_bind_by_parameter_name() {
+        let area = "fun area(width, height) { return width - height; }";
+        let eval = |call: &str| crate::eval(&format!("{} {}", area, call));
+
+        assert_eq!(eval("area(height: 2, width: 10);"), Ok(Object::Number(8.0)));
+        assert_eq!(eval("area(10, height: 2);"), Ok(Object::Number(8.0)));
+
+        let message = |call: &str| eval(call).unwrap_err()[0].message.clone();
+        assert_eq!(
+            message("area(10, depth: 2);"),
+            "<fn area> has no parameter named 'depth'."
+        );
+        assert_eq!(
+            message("area(height: 1, height: 2);"),
+            "Duplicate argument 'height'."
+        );
+        assert_eq!(
+            message("area(10, width: 2);"),
+            "Argument 'width' was already passed by position."
+        );
+        assert_eq!(
+            message("area(height: 2);"),
+            "<fn area> is missing argument 'width'."
+        );
+        assert_eq!(
+            message("clock(at: 1);"),
+            "<native fn clock> doesn't take named arguments."
+        );
+        assert_eq!(
+            message("area(width: 10, 2);"),
+            "Positional arguments must come before named arguments."
+        );
+    }
+
+    #[test]
+    fn default_parameters_fill_omitted_arguments() {
+        let greet = "
+            var greeting = \"Hi\";
+            fun greet(name, greeting = greeting + \" \", end = \"!\") {
+                return greeting + name + end;
+            }
+        ";
+        let eval = |call: &str| crate::eval(&format!("{} {}", greet, call));
+        let string = |s: &str| Ok(Object::String(s.to_string()));
+
+        assert_eq!(eval("greet(\"Bob\");"), string("Hi Bob!"));
+        assert_eq!(eval("greet(\"Bob\", \"Yo \");"), string("Yo Bob!"));
+        assert_eq!(eval("greet(\"Bob\", end: \"?\");"), string("Hi Bob?"));
+        // Defaults see the closure, not the caller's scope
+        assert_eq!(
+            eval("fun f() { var greeting = \"Bye\"; return greet(\"Bob\"); } f();"),
+            string("Hi Bob!")
+        );
+
+        assert_eq!(
+            eval("greet();").unwrap_err()[0].message,
+            "Expected 1 to 3 arguments but got 0."
+        );
+        assert_eq!(
+            crate::eval("fun f(a = 1, b) {}").unwrap_err()[0].message,
+            "Parameters without a default can't follow ones with a default."
+        );
+    }
+
+    #[test]
+    fn switch_runs_the_first_matching_case() {
+        let switch = "
+            fun describe(n) {
+                switch (n) {
+                    case 1: print \"one\";
+                    case 1 + 1:
+                        var name = \"two\";
+                        print name;
+                    case 2: print \"unreachable\";
+                    default: print \"many\";
+                }
+            }
+        ";
+
+        assert_eq!(
+            run(&format!(
+                "{} describe(1); describe(2); describe(3);",
+                switch
+            )),
+            "one\ntwo\nmany\n"
+        );
+        assert_eq!(
+            run("switch (\"a\") { case \"b\": print 1; } print 2;"),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn constants_cannot_be_reassigned() {
+        let message = |source: &str| crate::eval(source).unwrap_err()[0].message.clone();
+
+        assert_eq!(
+            crate::eval("const a = 1; fun f() { const b = a + 1; return b; } f();"),
+            Ok(Object::Number(2.0))
+        );
+        assert_eq!(
+            message("const a = 1; a = 2;"),
+            "Cannot assign to constant 'a'."
+        );
+        assert_eq!(
+            message("fun f() { const b = 1; fun g() { b = 2; } }"),
+            "Cannot assign to constant 'b'."
+        );
+        // A local variable shadowing a constant is a different binding
+        assert_eq!(
+            crate::eval("const a = 1; { var a = 2; a = 3; } a;"),
+            Ok(Object::Number(1.0))
+        );
+        assert_eq!(message("const a;"), "Constant 'a' must be initialized.");
+
+        // Nor can a global constant be declared again
+        assert_eq!(
+            message("const a = 1; var a = 2;"),
+            "Cannot redeclare constant 'a'."
+        );
+        assert_eq!(
+            message("const a = 1; fun a() {}"),
+            "Cannot redeclare constant 'a'."
+        );
+        assert_eq!(
+            message("const a = 1; const a = 2;"),
+            "Cannot redeclare constant 'a'."
+        );
+        assert_eq!(
+            crate::eval("var a = 1; const a = 2; a;"),
+            Ok(Object::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn block_expressions_yield_their_last_expression() {
+        assert_eq!(
+            crate::eval("fun compute() { return 4; } var x = { var t = compute(); t * 2 }; x;"),
+            Ok(Object::Number(8.0))
+        );
+        assert_eq!(crate::eval("var x = { 1; }; x;"), Ok(Object::Nil));
+        assert_eq!(
+            crate::eval("var s = { 1 } + { var a = 2; a }; s;"),
+            Ok(Object::Number(3.0))
+        );
+        // The block's variables go out of scope
+        assert_eq!(
+            crate::eval("var t = 1; var x = { var t = 2; t }; t + x;"),
+            Ok(Object::Number(3.0))
+        );
+        // A block in statement position is still a statement
+        assert_eq!(run("var a = 1; { var a = 2; print a; } print a;"), "2\n1\n");
+
+        assert_eq!(
+            crate::eval("fun f() { return { return 1; }; }").unwrap_err()[0].message,
+            "Can't return from inside a block expression."
+        );
+        assert_eq!(
+            crate::eval("fun f() { return { fun g() { return 1; } g }; } f()();"),
+            Ok(Object::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn frozen_globals_are_read_only_for_scripts() {
+        let eval = |source: &str| {
+            interpret_with(source, |interpreter| {
+                interpreter.define_global("limit", Object::Number(3.0));
+                interpreter.freeze_globals();
+            })
+        };
+        let message = |source: &str| eval(source).unwrap_err().message;
+
+        assert_eq!(
+            message("clock = nil;"),
+            "Cannot assign to 'clock', globals are frozen."
+        );
+        assert_eq!(
+            message("fun clock() { return 0; }"),
+            "Cannot define 'clock', globals are frozen."
+        );
+        assert_eq!(
+            message("var helper = 1;"),
+            "Cannot define 'helper', globals are frozen."
+        );
+        assert_eq!(eval("var x;").unwrap_err().token.lexeme.as_ref(), "x");
+
+        // Locals, even shadowing a frozen global, are the script's own
+        assert_eq!(
+            eval("{ var clock = limit; clock = clock + 1; } max(limit, 1);").unwrap(),
+            Some(Object::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn budget_stops_infinite_loops() {
+        let run = |source: &str, budget: usize| {
+            interpret_with(source, |interpreter| interpreter.set_budget(Some(budget)))
+        };
+
+        let error = run("while (true) {}", 1000).unwrap_err();
+        assert_eq!(error.message, "Execution budget exceeded.");
+
+        // `1 + 2;` is a statement and three expressions
+        assert_eq!(run("1 + 2;", 4).unwrap(), Some(Object::Number(3.0)));
+        assert!(run("1 + 2;", 3).is_err());
+
+        assert!(Interpreter::with_budget(0).interpret(vec![]).is_ok());
+    }
+
+    #[test]
+    fn truthiness_can_be_like_c() {
+        let source = "
+            var taken = [];
+            if (0) push(taken, \"0\");
+            if (\"\") push(taken, \"empty\");
+            if (!0) push(taken, \"!0\");
+            var n = 3;
+            while (n and n > -1) n = n - 1;
+            push(taken, n);
+            push(taken, 1 and 0);
+            push(taken, \"\" or \"default\");
+            taken;
+        ";
+        let run = |truthiness: Truthiness| {
+            interpret_with(source, |interpreter| interpreter.set_truthiness(truthiness))
+                .unwrap()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(run(Truthiness::Lox), r#"["0", "empty", -1, 0, ""]"#);
+        assert_eq!(run(Truthiness::C), r#"["!0", 0, 0, "default"]"#);
+    }
+
+    #[test]
+    fn number_format_applies_to_print_and_concatenation() {
+        let source = "
+            print 2 / 3;
+            print \"total: \" + 1234.5;
+            print [0.126, nil];
+            print str(10);
+        ";
+        // The optimizer must not write the numbers it folds any other way
+        let mut statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        crate::optimizer::Optimizer::new().optimize(&mut statements);
+        let optimized = crate::parser::Formatter::new().format_stmts(&statements);
+
+        let run = |number_format: NumberFormat| {
+            let setup =
+                |interpreter: &mut Interpreter| interpreter.set_number_format(number_format);
+            let output = run_with(source, setup);
+            assert_eq!(run_with(&optimized, setup), output);
+            output
+        };
+
+        assert_eq!(
+            run(NumberFormat::Shortest),
+            "0.6666666666666666\ntotal: 1234.5\n[0.126, nil]\n10\n"
+        );
+        assert_eq!(
+            run(NumberFormat::Fixed(2)),
+            "0.67\ntotal: 1234.50\n[0.13, nil]\n10.00\n"
+        );
+        assert_eq!(
+            run(NumberFormat::Significant(3)),
+            "0.667\ntotal: 1230\n[0.126, nil]\n10.0\n"
+        );
+    }
+
+    #[test]
+    fn coalescing_only_evaluates_the_right_side_after_nil() {
+        let source = "
+            var calls = 0;
+            fun fallback() { calls = calls + 1; return 1; }
+            var results = [0 ?? fallback(), false ?? fallback(), \"\" ?? fallback()];
+            calls;
+        ";
+        assert_eq!(crate::eval(source), Ok(Object::Number(0.0)));
+
+        let source = "
+            var calls = 0;
+            fun fallback() { calls = calls + 1; return 1; }
+            var result = nil ?? fallback();
+            [result, calls];
+        ";
+        assert_eq!(crate::eval(source).unwrap().to_string(), "[1, 1]");
+    }
+
+    #[test]
+    fn bitwise_operators_only_take_safe_integers() {
+        let error = |source: &str| crate::eval(source).unwrap_err()[0].message.clone();
+
+        assert_eq!(
+            crate::eval("(2 ** 53 - 1) | 0;"),
+            Ok(Object::Number(9007199254740991.0))
+        );
+        assert_eq!(
+            crate::eval("-(2 ** 53 - 1) & -1;"),
+            Ok(Object::Number(-9007199254740991.0))
+        );
+        // 2^53 is also what 2^53 + 1 rounds to
+        assert_eq!(error("2 ** 53 | 0;"), "Operand is not a safe integer.");
+        assert_eq!(
+            error("9007199254740993 | 0;"),
+            "Operand is not a safe integer."
+        );
+        assert_eq!(
+            error("(2 ** 53 + 2) & 1;"),
+            "Operand is not a safe integer."
+        );
+        assert_eq!(
+            error("1 ^ -(2 ** 53 + 2);"),
+            "Operand is not a safe integer."
+        );
+        assert_eq!(error("0.5 | 0;"), "Operand is not a safe integer.");
+        assert_eq!(error("~(1 / 0);"), "Operand is not a safe integer.");
+
+        // Results from 2^53 on could have been rounded
+        assert_eq!(error("1 << 53;"), "Result is not a safe integer.");
+        assert_eq!(
+            error("-(2 ** 53 - 1) - 1 >> 0;"),
+            "Operand is not a safe integer."
+        );
+        assert_eq!(error("~(2 ** 53 - 1);"), "Result is not a safe integer.");
+        assert_eq!(error("1 << 54;"), "Result is not a safe integer.");
+        assert_eq!(error("1 << 64;"), "Result is not a safe integer.");
+        assert_eq!(crate::eval("0 << 100;"), Ok(Object::Number(0.0)));
+        assert_eq!(crate::eval("-1 >> 100;"), Ok(Object::Number(-1.0)));
+
+        assert_eq!(error("1 << -1;"), "Shift amount must not be negative.");
+        assert_eq!(
+            error("\"a\" & 1;"),
+            "operands must be numeric for operation"
+        );
+    }
+
+    #[test]
+    fn panics_become_runtime_errors() {
+        let mut interpreter = Interpreter::with_writer(io::sink());
+        interpreter.define_native("boom", 0, |_, _| panic!("boom"));
+        interpreter.define_native("fail", 0, |_, _| {
+            std::panic::panic_any(1);
+        });
+
+        let mut run = |source: &str| interpret_in(&mut interpreter, source);
+
+        let error = run("fun f() { { boom(); } } f();").unwrap_err();
+        assert_eq!(error.message, "Internal interpreter error: boom.");
+        let error = run("fail();").unwrap_err();
+        assert_eq!(error.message, "Internal interpreter error: unknown panic.");
+
+        // The interpreter is still usable afterwards
+        assert_eq!(run("var a = 1; a + 1;").unwrap(), Some(Object::Number(2.0)));
+    }
+
+    #[test]
+    fn cancellation_interrupts_running_scripts() {
+        use std::time::Duration;
+
+        let mut canceller = None;
+        let start = Instant::now();
+        let error = interpret_with("while (true) {}", |interpreter| {
+            let token = interpreter.cancellation_token();
+            canceller = Some(std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                token.store(true, Ordering::Relaxed);
+            }));
+        })
+        .unwrap_err();
+        canceller.unwrap().join().unwrap();
+
+        assert_eq!(error.message, "Interrupted.");
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // Calls check it too, before running the body
+        let error = interpret_with("fun f() {} f();", |interpreter| {
+            interpreter
+                .cancellation_token()
+                .store(true, Ordering::Relaxed)
         })
+        .unwrap_err();
+        assert_eq!(error.message, "Interrupted.");
     }
 }
@@ -1,8 +1,13 @@
 mod environment;
 mod function;
+#[allow(clippy::module_inception)]
 mod interpreter;
+mod natives;
 mod object;
+mod profile;
 
+pub use function::Arity;
 pub use interpreter::Interpreter;
 pub use interpreter::Scope;
-pub use object::Object;
+pub use object::{NumberFormat, Object, Truthiness};
+pub use profile::Profile;
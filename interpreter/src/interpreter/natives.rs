@@ -0,0 +1,1052 @@
+use super::function::{Arity, Function};
+use super::object::{safe_integer, NumberFormat, Object};
+use super::Interpreter;
+use crate::error::RuntimeError;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, process, thread};
+
+pub fn define<F>(interpreter: &mut Interpreter, name: &str, arity: impl Into<Arity>, body: F)
+where
+    F: Fn(&mut Interpreter, &[Object]) -> Result<Object, RuntimeError> + 'static,
+{
+    interpreter.define_global(
+        name,
+        Object::Callable(Function::Native {
+            identifier: name.to_string(),
+            arity: arity.into(),
+            body: Rc::new(body),
+        }),
+    );
+}
+
+fn number(args: &[Object], index: usize) -> Result<f64, RuntimeError> {
+    f64::try_from(args[index].clone())
+}
+
+fn integer(args: &[Object], index: usize) -> Result<i64, RuntimeError> {
+    safe_integer(number(args, index)?)
+        .ok_or_else(|| RuntimeError::native("Argument must be an integer."))
+}
+
+fn string(args: &[Object], index: usize) -> Result<&str, RuntimeError> {
+    match &args[index] {
+        Object::String(string) => Ok(string),
+        _ => Err(RuntimeError::native("Argument must be a string.")),
+    }
+}
+
+fn list(args: &[Object], index: usize) -> Result<&Rc<RefCell<Vec<Object>>>, RuntimeError> {
+    match &args[index] {
+        Object::List(list) => Ok(list),
+        _ => Err(RuntimeError::native("Argument must be a list.")),
+    }
+}
+
+fn function(args: &[Object], index: usize) -> Result<&Function, RuntimeError> {
+    match &args[index] {
+        Object::Callable(function) => Ok(function),
+        _ => Err(RuntimeError::native("Argument must be a function.")),
+    }
+}
+
+/// A function argument that is called back with `arity` arguments.
+fn callback(args: &[Object], index: usize, arity: usize) -> Result<&Function, RuntimeError> {
+    let function = function(args, index)?;
+    if !function.arity().accepts(arity) {
+        return Err(RuntimeError::native(&format!(
+            "Function must take {}.",
+            Arity::Exact(arity)
+        )));
+    }
+    Ok(function)
+}
+
+/// Stable merge sort that stops at the first error of `compare`. Unlike
+/// `slice::sort_by` it is fine with comparators that are not a total order,
+/// which Lox comparators are free to be.
+fn merge_sort<F>(elements: Vec<Object>, compare: &mut F) -> Result<Vec<Object>, RuntimeError>
+where
+    F: FnMut(&Object, &Object) -> Result<Ordering, RuntimeError>,
+{
+    if elements.len() < 2 {
+        return Ok(elements);
+    }
+
+    let mut left = elements;
+    let right = left.split_off(left.len() / 2);
+    let left = merge_sort(left, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if compare(l, r)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// How `sort` orders lists without a comparator.
+fn natural_order(a: &Object, b: &Object) -> Result<Ordering, RuntimeError> {
+    match (a, b) {
+        (Object::Number(a), Object::Number(b)) => Ok(a.total_cmp(b)),
+        (Object::String(a), Object::String(b)) => Ok(a.cmp(b)),
+        _ => Err(RuntimeError::native(
+            "Lists must hold only numbers or only strings to sort without a comparator.",
+        )),
+    }
+}
+
+fn unary_math(interpreter: &mut Interpreter, name: &str, op: fn(f64) -> f64) {
+    define(interpreter, name, 1, move |_, args| {
+        Ok(Object::Number(op(number(args, 0)?)))
+    });
+}
+
+fn binary_math(interpreter: &mut Interpreter, name: &str, op: fn(f64, f64) -> f64) {
+    define(interpreter, name, 2, move |_, args| {
+        Ok(Object::Number(op(number(args, 0)?, number(args, 1)?)))
+    });
+}
+
+pub fn register_math(interpreter: &mut Interpreter) {
+    unary_math(interpreter, "sqrt", f64::sqrt);
+    unary_math(interpreter, "abs", f64::abs);
+    unary_math(interpreter, "floor", f64::floor);
+    unary_math(interpreter, "ceil", f64::ceil);
+    unary_math(interpreter, "round", f64::round);
+    binary_math(interpreter, "pow", f64::powf);
+    binary_math(interpreter, "min", f64::min);
+    binary_math(interpreter, "max", f64::max);
+    define(interpreter, "is_nan", 1, |_, args| {
+        Ok(Object::Boolean(number(args, 0)?.is_nan()))
+    });
+
+    interpreter.define_global("pi", Object::Number(std::f64::consts::PI));
+    // How infinite and not-a-number results print, see `Object`'s `Display`
+    interpreter.define_global("inf", Object::Number(f64::INFINITY));
+    interpreter.define_global("nan", Object::Number(f64::NAN));
+}
+
+pub fn register_string(interpreter: &mut Interpreter) {
+    define(interpreter, "len", 1, |_, args| {
+        let len = match &args[0] {
+            Object::String(string) => string.chars().count(),
+            Object::List(list) => list.borrow().len(),
+            Object::Map(map) => map.borrow().len(),
+            _ => {
+                return Err(RuntimeError::native(
+                    "Argument must be a string, list or map.",
+                ))
+            }
+        };
+        Ok(Object::Number(len as f64))
+    });
+    define(interpreter, "substr", 3, |_, args| {
+        let start = args[1].to_index()?;
+        let len = args[2].to_index()?;
+        let substring = string(args, 0)?.chars().skip(start).take(len).collect();
+        Ok(Object::String(substring))
+    });
+    define(interpreter, "upper", 1, |_, args| {
+        Ok(Object::String(string(args, 0)?.to_uppercase()))
+    });
+    define(interpreter, "lower", 1, |_, args| {
+        Ok(Object::String(string(args, 0)?.to_lowercase()))
+    });
+    define(interpreter, "indexOf", 2, |_, args| {
+        let haystack = string(args, 0)?;
+        let index = match haystack.find(string(args, 1)?) {
+            // Report the position in chars, not bytes
+            Some(byte_index) => haystack[..byte_index].chars().count() as f64,
+            None => -1.0,
+        };
+        Ok(Object::Number(index))
+    });
+    // An empty separator splits between every char
+    define(interpreter, "split", 2, |_, args| {
+        let (string, separator) = (string(args, 0)?, string(args, 1)?);
+        let parts = if separator.is_empty() {
+            string
+                .chars()
+                .map(|char| Object::String(char.to_string()))
+                .collect()
+        } else {
+            string
+                .split(separator)
+                .map(|part| Object::String(part.to_string()))
+                .collect()
+        };
+        Ok(Object::List(Rc::new(RefCell::new(parts))))
+    });
+}
+
+pub fn register_collections(interpreter: &mut Interpreter) {
+    define(interpreter, "push", 2, |_, args| {
+        list(args, 0)?.borrow_mut().push(args[1].clone());
+        Ok(Object::Nil)
+    });
+    // `range(a, b)` is the integers from `a` up to, but not including, `b`
+    define(interpreter, "range", 2, |_, args| {
+        Object::range(number(args, 0)?, number(args, 1)?)
+    });
+    // Lists and maps are shared by assignment, `copy` is how to get a new one
+    define(interpreter, "copy", 1, |_, args| Ok(args[0].deep_clone()));
+
+    // The callbacks below see the elements the list had when the call began,
+    // even if they change the list
+    define(interpreter, "map", 2, |interpreter, args| {
+        let elements = list(args, 0)?.borrow().clone();
+        let function = callback(args, 1, 1)?;
+        let mapped = elements
+            .into_iter()
+            .map(|element| function.call(interpreter, &[element]))
+            .collect::<Result<_, _>>()?;
+        Ok(Object::List(Rc::new(RefCell::new(mapped))))
+    });
+    define(interpreter, "filter", 2, |interpreter, args| {
+        let elements = list(args, 0)?.borrow().clone();
+        let function = callback(args, 1, 1)?;
+        let mut kept = Vec::new();
+        for element in elements {
+            let keep = function.call(interpreter, std::slice::from_ref(&element))?;
+            if interpreter.is_truthy(&keep) {
+                kept.push(element);
+            }
+        }
+        Ok(Object::List(Rc::new(RefCell::new(kept))))
+    });
+    // `sort(list)` orders numbers or strings in place. `sort(list, compare)`
+    // orders anything, `compare(a, b)` is negative when `a` goes first
+    define(
+        interpreter,
+        "sort",
+        Arity::Between(1, 2),
+        |interpreter, args| {
+            let list = list(args, 0)?;
+            let elements = list.borrow().clone();
+
+            let sorted = match args.get(1) {
+                Some(_) => {
+                    let function = callback(args, 1, 2)?;
+                    merge_sort(elements, &mut |a, b| {
+                        let order = function.call(interpreter, &[a.clone(), b.clone()])?;
+                        match order {
+                            Object::Number(order) if order < 0.0 => Ok(Ordering::Less),
+                            Object::Number(order) if order > 0.0 => Ok(Ordering::Greater),
+                            Object::Number(_) => Ok(Ordering::Equal),
+                            _ => Err(RuntimeError::native("Comparator must return a number.")),
+                        }
+                    })?
+                }
+                None => merge_sort(elements, &mut natural_order)?,
+            };
+
+            *list.borrow_mut() = sorted;
+            Ok(Object::Nil)
+        },
+    );
+    define(interpreter, "reduce", 3, |interpreter, args| {
+        let elements = list(args, 0)?.borrow().clone();
+        let function = callback(args, 1, 2)?;
+        elements
+            .into_iter()
+            .try_fold(args[2].clone(), |acc, element| {
+                function.call(interpreter, &[acc, element])
+            })
+    });
+}
+
+/// Natives that take functions and return new ones. The returned natives
+/// close over what they need, like any other Rust closure.
+pub fn register_functional(interpreter: &mut Interpreter) {
+    // `memoize(f)` calls `f` once per distinct argument, compared by how
+    // they print, so `1` and `"1"` share a result
+    define(interpreter, "memoize", 1, |_, args| {
+        let function = function(args, 0)?.clone();
+        if !function.arity().accepts(1) {
+            return Err(RuntimeError::native(
+                "Only functions of one argument can be memoized.",
+            ));
+        }
+
+        let cache: RefCell<HashMap<String, Object>> = RefCell::new(HashMap::new());
+        Ok(Object::Callable(Function::Native {
+            identifier: function.name().to_string(),
+            arity: Arity::Exact(1),
+            body: Rc::new(move |interpreter, args| {
+                let key = args[0].to_string();
+                // The borrow ends before calling, a recursive function calls
+                // back into this cache
+                let cached = cache.borrow().get(&key).cloned();
+                if let Some(value) = cached {
+                    return Ok(value);
+                }
+
+                let value = function.call(interpreter, args)?;
+                cache.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            }),
+        }))
+    });
+
+    // `partial(f, a, b)` is `f` with `a` and `b` bound as its first arguments
+    define(interpreter, "partial", Arity::AtLeast(1), |_, args| {
+        let function = function(args, 0)?;
+        let bound = args[1..].to_vec();
+        match function.partial(bound) {
+            Some(partial) => Ok(Object::Callable(partial)),
+            None => Err(RuntimeError::native(&format!(
+                "Cannot bind {} to a function that takes {}.",
+                Arity::Exact(args.len() - 1),
+                function.arity()
+            ))),
+        }
+    });
+}
+
+pub fn register_conversion(interpreter: &mut Interpreter) {
+    define(interpreter, "num", 1, |_, args| match &args[0] {
+        Object::Number(number) => Ok(Object::Number(*number)),
+        Object::Boolean(boolean) => Ok(Object::Number(if *boolean { 1.0 } else { 0.0 })),
+        Object::String(string) => match string.trim().parse::<f64>() {
+            Ok(number) => Ok(Object::Number(number)),
+            Err(_) => Err(RuntimeError::native(&format!(
+                "Cannot convert '{}' to a number.",
+                string
+            ))),
+        },
+        _ => Err(RuntimeError::native(
+            "Argument must be a number, string or boolean.",
+        )),
+    });
+    define(interpreter, "str", 1, |interpreter, args| {
+        Ok(Object::String(interpreter.stringify(&args[0])))
+    });
+    define(interpreter, "toFixed", 2, |_, args| {
+        let (number, digits) = (number(args, 0)?, integer(args, 1)?);
+        if !(0..=100).contains(&digits) {
+            return Err(RuntimeError::native("Digits must be between 0 and 100."));
+        }
+        Ok(Object::String(
+            NumberFormat::Fixed(digits as usize).format(number),
+        ))
+    });
+    define(interpreter, "typeof", 1, |_, args| {
+        Ok(Object::String(args[0].type_name().to_string()))
+    });
+}
+
+/// `jsonParse(text)` and `jsonStringify(value)` convert between JSON and
+/// data values as `Object`'s serde implementations do, so they need the
+/// `serde` feature.
+#[cfg(feature = "serde")]
+pub fn register_json(interpreter: &mut Interpreter) {
+    define(interpreter, "jsonParse", 1, |_, args| {
+        serde_json::from_str(string(args, 0)?)
+            .map_err(|e| RuntimeError::native(&format!("Invalid JSON: {}.", e)))
+    });
+    define(interpreter, "jsonStringify", 1, |_, args| {
+        serde_json::to_string(&args[0])
+            .map(Object::String)
+            .map_err(|e| RuntimeError::native(&format!("Cannot convert to JSON: {}.", e)))
+    });
+}
+
+pub fn register_io(interpreter: &mut Interpreter) {
+    define(
+        interpreter,
+        "readLine",
+        0,
+        |interpreter, _| match interpreter.read_line() {
+            Ok(Some(line)) => Ok(Object::String(line)),
+            Ok(None) => Ok(Object::Nil),
+            Err(e) => Err(RuntimeError::native(&format!(
+                "Failed to read input: {}.",
+                e
+            ))),
+        },
+    );
+    define(interpreter, "readFile", 1, |interpreter, args| {
+        interpreter.check_filesystem_access()?;
+        let path = string(args, 0)?;
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Object::String(contents)),
+            Err(e) => Err(RuntimeError::native(&format!(
+                "Failed to read '{}': {}.",
+                path, e
+            ))),
+        }
+    });
+    // Creates the file, or replaces what it had
+    define(interpreter, "writeFile", 2, |interpreter, args| {
+        interpreter.check_filesystem_access()?;
+        let path = string(args, 0)?;
+        match fs::write(path, string(args, 1)?) {
+            Ok(()) => Ok(Object::Nil),
+            Err(e) => Err(RuntimeError::native(&format!(
+                "Failed to write '{}': {}.",
+                path, e
+            ))),
+        }
+    });
+}
+
+/// `exit(code)` flushes `print` output and terminates the process right away
+/// with `code`, which must be an integer in 0..=255. Since the process ends
+/// before control returns to the `Runner`, the script's code replaces the
+/// runner's own 65/70 codes; errors reported earlier are still on stderr.
+pub fn register_system(interpreter: &mut Interpreter) {
+    define(interpreter, "exit", 1, |interpreter, args| {
+        interpreter.check_process_exit()?;
+        let code = number(args, 0)?;
+
+        if code.fract() != 0.0 || !(0.0..=255.0).contains(&code) {
+            return Err(RuntimeError::native(
+                "Exit code must be an integer between 0 and 255.",
+            ));
+        }
+
+        if let Err(e) = interpreter.flush() {
+            return Err(RuntimeError::native(&format!(
+                "Failed to flush output: {}.",
+                e
+            )));
+        }
+
+        process::exit(code as i32)
+    });
+    // Unset variables, and those that aren't valid unicode, are nil
+    define(interpreter, "getenv", 1, |_, args| {
+        Ok(std::env::var(string(args, 0)?).map_or(Object::Nil, Object::String))
+    });
+    define(interpreter, "args", 0, |interpreter, _| {
+        let args = interpreter.args().iter().cloned().map(Object::String);
+        Ok(Object::List(Rc::new(RefCell::new(args.collect()))))
+    });
+}
+
+/// Pseudo-random numbers, reproducible with `seed(n)`. They are not fit for
+/// cryptography.
+pub fn register_random(interpreter: &mut Interpreter) {
+    // A number in [0, 1), from the top 53 bits
+    define(interpreter, "random", 0, |interpreter, _| {
+        const SCALE: f64 = (1u64 << 53) as f64;
+        let bits = interpreter.next_random() >> 11;
+        Ok(Object::Number(bits as f64 / SCALE))
+    });
+    // An integer in [lo, hi], both included
+    define(interpreter, "randomInt", 2, |interpreter, args| {
+        let (lo, hi) = (integer(args, 0)?, integer(args, 1)?);
+        if lo > hi {
+            return Err(RuntimeError::native(
+                "Lower bound must not be greater than the upper bound.",
+            ));
+        }
+
+        let count = (hi - lo) as u128 + 1;
+        let offset = (interpreter.next_random() as u128 * count) >> 64;
+        Ok(Object::Number((lo + offset as i64) as f64))
+    });
+    define(interpreter, "seed", 1, |interpreter, args| {
+        interpreter.set_seed(integer(args, 0)? as u64);
+        Ok(Object::Nil)
+    });
+}
+
+/// How long `sleep` waits at a time before checking whether the program was
+/// cancelled, see `Interpreter::cancellation_token`.
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+pub fn register_time(interpreter: &mut Interpreter) {
+    // Milliseconds since the Unix epoch
+    define(interpreter, "now", 0, |_, _| {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Ok(Object::Number(since_epoch.as_secs_f64() * 1000.0))
+    });
+    define(interpreter, "sleep", 1, |interpreter, args| {
+        let ms = number(args, 0)?;
+        if !(ms >= 0.0 && ms.is_finite()) {
+            return Err(RuntimeError::native(
+                "Duration must be a non-negative number of milliseconds.",
+            ));
+        }
+
+        let end = Instant::now() + Duration::from_secs_f64(ms / 1000.0);
+        loop {
+            interpreter.check_cancelled()?;
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(Object::Nil);
+            }
+            thread::sleep(left.min(SLEEP_SLICE));
+        }
+    });
+    // `formatTime(seconds)` is the UTC time that many seconds after the Unix
+    // epoch, as in `2024-02-29T13:05:09Z`
+    define(interpreter, "formatTime", 1, |_, args| {
+        let seconds = number(args, 0)?;
+        // Years beyond 9999 don't fit the format
+        if !(-62_167_219_200.0..253_402_300_800.0).contains(&seconds) {
+            return Err(RuntimeError::native(
+                "Time must be between the years 0 and 9999.",
+            ));
+        }
+
+        let seconds = seconds.floor() as i64;
+        let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+        let (year, month, day) = civil_from_days(days);
+        Ok(Object::String(format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        )))
+    });
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01, from Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+pub fn register_assert(interpreter: &mut Interpreter) {
+    define(interpreter, "assert", 1, |interpreter, args| {
+        if !interpreter.is_truthy(&args[0]) {
+            return Err(RuntimeError::native("Assertion failed."));
+        }
+        Ok(Object::Nil)
+    });
+    define(interpreter, "assert_eq", 2, |_, args| {
+        if args[0] != args[1] {
+            return Err(RuntimeError::native(&format!(
+                "Assertion failed: {:?} != {:?}.",
+                args[0], args[1]
+            )));
+        }
+        Ok(Object::Nil)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval;
+    use crate::interpreter::interpreter::tests::{interpret_with, run_with};
+    use crate::interpreter::Object;
+
+    #[test]
+    fn math_functions() {
+        assert_eq!(eval("sqrt(16);"), Ok(Object::Number(4.0)));
+        assert_eq!(eval("abs(-2.5);"), Ok(Object::Number(2.5)));
+        assert_eq!(eval("floor(1.7) + ceil(1.2);"), Ok(Object::Number(3.0)));
+        assert_eq!(eval("round(2.5);"), Ok(Object::Number(3.0)));
+        assert_eq!(eval("pow(2, 10);"), Ok(Object::Number(1024.0)));
+        assert_eq!(eval("min(3, 1) + max(3, 1);"), Ok(Object::Number(4.0)));
+        assert_eq!(eval("pi;"), Ok(Object::Number(std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn string_functions() {
+        let string = |s: &str| Ok(Object::String(s.to_string()));
+
+        assert_eq!(eval("len(\"héllo\");"), Ok(Object::Number(5.0)));
+        assert_eq!(eval("substr(\"héllo\", 1, 3);"), string("éll"));
+        assert_eq!(eval("substr(\"héllo\", 3, 10);"), string("lo"));
+        assert_eq!(eval("upper(\"héllo\");"), string("HÉLLO"));
+        assert_eq!(eval("lower(\"HÉLLO\");"), string("héllo"));
+        assert_eq!(
+            eval("indexOf(\"héllo\", \"llo\");"),
+            Ok(Object::Number(2.0))
+        );
+        assert_eq!(eval("indexOf(\"héllo\", \"x\");"), Ok(Object::Number(-1.0)));
+    }
+
+    #[test]
+    fn split_returns_a_list_of_strings() {
+        let split = |source: &str| eval(source).unwrap().to_string();
+
+        assert_eq!(split(r#"split("a,b,,c", ",");"#), r#"["a", "b", "", "c"]"#);
+        assert_eq!(split(r#"split("a, b", ", ");"#), r#"["a", "b"]"#);
+        assert_eq!(
+            split(r#"split("héllo", "");"#),
+            r#"["h", "é", "l", "l", "o"]"#
+        );
+        assert_eq!(split(r#"split("", "");"#), "[]");
+        // Without the separator there is a single part
+        assert_eq!(split(r#"split("abc", ";");"#), r#"["abc"]"#);
+        assert_eq!(split(r#"split("", ";");"#), r#"[""]"#);
+
+        let diagnostics = eval(r#"split("a", 1);"#).unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a string.");
+    }
+
+    #[test]
+    fn substr_rejects_invalid_indices() {
+        let diagnostics = eval("substr(\"abc\", -1, 2);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Index must not be negative.");
+
+        let diagnostics = eval("substr(\"abc\", 0, 1.5);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Index must be an integer.");
+    }
+
+    #[test]
+    fn string_functions_reject_non_strings() {
+        let diagnostics = eval("upper(1);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a string.");
+    }
+
+    #[test]
+    fn memoize_calls_functions_once_per_argument() {
+        let source = "
+            var calls = 0;
+            fun fib(n) {
+                calls = calls + 1;
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            fib = memoize(fib);
+        ";
+
+        assert_eq!(
+            eval(&format!("{} fib(30);", source)),
+            Ok(Object::Number(832040.0))
+        );
+        assert_eq!(
+            eval(&format!("{} fib(30); fib(30); calls;", source)),
+            Ok(Object::Number(31.0))
+        );
+        assert_eq!(
+            eval("fun f(a) { return a; } str(memoize(f));"),
+            Ok(Object::String("<native fn f>".to_string()))
+        );
+    }
+
+    #[test]
+    fn memoize_rejects_functions_of_other_arities() {
+        let diagnostics = eval("memoize(1);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a function.");
+
+        let diagnostics = eval("fun f(a, b) {} memoize(f);").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Only functions of one argument can be memoized."
+        );
+        assert_eq!(
+            eval("fun f(a, b = 1) { return a + b; } memoize(f)(1);"),
+            Ok(Object::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn partial_binds_leading_arguments() {
+        let source = "fun sub(a, b) { return a - b; } var from10 = partial(sub, 10);";
+
+        assert_eq!(
+            eval(&format!("{} from10(3);", source)),
+            Ok(Object::Number(7.0))
+        );
+        assert_eq!(
+            eval(&format!("{} partial(from10, 4)();", source)),
+            Ok(Object::Number(6.0))
+        );
+
+        let diagnostics = eval(&format!("{} from10(3, 4);", source)).unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "<native fn sub> expected 1 argument but got 2."
+        );
+
+        let diagnostics = eval(&format!("{} partial(sub, 1, 2, 3);", source)).unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Cannot bind 3 arguments to a function that takes 2 arguments."
+        );
+    }
+
+    #[test]
+    fn higher_order_list_functions() {
+        let source = "
+            fun even(n) { return n - floor(n / 2) * 2 == 0; }
+            fun square(n) { return n * n; }
+            fun add(a, b) { return a + b; }
+        ";
+
+        assert_eq!(
+            eval(&format!(
+                "{} reduce(map(filter(range(0, 6), even), square), add, 0);",
+                source
+            )),
+            Ok(Object::Number(20.0))
+        );
+        assert_eq!(
+            eval(&format!("{} str(map([], square));", source)),
+            Ok(Object::String("[]".to_string()))
+        );
+        assert_eq!(
+            eval(&format!("{} reduce([\"a\", \"b\"], add, \"\");", source)),
+            Ok(Object::String("ab".to_string()))
+        );
+    }
+
+    #[test]
+    fn higher_order_list_functions_check_their_callbacks() {
+        let diagnostics = eval("fun add(a, b) {} map([1], add);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Function must take 1 argument.");
+
+        let diagnostics = eval("fun f(a) {} reduce([1], f, 0);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Function must take 2 arguments.");
+
+        let diagnostics = eval("filter([1], 1);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a function.");
+
+        // Errors raised by the callback reach the caller unchanged
+        let source = "fun f(n) { return n - nil; }\nmap([1], f);";
+        let diagnostics = eval(source).unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "operands must be numeric for operation"
+        );
+        assert_eq!(diagnostics[0].line, 1);
+    }
+
+    #[test]
+    fn sort_orders_lists_in_place() {
+        let sorted = |source: &str| match eval(source) {
+            Ok(list) => list.to_string(),
+            Err(diagnostics) => diagnostics[0].message.clone(),
+        };
+
+        assert_eq!(
+            sorted("var a = [3, -1, 2.5, 0]; sort(a); a;"),
+            "[-1, 0, 2.5, 3]"
+        );
+        assert_eq!(
+            sorted("var a = [\"pear\", \"apple\", \"fig\"]; sort(a); a;"),
+            "[\"apple\", \"fig\", \"pear\"]"
+        );
+        assert_eq!(
+            sorted("fun desc(a, b) { return b - a; } var a = [1, 3, 2]; sort(a, desc); a;"),
+            "[3, 2, 1]"
+        );
+        assert_eq!(
+            sorted("var a = [1, \"1\"]; sort(a);"),
+            "Lists must hold only numbers or only strings to sort without a comparator."
+        );
+        assert_eq!(
+            sorted("fun cmp(a, b) { return nil; } sort([1, 2], cmp);"),
+            "Comparator must return a number."
+        );
+        assert_eq!(
+            sorted("fun cmp(a, b) { return a - b; } sort([1, \"2\"], cmp);"),
+            "operands must be numeric for operation"
+        );
+    }
+
+    #[test]
+    fn conversion_functions() {
+        assert_eq!(eval("num(\" 4.5 \");"), Ok(Object::Number(4.5)));
+        assert_eq!(eval("num(2);"), Ok(Object::Number(2.0)));
+        assert_eq!(eval("num(true) + num(false);"), Ok(Object::Number(1.0)));
+        assert_eq!(
+            eval("str(1.5) + str(nil);"),
+            Ok(Object::String("1.5nil".to_string()))
+        );
+
+        let diagnostics = eval("num(\"abc\");").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Cannot convert 'abc' to a number.");
+    }
+
+    #[test]
+    fn to_fixed_rounds_to_digits() {
+        assert_eq!(
+            eval("toFixed(3.14159, 2);"),
+            Ok(Object::String("3.14".to_string()))
+        );
+        assert_eq!(
+            eval("toFixed(2.5, 0) + \" \" + toFixed(-1, 3);"),
+            Ok(Object::String("2 -1.000".to_string()))
+        );
+        // Only the returned string is rounded
+        assert_eq!(
+            eval("toFixed(3.14159, 2); str(3.14159);"),
+            Ok(Object::String("3.14159".to_string()))
+        );
+
+        let diagnostics = eval("toFixed(1, -1);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Digits must be between 0 and 100.");
+        let diagnostics = eval("toFixed(1, 1.5);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be an integer.");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trips_data_values() {
+        // Lox strings can't hold quotes, the JSON comes from the host
+        let json = r#"{"a":[1.0,2.5,"x"],"b":{"c":null},"d":true}"#;
+        let source = "
+            var value = jsonParse(json);
+            print value[\"a\"][1] + len(value);
+            print jsonParse(jsonStringify(value)) == value;
+            print jsonStringify(value) == json;
+        ";
+        let output = run_with(source, |interpreter| {
+            interpreter.define_global("json", Object::String(json.to_string()))
+        });
+        assert_eq!(output, "5.5\ntrue\ntrue\n");
+
+        let diagnostics = eval("jsonParse(\"[1,\");").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Invalid JSON: EOF while parsing a value at line 1 column 3."
+        );
+
+        let diagnostics = eval("jsonStringify([clock]);").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Cannot convert to JSON: cannot serialize function."
+        );
+    }
+
+    #[test]
+    fn read_line_uses_configured_reader() {
+        let output = run_with(
+            "print readLine(); print readLine(); print readLine();",
+            |interpreter| interpreter.set_reader("hello\r\nworld".as_bytes()),
+        );
+        assert_eq!(output, "hello\nworld\nnil\n");
+    }
+
+    #[test]
+    fn files_are_read_and_written() {
+        let dir = std::env::temp_dir().join(format!("rlox-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.txt").display().to_string();
+
+        assert_eq!(
+            eval(&format!(
+                "writeFile({:?}, \"a\nb\"); readFile({:?});",
+                path, path
+            )),
+            Ok(Object::String("a\nb".to_string()))
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb");
+
+        let missing = dir.join("missing.txt").display().to_string();
+        let diagnostics = eval(&format!("readFile({:?});", missing)).unwrap_err();
+        assert!(diagnostics[0]
+            .message
+            .starts_with(&format!("Failed to read '{}': ", missing)));
+
+        let diagnostics = eval(&format!("writeFile({:?}, \"\");", dir.display())).unwrap_err();
+        assert!(diagnostics[0].message.starts_with("Failed to write"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filesystem_access_can_be_disabled() {
+        let error = interpret_with("readFile(\"Cargo.toml\");", |interpreter| {
+            interpreter.set_filesystem_access(false)
+        })
+        .unwrap_err();
+        assert_eq!(error.message, "Filesystem access is disabled.");
+    }
+
+    #[test]
+    fn getenv_reads_environment_variables() {
+        std::env::set_var("RLOX_GETENV_TEST", "value");
+
+        assert_eq!(
+            eval("getenv(\"RLOX_GETENV_TEST\");"),
+            Ok(Object::String("value".to_string()))
+        );
+        assert_eq!(eval("getenv(\"RLOX_GETENV_UNSET\");"), Ok(Object::Nil));
+    }
+
+    #[test]
+    fn args_are_the_ones_set_by_the_host() {
+        let output = run_with("print args(); print len(args()[0]);", |interpreter| {
+            interpreter.set_args(vec!["--flag".to_string(), "two".to_string()])
+        });
+        assert_eq!(output, "[\"--flag\", \"two\"]\n6\n");
+        assert_eq!(eval("len(args());"), Ok(Object::Number(0.0)));
+    }
+
+    #[test]
+    fn random_numbers_are_in_range() {
+        let source = "
+            var ok = true;
+            for (var i = 0; i < 1000; i = i + 1) {
+                var r = random();
+                var n = randomInt(-2, 2);
+                ok = ok and r >= 0 and r < 1 and n >= -2 and n <= 2 and n == floor(n);
+            }
+            ok and randomInt(7, 7) == 7;
+        ";
+        assert_eq!(eval(source), Ok(Object::Boolean(true)));
+
+        let diagnostics = eval("randomInt(2, 1);").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Lower bound must not be greater than the upper bound."
+        );
+
+        let diagnostics = eval("randomInt(0, 1.5);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be an integer.");
+    }
+
+    #[test]
+    fn seeding_repeats_random_numbers() {
+        let source = "
+            seed(42);
+            var a = [random(), randomInt(0, 1000000)];
+            seed(42);
+            var b = [random(), randomInt(0, 1000000)];
+            seed(43);
+            var c = [random(), randomInt(0, 1000000)];
+            a == b and a != c;
+        ";
+        assert_eq!(eval(source), Ok(Object::Boolean(true)));
+    }
+
+    #[test]
+    fn format_time_prints_utc_dates() {
+        let format_time = |seconds: &str| match eval(&format!("formatTime({});", seconds)) {
+            Ok(Object::String(time)) => time,
+            Ok(other) => panic!("unexpected result {:?}", other),
+            Err(diagnostics) => diagnostics[0].message.clone(),
+        };
+
+        assert_eq!(format_time("0"), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time("951825909.9"), "2000-02-29T12:05:09Z");
+        assert_eq!(format_time("-1"), "1969-12-31T23:59:59Z");
+        assert_eq!(format_time("253402300799"), "9999-12-31T23:59:59Z");
+        assert_eq!(
+            format_time("253402300800"),
+            "Time must be between the years 0 and 9999."
+        );
+    }
+
+    #[test]
+    fn sleep_waits_and_validates_its_duration() {
+        assert_eq!(
+            eval("var start = now(); sleep(20); now() - start >= 20;"),
+            Ok(Object::Boolean(true))
+        );
+
+        for duration in ["-1", "1 / 0", "0 / 0"] {
+            let diagnostics = eval(&format!("sleep({});", duration)).unwrap_err();
+            assert_eq!(
+                diagnostics[0].message,
+                "Duration must be a non-negative number of milliseconds."
+            );
+        }
+
+        let diagnostics = eval("sleep(\"1\");").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a number.");
+    }
+
+    #[test]
+    fn sleep_stops_when_cancelled() {
+        let mut canceller = None;
+        let start = std::time::Instant::now();
+        let error = interpret_with("sleep(60000);", |interpreter| {
+            let token = interpreter.cancellation_token();
+            canceller = Some(std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                token.store(true, std::sync::atomic::Ordering::Relaxed);
+            }));
+        })
+        .unwrap_err();
+        canceller.unwrap().join().unwrap();
+
+        assert_eq!(error.message, "Interrupted.");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn exit_rejects_invalid_codes() {
+        let message = |source: &str| {
+            interpret_with(source, |interpreter| interpreter.set_process_exit(true))
+                .unwrap_err()
+                .message
+        };
+
+        for code in ["-1", "256", "1.5"] {
+            assert_eq!(
+                message(&format!("exit({});", code)),
+                "Exit code must be an integer between 0 and 255."
+            );
+        }
+        assert_eq!(message("exit(\"1\");"), "Argument must be a number.");
+    }
+
+    #[test]
+    fn exit_is_disabled_unless_the_host_allows_it() {
+        let diagnostics = eval("exit(0);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Exiting the process is disabled.");
+    }
+
+    #[test]
+    fn assertions() {
+        assert_eq!(eval("assert(1 < 2); assert_eq(1 + 1, 2);"), Ok(Object::Nil));
+
+        let diagnostics = eval("assert(nil);").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Assertion failed.");
+
+        let diagnostics = eval("assert_eq(1, \"1\");").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Assertion failed: Number(1.0) != String(\"1\")."
+        );
+    }
+
+    #[test]
+    fn typeof_names_runtime_types() {
+        let type_of = |source: &str| match eval(source) {
+            Ok(Object::String(name)) => name,
+            other => panic!("unexpected result {:?}", other),
+        };
+
+        assert_eq!(type_of("typeof(1);"), "number");
+        assert_eq!(type_of("typeof(\"a\");"), "string");
+        assert_eq!(type_of("typeof(true);"), "boolean");
+        assert_eq!(type_of("typeof(nil);"), "nil");
+        assert_eq!(type_of("typeof(clock);"), "function");
+        assert_eq!(type_of("fun f() {} typeof(f);"), "function");
+    }
+
+    #[test]
+    fn math_functions_reject_non_numbers() {
+        let diagnostics = eval("sqrt(\"four\");").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Argument must be a number.");
+    }
+}
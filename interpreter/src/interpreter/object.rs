@@ -1,65 +1,954 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Display};
+use std::ops::Range;
+use std::rc::Rc;
 
 use super::function::Function;
+use crate::error::RuntimeError;
 
+// Identifies a list or map for the deep operations, like printing and
+// equality, which keep track of the ones they visit to stop at cycles
+fn pointer<T>(rc: &Rc<T>) -> *const () {
+    Rc::as_ptr(rc) as *const ()
+}
+
+/// The largest integer that no other integer rounds to as a number: 2^53 + 1
+/// rounds to 2^53, so 2^53 itself isn't safe.
+pub(crate) const MAX_SAFE_INTEGER: f64 = ((1u64 << 53) - 1) as f64;
+
+/// `number` as an integer, when it is a safe one, see `MAX_SAFE_INTEGER`.
+pub(crate) fn safe_integer(number: f64) -> Option<i64> {
+    (number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER).then_some(number as i64)
+}
+
+/// Lists and maps are shared references: assigning one, or passing it to a
+/// function, doesn't copy it, so changes made through any of the references
+/// are seen by all of them. The `copy` native makes an independent copy, see
+/// `Object::deep_clone`.
 #[derive(Debug, Clone)]
 pub enum Object {
     Number(f64),
     String(String),
     Boolean(bool),
     Callable(Function),
+    List(Rc<RefCell<Vec<Object>>>),
+    /// Maps have string keys, kept sorted.
+    Map(Rc<RefCell<BTreeMap<String, Object>>>),
     Nil,
 }
 
-impl From<Object> for bool {
-    fn from(object: Object) -> Self {
+impl Object {
+    pub fn list(elements: Vec<Object>) -> Self {
+        Object::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn map(entries: BTreeMap<String, Object>) -> Self {
+        Object::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Number(_) => "number",
+            Object::String(_) => "string",
+            Object::Boolean(_) => "boolean",
+            Object::Callable(_) => "function",
+            Object::List(_) => "list",
+            Object::Map(_) => "map",
+            Object::Nil => "nil",
+        }
+    }
+
+    /// Converts a number used as an index or a count, failing instead of
+    /// truncating when it isn't a non-negative integer that fits in a `usize`.
+    /// Like other native errors, the error gets its location where it's raised.
+    pub fn to_index(&self) -> Result<usize, RuntimeError> {
+        let number = match self {
+            Object::Number(number) => *number,
+            _ => return Err(RuntimeError::native("Index must be a number.")),
+        };
+
+        if !number.is_finite() || number.fract() != 0.0 {
+            return Err(RuntimeError::native("Index must be an integer."));
+        }
+        if number < 0.0 {
+            return Err(RuntimeError::native("Index must not be negative."));
+        }
+        // usize::MAX as f64 rounds up, so it is already out of range
+        if number >= usize::MAX as f64 {
+            return Err(RuntimeError::native("Index is too large."));
+        }
+
+        Ok(number as usize)
+    }
+
+    /// Converts an index into a list or string of `len` elements. Negative
+    /// indices count from the end, so `-1` is the last element.
+    pub fn to_index_in(&self, len: usize) -> Result<usize, RuntimeError> {
+        let index = self.to_signed_index()?;
+        let index = if index < 0 { index + len as i64 } else { index };
+
+        if index < 0 || index >= len as i64 {
+            return Err(RuntimeError::native("Index out of range."));
+        }
+        Ok(index as usize)
+    }
+
+    /// Converts the bounds of a slice of a list or string of `len` elements,
+    /// `start..end`, or `start..=end` when `inclusive`. Negative bounds count
+    /// from the end like indices do. Bounds past either end are clamped to it
+    /// instead of failing, but a start after the end is an error.
+    pub fn to_slice_in(
+        start: &Object,
+        end: &Object,
+        inclusive: bool,
+        len: usize,
+    ) -> Result<Range<usize>, RuntimeError> {
+        let len = len as i64;
+        let from_end = |index: i64| if index < 0 { index + len } else { index };
+
+        let start = from_end(start.to_signed_index()?);
+        let mut end = from_end(end.to_signed_index()?);
+        if inclusive {
+            end += 1;
+        }
+
+        if start > end {
+            return Err(RuntimeError::native(
+                "Slice start must not be greater than its end.",
+            ));
+        }
+
+        let clamp = |index: i64| index.clamp(0, len) as usize;
+        Ok(clamp(start)..clamp(end))
+    }
+
+    // An integer index, before it's checked against a length
+    fn to_signed_index(&self) -> Result<i64, RuntimeError> {
+        let number = match self {
+            Object::Number(number) => *number,
+            _ => return Err(RuntimeError::native("Index must be a number.")),
+        };
+
+        if !number.is_finite() || number.fract() != 0.0 {
+            return Err(RuntimeError::native("Index must be an integer."));
+        }
+
+        // Saturates far beyond any length
+        Ok(number as i64)
+    }
+
+    /// The integers from `start` up to, but not including, `end`, as a list,
+    /// which is empty when `end` isn't after `start`.
+    pub(crate) fn range(start: f64, end: f64) -> Result<Object, RuntimeError> {
+        if start.fract() != 0.0 || end.fract() != 0.0 {
+            return Err(RuntimeError::native("Range bounds must be integers."));
+        }
+
+        let range = (start as i64..end as i64).map(|i| Object::Number(i as f64));
+        Ok(Object::list(range.collect()))
+    }
+
+    /// Copies lists and maps, and the ones they contain, so the copy shares no
+    /// list or map with the original. A collection that appears several times,
+    /// or contains itself, is copied once and the copy keeps that shape.
+    /// Other values are cloned as usual.
+    pub fn deep_clone(&self) -> Object {
+        self.deep_clone_nested(&mut HashMap::new())
+    }
+
+    // `copies` maps the lists and maps copied so far to their copies
+    fn deep_clone_nested(&self, copies: &mut HashMap<*const (), Object>) -> Object {
+        match self {
+            Object::List(list) => {
+                if let Some(copy) = copies.get(&pointer(list)) {
+                    return copy.clone();
+                }
+
+                let copy = Rc::new(RefCell::new(Vec::with_capacity(list.borrow().len())));
+                copies.insert(pointer(list), Object::List(Rc::clone(&copy)));
+                for element in list.borrow().iter() {
+                    let element = element.deep_clone_nested(copies);
+                    copy.borrow_mut().push(element);
+                }
+                Object::List(copy)
+            }
+            Object::Map(map) => {
+                if let Some(copy) = copies.get(&pointer(map)) {
+                    return copy.clone();
+                }
+
+                let copy = Rc::new(RefCell::new(BTreeMap::new()));
+                copies.insert(pointer(map), Object::Map(Rc::clone(&copy)));
+                for (key, value) in map.borrow().iter() {
+                    let value = value.deep_clone_nested(copies);
+                    copy.borrow_mut().insert(key.clone(), value);
+                }
+                Object::Map(copy)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// `false` and `nil` are falsey, every other value is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Boolean(false) | Object::Nil)
+    }
+
+    /// Whether the value is truthy under `truthiness`.
+    pub fn is_truthy_under(&self, truthiness: Truthiness) -> bool {
+        match (truthiness, self) {
+            (Truthiness::C, Object::Number(number)) => *number != 0.0,
+            (Truthiness::C, Object::String(string)) => !string.is_empty(),
+            _ => self.is_truthy(),
+        }
+    }
+}
+
+/// Which values conditions, `!`, `and`, `or` and `xor` take as false, see
+/// `Interpreter::set_truthiness`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Truthiness {
+    /// Only `false` and `nil` are falsey, as in Lox.
+    #[default]
+    Lox,
+    /// Like C, `0`, `-0` and the empty string are falsey too. Empty lists and
+    /// maps, and NaN, are still truthy.
+    C,
+}
+
+/// How numbers are turned into text by `print`, string concatenation and
+/// `str`, see `Interpreter::set_number_format`. NaN and the infinities print
+/// as `nan`, `inf` and `-inf` in every format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// As many digits as it takes to read the number back exactly, without a
+    /// trailing `.0`.
+    #[default]
+    Shortest,
+    /// Always this many digits after the decimal point, like `toFixed`.
+    Fixed(usize),
+    /// Rounded to this many significant digits, at least one.
+    Significant(usize),
+}
+
+impl NumberFormat {
+    pub fn format(self, number: f64) -> String {
+        if !number.is_finite() {
+            // f64's Display already prints the infinities as `inf` and `-inf`
+            return match number.is_nan() {
+                true => "nan".to_string(),
+                false => number.to_string(),
+            };
+        }
+
+        match self {
+            NumberFormat::Shortest => number.to_string(),
+            NumberFormat::Fixed(digits) => format!("{:.*}", digits, number),
+            NumberFormat::Significant(digits) => {
+                // Rounding in scientific notation gives the exponent of the
+                // rounded number, which can be one more than the original's
+                let scientific = format!("{:.*e}", digits.max(1) - 1, number);
+                let exponent = scientific[scientific.find('e').unwrap() + 1..]
+                    .parse::<i64>()
+                    .unwrap();
+                let rounded = scientific.parse::<f64>().unwrap();
+                let decimals = (digits.max(1) as i64 - 1 - exponent).max(0) as usize;
+                format!("{:.*}", decimals, rounded)
+            }
+        }
+    }
+}
+
+impl From<f64> for Object {
+    fn from(number: f64) -> Self {
+        Object::Number(number)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(boolean: bool) -> Self {
+        Object::Boolean(boolean)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(string: &str) -> Self {
+        Object::String(string.to_string())
+    }
+}
+
+impl From<String> for Object {
+    fn from(string: String) -> Self {
+        Object::String(string)
+    }
+}
+
+impl TryFrom<Object> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
         match object {
-            Object::Boolean(boolean) => boolean,
-            Object::Nil => false,
-            _ => true,
+            Object::Number(number) => Ok(number),
+            _ => Err(RuntimeError::native("Argument must be a number.")),
         }
     }
 }
 
-impl From<&Object> for bool {
-    fn from(object: &Object) -> Self {
+/// Only accepts booleans, use `Object::is_truthy` for Lox's truthiness.
+impl TryFrom<Object> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
         match object {
-            Object::Boolean(boolean) => *boolean,
-            Object::Nil => false,
-            _ => true,
+            Object::Boolean(boolean) => Ok(boolean),
+            _ => Err(RuntimeError::native("Argument must be a boolean.")),
         }
     }
 }
 
-impl From<Object> for String {
-    fn from(object: Object) -> Self {
+/// Only accepts strings, use `to_string` to format any value.
+impl TryFrom<Object> for String {
+    type Error = RuntimeError;
+
+    fn try_from(object: Object) -> Result<Self, Self::Error> {
         match object {
-            Object::Number(number) => number.to_string(),
-            Object::Boolean(boolean) => boolean.to_string(),
-            Object::String(string) => string,
-            Object::Nil => "nil".to_string(),
-            Object::Callable(_fn) => "<native fn>".to_string(),
+            Object::String(string) => Ok(string),
+            _ => Err(RuntimeError::native("Argument must be a string.")),
         }
     }
 }
 
+/// The equality of `==` and `!=`. It never converts between types, so values
+/// of different types are never equal: `0 == false`, `nil == false` and
+/// `"1" == 1` are all false. Because of that, Lox has no separate strict
+/// equality operator.
+///
+/// Numbers follow IEEE 754, so `nan == nan` is false; `is_nan` detects it.
+/// Functions are only equal to themselves. Lists and maps are equal when
+/// their elements are, which holds for ones that contain themselves in the
+/// same places.
 impl PartialEq for Object {
     fn eq(&self, other: &Self) -> bool {
+        self.eq_nested(other, &mut HashSet::new())
+    }
+}
+
+impl Object {
+    // `compared` holds the pairs of lists and maps already being compared,
+    // which are taken to be equal when a cycle leads back to them: if they
+    // aren't, the comparison that started first finds out
+    fn eq_nested(&self, other: &Self, compared: &mut HashSet<(*const (), *const ())>) -> bool {
         match (self, other) {
             (Object::Number(l), Object::Number(r)) => l == r,
             (Object::String(l), Object::String(r)) => l == r,
             (Object::Boolean(l), Object::Boolean(r)) => l == r,
+            (Object::Callable(l), Object::Callable(r)) => l == r,
+            (Object::List(l), Object::List(r)) => {
+                if Rc::ptr_eq(l, r) || !compared.insert((pointer(l), pointer(r))) {
+                    return true;
+                }
+
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(l, r)| l.eq_nested(r, compared))
+            }
+            (Object::Map(l), Object::Map(r)) => {
+                if Rc::ptr_eq(l, r) || !compared.insert((pointer(l), pointer(r))) {
+                    return true;
+                }
+
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|((lk, lv), (rk, rv))| lk == rk && lv.eq_nested(rv, compared))
+            }
             (Object::Nil, Object::Nil) => true,
             _ => false,
         }
     }
 }
 
+/// Formats values the way `print` shows them, like the reference Lox
+/// implementations: strings without quotes, and numbers with as many digits
+/// as it takes to read them back exactly, without a trailing `.0`, so
+/// `0.1 + 0.2` is `0.30000000000000004` and `-0` keeps its sign. Infinities
+/// and NaN print as `inf`, `-inf` and `nan`, the globals that hold them, so
+/// every number prints as source that evaluates back to it.
+///
+/// Lists and maps print like `[1, "two", nil]` and `{"a": 1}`, quoting the
+/// strings they contain. A list or map that contains itself prints as `[...]`
+/// or `{...}` where it appears again.
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        self.display_under(NumberFormat::Shortest).fmt(f)
+    }
+}
+
+impl Object {
+    /// Formats like `Display`, but with its numbers, including the ones in
+    /// lists and maps, in `numbers`.
+    pub fn display_under(&self, numbers: NumberFormat) -> impl Display + '_ {
+        Displayed {
+            object: self,
+            numbers,
+        }
+    }
+}
+
+struct Displayed<'a> {
+    object: &'a Object,
+    numbers: NumberFormat,
+}
+
+impl Display for Displayed<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.object {
+            Object::Number(number) => write!(f, "{}", self.numbers.format(*number)),
+            Object::String(string) => write!(f, "{}", string),
+            Object::Boolean(boolean) => write!(f, "{}", boolean),
             Object::Callable(ref fun) => write!(f, "{}", fun),
-            _ => write!(f, "{:?}", self),
+            Object::List(_) | Object::Map(_) => {
+                self.object.fmt_nested(f, self.numbers, &mut HashSet::new())
+            }
+            Object::Nil => write!(f, "nil"),
         }
     }
 }
+
+impl Object {
+    // `open` holds the lists and maps being printed around this value
+    fn fmt_nested(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        numbers: NumberFormat,
+        open: &mut HashSet<*const ()>,
+    ) -> std::fmt::Result {
+        match self {
+            Object::String(string) => write!(f, "\"{}\"", string),
+            Object::List(list) => {
+                if !open.insert(pointer(list)) {
+                    return write!(f, "[...]");
+                }
+
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.fmt_nested(f, numbers, open)?;
+                }
+                open.remove(&pointer(list));
+                write!(f, "]")
+            }
+            Object::Map(map) => {
+                if !open.insert(pointer(map)) {
+                    return write!(f, "{{...}}");
+                }
+
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "\"{}\": ", key)?;
+                    value.fmt_nested(f, numbers, open)?;
+                }
+                open.remove(&pointer(map));
+                write!(f, "}}")
+            }
+            _ => write!(f, "{}", self.display_under(numbers)),
+        }
+    }
+}
+
+/// Data values serialize as their plain JSON counterparts: numbers, strings,
+/// booleans, `null` for nil, arrays for lists and objects for maps. Functions,
+/// and lists or maps that contain themselves, can't be serialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Object {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let open = RefCell::new(HashSet::new());
+        Nested {
+            object: self,
+            open: &open,
+        }
+        .serialize(serializer)
+    }
+}
+
+// An object inside the lists and maps in `open`, which are being serialized
+#[cfg(feature = "serde")]
+struct Nested<'a> {
+    object: &'a Object,
+    open: &'a RefCell<HashSet<*const ()>>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Nested<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::Error;
+
+        match self.object {
+            Object::Number(number) => serializer.serialize_f64(*number),
+            Object::String(string) => serializer.serialize_str(string),
+            Object::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            Object::Callable(_) => Err(S::Error::custom("cannot serialize function")),
+            Object::List(list) => self.enter(pointer(list), || {
+                let open = self.open;
+                serializer.collect_seq(list.borrow().iter().map(|object| Nested { object, open }))
+            }),
+            Object::Map(map) => self.enter(pointer(map), || {
+                let open = self.open;
+                serializer.collect_map(
+                    map.borrow()
+                        .iter()
+                        .map(|(key, object)| (key, Nested { object, open })),
+                )
+            }),
+            Object::Nil => serializer.serialize_unit(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Nested<'_> {
+    fn enter<T, E>(
+        &self,
+        collection: *const (),
+        serialize: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E>
+    where
+        E: serde::ser::Error,
+    {
+        if !self.open.borrow_mut().insert(collection) {
+            return Err(E::custom("cannot serialize a cycle"));
+        }
+
+        let result = serialize();
+        self.open.borrow_mut().remove(&collection);
+        result
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Object {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ObjectVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ObjectVisitor {
+            type Value = Object;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a number, string, boolean, null, array or object")
+            }
+
+            fn visit_bool<E>(self, value: bool) -> Result<Object, E> {
+                Ok(Object::Boolean(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<Object, E> {
+                Ok(Object::Number(value as f64))
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<Object, E> {
+                Ok(Object::Number(value as f64))
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<Object, E> {
+                Ok(Object::Number(value))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Object, E> {
+                Ok(Object::String(value.to_string()))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Object, E> {
+                Ok(Object::String(value))
+            }
+
+            fn visit_unit<E>(self) -> Result<Object, E> {
+                Ok(Object::Nil)
+            }
+
+            fn visit_none<E>(self) -> Result<Object, E> {
+                Ok(Object::Nil)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Object, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut list = Vec::new();
+                while let Some(element) = seq.next_element()? {
+                    list.push(element);
+                }
+                Ok(Object::list(list))
+            }
+
+            fn visit_map<A>(self, mut entries: A) -> Result<Object, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut map = BTreeMap::new();
+                while let Some((key, value)) = entries.next_entry()? {
+                    map.insert(key, value);
+                }
+                Ok(Object::map(map))
+            }
+        }
+
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::RuntimeError;
+    use crate::eval;
+    use crate::interpreter::{NumberFormat, Object, Truthiness};
+    #[cfg(feature = "serde")]
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn functions_stringify_with_their_names() {
+        let string = |s: &str| Ok(Object::String(s.to_string()));
+
+        assert_eq!(eval("fun greet() {} str(greet);"), string("<fn greet>"));
+        assert_eq!(eval("str(clock);"), string("<native fn clock>"));
+
+        let function = eval("fun greet() {} greet;").unwrap();
+        assert_eq!(function.to_string(), "<fn greet>");
+    }
+
+    #[test]
+    fn index_conversion_rejects_lossy_numbers() {
+        assert_eq!(Object::Number(3.0).to_index().unwrap(), 3);
+        assert_eq!(Object::Number(-0.0).to_index().unwrap(), 0);
+
+        let message = |object: Object| object.to_index().unwrap_err().message;
+        assert_eq!(message(Object::Number(1.5)), "Index must be an integer.");
+        assert_eq!(
+            message(Object::Number(f64::NAN)),
+            "Index must be an integer."
+        );
+        assert_eq!(
+            message(Object::Number(f64::INFINITY)),
+            "Index must be an integer."
+        );
+        assert_eq!(message(Object::Number(-1.0)), "Index must not be negative.");
+        assert_eq!(message(Object::Number(1e20)), "Index is too large.");
+        assert_eq!(message(Object::Nil), "Index must be a number.");
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let index = |i: f64| Object::Number(i).to_index_in(3);
+        assert_eq!(index(0.0).unwrap(), 0);
+        assert_eq!(index(-1.0).unwrap(), 2);
+        assert_eq!(index(-3.0).unwrap(), 0);
+        assert_eq!(index(3.0).unwrap_err().message, "Index out of range.");
+        assert_eq!(index(-4.0).unwrap_err().message, "Index out of range.");
+        assert_eq!(index(0.5).unwrap_err().message, "Index must be an integer.");
+
+        let slice = |start: f64, end: f64, inclusive: bool| {
+            Object::to_slice_in(&Object::Number(start), &Object::Number(end), inclusive, 3)
+        };
+        assert_eq!(slice(1.0, 3.0, false).unwrap(), 1..3);
+        assert_eq!(slice(1.0, 1.0, true).unwrap(), 1..2);
+        assert_eq!(slice(0.0, -1.0, false).unwrap(), 0..2);
+        assert_eq!(slice(-10.0, 10.0, false).unwrap(), 0..3);
+        assert_eq!(slice(5.0, 10.0, false).unwrap(), 3..3);
+        assert_eq!(
+            slice(2.0, 1.0, false).unwrap_err().message,
+            "Slice start must not be greater than its end."
+        );
+        assert_eq!(
+            slice(-1.0, 0.0, false).unwrap_err().message,
+            "Slice start must not be greater than its end."
+        );
+    }
+
+    #[test]
+    fn equality_rules() {
+        let is = |source: &str| match eval(source) {
+            Ok(Object::Boolean(result)) => result,
+            other => panic!("unexpected result {:?}", other),
+        };
+
+        assert!(is("1 == 1.0;"));
+        assert!(is("\"a\" == \"a\";"));
+        assert!(is("nil == nil;"));
+        assert!(is("true != false;"));
+
+        // No conversions between types
+        assert!(!is("0 == false;"));
+        assert!(!is("nil == false;"));
+        assert!(!is("\"1\" == 1;"));
+        assert!(!is("\"\" == nil;"));
+
+        assert!(!is("var nan = 0 / 0; nan == nan;"));
+        assert!(is("var nan = 0 / 0; nan != nan;"));
+        assert!(is("is_nan(0 / 0);"));
+        assert!(!is("is_nan(1);"));
+
+        assert!(is("fun f() {} f == f;"));
+        assert!(is("clock == clock;"));
+        assert!(!is("fun f() {} fun g() {} f == g;"));
+        assert!(!is("fun make() { fun f() {} return f; } make() == make();"));
+    }
+
+    #[test]
+    fn numbers_print_like_reference_lox() {
+        let cases = [
+            (0.1 + 0.2, "0.30000000000000004"),
+            (3.0, "3"),
+            (-0.001, "-0.001"),
+            (123.456, "123.456"),
+            (-0.0, "-0"),
+            (1e21, "1000000000000000000000"),
+            (1e-7, "0.0000001"),
+            (f64::MAX, &format!("179769313486231570{}", "0".repeat(291))),
+            (5e-324, &format!("0.{}5", "0".repeat(323))),
+        ];
+
+        for (number, expected) in cases {
+            assert_eq!(Object::Number(number).to_string(), expected);
+        }
+
+        assert_eq!(Object::String("a b".to_string()).to_string(), "a b");
+        assert_eq!(Object::Number(f64::INFINITY).to_string(), "inf");
+        assert_eq!(Object::Number(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(Object::Number(f64::NAN).to_string(), "nan");
+        assert_eq!(Object::Number(-f64::NAN).to_string(), "nan");
+        assert_eq!(Object::Boolean(true).to_string(), "true");
+        assert_eq!(Object::Nil.to_string(), "nil");
+    }
+
+    #[test]
+    fn special_numbers_print_as_source_for_themselves() {
+        for source in ["1 / 0", "-1 / 0", "0 / 0", "-0", "0.1 + 0.2"] {
+            let value = eval(&format!("{};", source)).unwrap();
+            let printed = value.to_string();
+
+            match eval(&format!("{};", printed)).unwrap() {
+                Object::Number(number) if number.is_nan() => assert_eq!(printed, "nan"),
+                Object::Number(number) => {
+                    let Object::Number(original) = value else {
+                        unreachable!()
+                    };
+                    assert_eq!(number.to_bits(), original.to_bits(), "{}", printed);
+                }
+                other => panic!("{} evaluated to {:?}", printed, other),
+            }
+        }
+
+        assert_eq!(
+            eval("str([1 / 0, 0 / 0]);"),
+            Ok(Object::String("[inf, nan]".to_string()))
+        );
+    }
+
+    #[test]
+    fn number_formats() {
+        let cases = [
+            (NumberFormat::Shortest, 0.1 + 0.2, "0.30000000000000004"),
+            (NumberFormat::Shortest, -0.0, "-0"),
+            (NumberFormat::Fixed(2), 1.23456, "1.23"),
+            (NumberFormat::Fixed(0), 1234.0, "1234"),
+            (NumberFormat::Significant(2), 9.99, "10"),
+            (NumberFormat::Significant(2), 123456.0, "120000"),
+            (NumberFormat::Significant(3), 0.000123456, "0.000123"),
+            (NumberFormat::Significant(3), 0.0, "0.00"),
+            (NumberFormat::Significant(0), 7.6, "8"),
+            (NumberFormat::Fixed(2), f64::NEG_INFINITY, "-inf"),
+            (NumberFormat::Significant(2), f64::NAN, "nan"),
+        ];
+        for (format, number, expected) in cases {
+            assert_eq!(format.format(number), expected, "{:?}", format);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn data_values_round_trip_through_json() {
+        let values = [
+            (
+                Object::list(vec![Object::Number(1.0), Object::Nil]),
+                "[1.0,null]",
+            ),
+            (
+                Object::map(BTreeMap::from([("a".to_string(), Object::list(vec![]))])),
+                r#"{"a":[]}"#,
+            ),
+            (Object::Number(1.5), "1.5"),
+            (Object::String("a \"b\"".to_string()), r#""a \"b\"""#),
+            (Object::Boolean(false), "false"),
+            (Object::Nil, "null"),
+        ];
+
+        for (value, json) in values {
+            assert_eq!(serde_json::to_string(&value).unwrap(), json);
+            assert_eq!(serde_json::from_str::<Object>(json).unwrap(), value);
+        }
+
+        assert_eq!(
+            serde_json::from_str::<Object>("3").unwrap(),
+            Object::Number(3.0)
+        );
+
+        let function = eval("fun f() {} f;").unwrap();
+        let error = serde_json::to_string(&function).unwrap_err();
+        assert_eq!(error.to_string(), "cannot serialize function");
+
+        let cycle = eval("var a = [1]; push(a, [a]); a;").unwrap();
+        let error = serde_json::to_string(&cycle).unwrap_err();
+        assert_eq!(error.to_string(), "cannot serialize a cycle");
+        let shared = eval("var a = [1]; [a, a];").unwrap();
+        assert_eq!(serde_json::to_string(&shared).unwrap(), "[[1.0],[1.0]]");
+    }
+
+    #[test]
+    fn converts_to_and_from_rust_types() {
+        assert_eq!(Object::from(3.0), Object::Number(3.0));
+        assert_eq!(Object::from(true), Object::Boolean(true));
+        assert_eq!(Object::from("a"), Object::String("a".to_string()));
+        assert_eq!(
+            Object::from("a".to_string()),
+            Object::String("a".to_string())
+        );
+
+        assert_eq!(f64::try_from(Object::from(3.0)).unwrap(), 3.0);
+        assert!(bool::try_from(Object::from(true)).unwrap());
+        assert_eq!(String::try_from(Object::from("a")).unwrap(), "a");
+
+        let message = |error: RuntimeError| error.message;
+        assert_eq!(
+            message(f64::try_from(Object::from("3")).unwrap_err()),
+            "Argument must be a number."
+        );
+        assert_eq!(
+            message(bool::try_from(Object::Nil).unwrap_err()),
+            "Argument must be a boolean."
+        );
+        assert_eq!(
+            message(String::try_from(Object::from(3.0)).unwrap_err()),
+            "Argument must be a string."
+        );
+    }
+
+    #[test]
+    fn only_false_and_nil_are_falsey() {
+        assert!(!Object::Boolean(false).is_truthy());
+        assert!(!Object::Nil.is_truthy());
+        assert!(Object::Boolean(true).is_truthy());
+        assert!(Object::Number(0.0).is_truthy());
+        assert!(Object::String(String::new()).is_truthy());
+
+        let c_truthy = |object: Object| object.is_truthy_under(Truthiness::C);
+        assert!(!c_truthy(Object::Number(0.0)));
+        assert!(!c_truthy(Object::Number(-0.0)));
+        assert!(!c_truthy(Object::String(String::new())));
+        assert!(!c_truthy(Object::Nil));
+        assert!(c_truthy(Object::Number(f64::NAN)));
+        assert!(c_truthy(Object::String("0".to_string())));
+        assert!(c_truthy(Object::list(vec![])));
+    }
+
+    #[test]
+    fn collections_print_nested_values_quoted() {
+        let printed = |source: &str| eval(source).unwrap().to_string();
+
+        assert_eq!(printed("[1, \"two\", nil];"), "[1, \"two\", nil]");
+        assert_eq!(printed("({\"a\": 1});"), "{\"a\": 1}");
+        assert_eq!(printed("[];"), "[]");
+        assert_eq!(printed("({});"), "{}");
+        assert_eq!(
+            printed("[[1, [\"a\"]], {\"b\": {\"c\": [true]}, \"a\": clock}];"),
+            "[[1, [\"a\"]], {\"a\": <native fn clock>, \"b\": {\"c\": [true]}}]"
+        );
+        // Only strings inside collections are quoted
+        assert_eq!(printed("\"two\";"), "two");
+    }
+
+    #[test]
+    fn self_referencing_collections_print() {
+        let printed = |source: &str| eval(source).unwrap().to_string();
+
+        assert_eq!(printed("var a = [1]; push(a, a); a;"), "[1, [...]]");
+        assert_eq!(
+            printed("var m = {}; m[\"self\"] = m; m[\"list\"] = [m]; m;"),
+            "{\"list\": [{...}], \"self\": {...}}"
+        );
+        // The same list twice, side by side, isn't a cycle
+        assert_eq!(printed("var a = [1]; [a, a];"), "[[1], [1]]");
+    }
+
+    #[test]
+    fn self_referencing_collections_compare() {
+        let is = |source: &str| match eval(source) {
+            Ok(Object::Boolean(result)) => result,
+            other => panic!("unexpected result {:?}", other),
+        };
+        let cycles = "
+            var a = [1]; push(a, a);
+            var b = [1]; push(b, b);
+            var c = [2]; push(c, c);
+            var m = {}; m[\"m\"] = m;
+            var n = {}; n[\"m\"] = n;
+        ";
+
+        assert!(is(&format!("{} a == a;", cycles)));
+        assert!(is(&format!("{} a == b;", cycles)));
+        assert!(!is(&format!("{} a == c;", cycles)));
+        assert!(is(&format!("{} [a, b] == [b, a];", cycles)));
+        assert!(is(&format!("{} m == n;", cycles)));
+        assert!(!is(&format!("{} m == {{\"m\": 1}};", cycles)));
+        assert!(is(&format!("{} str(a) == \"[1, [...]]\";", cycles)));
+    }
+
+    #[test]
+    fn copy_breaks_aliasing() {
+        let printed = |source: &str| eval(source).unwrap().to_string();
+
+        assert_eq!(
+            printed("var a = [1, {\"k\": [2]}]; var b = a; b[0] = 0; a;"),
+            "[0, {\"k\": [2]}]"
+        );
+        assert_eq!(
+            printed("var a = [1, {\"k\": [2]}]; var b = copy(a); b[0] = 0; b[1][\"k\"][0] = 0; a;"),
+            "[1, {\"k\": [2]}]"
+        );
+        assert_eq!(printed("copy(\"s\");"), "s");
+
+        // Shared and self-referencing lists keep their shape
+        let copy = eval("var a = [1]; push(a, a); var b = copy(a); b[0] = 2; b;").unwrap();
+        assert_eq!(copy.to_string(), "[2, [...]]");
+        let Object::List(list) = &copy else {
+            panic!("not a list: {:?}", copy);
+        };
+        assert!(matches!(&list.borrow()[1], Object::List(inner) if Rc::ptr_eq(inner, list)));
+
+        let copy = eval("var a = [1]; copy([a, a]);").unwrap();
+        let Object::List(list) = &copy else {
+            panic!("not a list: {:?}", copy);
+        };
+        let list = list.borrow();
+        assert!(
+            matches!((&list[0], &list[1]), (Object::List(l), Object::List(r)) if Rc::ptr_eq(l, r))
+        );
+    }
+}
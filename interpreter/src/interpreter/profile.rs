@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// What a profiling interpreter measured, see `Interpreter::with_profiling`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    /// How many times each kind of node, as named by `Expr::kind` and
+    /// `Stmt::kind`, was evaluated or executed.
+    pub node_counts: HashMap<&'static str, usize>,
+    /// How many functions were called, natives included.
+    pub calls: usize,
+    /// Total time spent in calls. A call's time includes the calls it makes,
+    /// so recursive functions are counted more than once.
+    pub call_time: Duration,
+}
+
+impl Profile {
+    /// Node kinds and their counts, the most frequent first.
+    pub fn hottest(&self) -> Vec<(&'static str, usize)> {
+        let mut counts = self
+            .node_counts
+            .iter()
+            .map(|(&kind, &count)| (kind, count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|(l_kind, l), (r_kind, r)| r.cmp(l).then(l_kind.cmp(r_kind)));
+        counts
+    }
+
+    pub(crate) fn record(&mut self, kind: &'static str) {
+        *self.node_counts.entry(kind).or_insert(0) += 1;
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} calls in {:.3?}", self.calls, self.call_time)?;
+        for (kind, count) in self.hottest() {
+            writeln!(f, "{:>12}  {}", count, kind)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+    use std::io;
+
+    #[test]
+    fn counts_nodes_and_calls() {
+        let source = "fun f(n) { return n; } var i = 0; while (i < 3) i = f(i) + 1;";
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        let mut interpreter = Interpreter::with_profiling(io::sink());
+        Resolver::new(&mut interpreter).resolve(&statements);
+        interpreter.interpret(statements).unwrap();
+
+        let profile = interpreter.profile().unwrap();
+        assert_eq!(profile.calls, 3);
+        assert_eq!(profile.node_counts["While"], 1);
+        assert_eq!(profile.node_counts["Return"], 3);
+        assert_eq!(
+            profile.hottest()[..3],
+            [("Variable", 13), ("Literal", 8), ("Binary", 7)]
+        );
+
+        let mut interpreter = Interpreter::with_writer(io::sink());
+        interpreter.interpret(vec![]).unwrap();
+        assert_eq!(interpreter.profile(), None);
+    }
+}
@@ -0,0 +1,223 @@
+//! A tree-walking interpreter for Lox.
+//!
+//! `eval` runs a program and returns the value of its last expression:
+//!
+//! ```
+//! let value = rlox::eval("print 1 + 1; var a = 2; a * 3;").unwrap();
+//! assert_eq!(value, rlox::Object::Number(6.0));
+//!
+//! let diagnostics = rlox::eval("print nope;").unwrap_err();
+//! assert_eq!(diagnostics[0].message, "Undefined variable 'nope'.");
+//! ```
+//!
+//! Hosts that need more control run the stages themselves, sharing an
+//! `ErrorReporter`: a `Scanner` turns source into `Token`s, a `Parser` turns
+//! them into statements, the `Resolver` binds their variables for the
+//! `Interpreter`, which runs them.
+//!
+//! ```
+//! use rlox::{ErrorReporter, Interpreter, Parser, Resolver, Scanner};
+//!
+//! let mut reporter = ErrorReporter::new();
+//! reporter.set_print(false);
+//! let mut out = Vec::new();
+//! let mut interpreter = Interpreter::with_writer(&mut out);
+//! interpreter.set_error_reporter(&reporter);
+//!
+//! let mut scanner = Scanner::new("print \"hi\";");
+//! scanner.set_error_reporter(&reporter);
+//! let mut parser = Parser::new(scanner.scan_tokens());
+//! parser.set_error_reporter(&reporter);
+//! let statements = parser.parse();
+//! let mut resolver = Resolver::new(&mut interpreter);
+//! resolver.set_error_reporter(&reporter);
+//! resolver.resolve(&statements);
+//! interpreter.run(statements);
+//! drop(interpreter);
+//!
+//! assert!(reporter.diagnostics().is_empty());
+//! assert_eq!(out, b"hi\n");
+//! ```
+
+#![allow(clippy::result_large_err)]
+
+pub mod ast;
+pub mod error;
+pub mod interpreter;
+pub mod optimizer;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::token::Token;
+pub use error::{Diagnostic, ErrorReporter, RuntimeError, Severity};
+pub use interpreter::{Interpreter, Object};
+pub use parser::Parser;
+pub use resolver::Resolver;
+pub use scanner::Scanner;
+
+use optimizer::Optimizer;
+use parser::Formatter;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Scans, parses, optimizes, resolves and interprets `source`, returning the value of the last
+/// statement when it is an expression statement, or `nil` otherwise.
+///
+/// Errors are collected, without printing them, and returned instead of
+/// terminating the process. So are panics, as an "Internal interpreter
+/// error.", see `Interpreter::interpret`.
+pub fn eval(source: &str) -> Result<Object, Vec<Diagnostic>> {
+    let mut reporter = ErrorReporter::new();
+    reporter.set_print(false);
+
+    // `interpret` already catches panics while running, this also covers
+    // scanning, parsing and resolving
+    match panic::catch_unwind(AssertUnwindSafe(|| eval_with(source, &reporter))) {
+        Ok(result) => result,
+        Err(payload) => {
+            reporter.runtime_error(&RuntimeError::from_panic(payload));
+            Err(reporter.diagnostics())
+        }
+    }
+}
+
+fn eval_with(source: &str, reporter: &ErrorReporter) -> Result<Object, Vec<Diagnostic>> {
+    let mut interpreter = Interpreter::new();
+
+    let mut scanner = Scanner::new(source);
+    scanner.set_error_reporter(reporter);
+    scanner.set_interner(interpreter.interner());
+    let tokens = scanner.scan_tokens();
+
+    if reporter.has_error() {
+        return Err(reporter.diagnostics());
+    }
+
+    let mut parser = Parser::new(tokens);
+    parser.set_error_reporter(reporter);
+    let mut statements = parser.parse();
+
+    if reporter.has_error() {
+        return Err(reporter.diagnostics());
+    }
+
+    Optimizer::new().optimize(&mut statements);
+
+    let mut resolver = Resolver::new(&mut interpreter);
+    resolver.set_error_reporter(reporter);
+    resolver.resolve(&statements);
+
+    if reporter.has_error() {
+        return Err(reporter.diagnostics());
+    }
+
+    match interpreter.interpret(statements) {
+        Ok(value) => Ok(value.unwrap_or(Object::Nil)),
+        Err(e) => {
+            reporter.runtime_error(&e);
+            Err(reporter.diagnostics())
+        }
+    }
+}
+
+/// Formats `source` as canonical Lox, see `Formatter`, indented by four
+/// spaces and keeping its comments. Returns the scanning and parsing errors
+/// instead when there are any.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut reporter = ErrorReporter::new();
+    reporter.set_print(false);
+
+    let mut scanner = Scanner::new(source);
+    scanner.set_error_reporter(&reporter);
+    scanner.set_preserve_comments(true);
+    let tokens = scanner.scan_tokens();
+
+    let mut formatter = Formatter::new();
+    formatter.set_comments(&tokens);
+
+    let mut parser = Parser::new(tokens);
+    parser.set_error_reporter(&reporter);
+    let statements = parser.parse();
+
+    if reporter.has_error() {
+        return Err(reporter.diagnostics());
+    }
+
+    Ok(formatter.format_stmts(&statements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Severity;
+
+    #[test]
+    fn eval_returns_last_expression_value() {
+        let value = eval("var a = 1; a + 2;").unwrap();
+        assert_eq!(value, Object::Number(3.0));
+    }
+
+    #[test]
+    fn eval_collects_errors() {
+        let diagnostics = eval("var a = ;").unwrap_err();
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                line: 1,
+                column: 9,
+                message: "Expected expression.".to_string(),
+                severity: Severity::Error,
+                span: None,
+                place: " at ';'".to_string(),
+            }]
+        );
+
+        let diagnostics = eval("print b;").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Undefined variable 'b'.");
+    }
+
+    #[test]
+    fn chained_comparisons_are_rejected() {
+        let diagnostics = eval("var x = 5; print 1 < x < 10;").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Chained comparison is not allowed; use 'and'."
+        );
+        assert_eq!(diagnostics[0].column, 24);
+
+        assert_eq!(
+            eval("var x = 5; 1 < x and x < 10;"),
+            Ok(Object::Boolean(true))
+        );
+        assert_eq!(eval("(1 < 2) == true;"), Ok(Object::Boolean(true)));
+    }
+
+    #[test]
+    fn errors_carry_the_span_of_their_node() {
+        let source = "var a = 1;\nprint a - (nil);";
+        let diagnostics = eval(source).unwrap_err();
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].span.unwrap().text(source), "a - (nil)");
+
+        // Native errors are in the call that raised them
+        let source = "print len(1);";
+        let diagnostics = eval(source).unwrap_err();
+        assert_eq!(diagnostics[0].span.unwrap().text(source), "len(1)");
+
+        let source = "{ const a = 1; a = 2; }";
+        let diagnostics = eval(source).unwrap_err();
+        assert_eq!(diagnostics[0].span.unwrap().text(source), "a = 2");
+    }
+
+    #[test]
+    fn format_is_idempotent_and_reports_errors() {
+        let formatted = format("var a=1;{print a;} // a").unwrap();
+        assert_eq!(formatted, "var a = 1;\n{\n    print a;\n} // a\n");
+        assert_eq!(format(&formatted).unwrap(), formatted);
+
+        let diagnostics = format("print (1;").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Expect ')' after expression.");
+    }
+}
@@ -1,29 +1,74 @@
-use std::{env, process};
+use std::io::{self, IsTerminal};
+use std::{env, process, thread};
 
-mod ast;
-mod error;
-mod interpreter;
-mod parser;
 mod runner;
-mod scanner;
 
-use runner::Runner;
+use runner::{Mode, Runner};
+
+const INTERPRETER_STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() {
-    let args = env::args().collect::<Vec<String>>();
+    let mut mode = Mode::Run;
+    let mut auto_semicolons = false;
+    let mut profile = false;
+    let mut warn_shadowing = false;
+    let mut warn_unreachable = false;
+    let mut script_path = None;
+    let mut script_args = Vec::new();
+
+    for arg in env::args().skip(1) {
+        // Everything after the script is for the script, see `args()`
+        if script_path.is_some() {
+            script_args.push(arg);
+            continue;
+        }
 
-    if args.len() > 2 {
-        eprintln!("Usage: rlox [script]");
-        process::exit(64);
+        match arg.as_str() {
+            "--dump-ast" => mode = Mode::DumpAst,
+            "--dump-tokens" => mode = Mode::DumpTokens,
+            "--format" => mode = Mode::Format,
+            "--auto-semicolons" => auto_semicolons = true,
+            "--profile" => profile = true,
+            "--warn-shadowing" => warn_shadowing = true,
+            "--warn-unreachable" => warn_unreachable = true,
+            #[cfg(feature = "serde")]
+            "--dump-json" => mode = Mode::DumpJson,
+            _ if !arg.starts_with("--") => script_path = Some(arg),
+            _ => usage(),
+        }
     }
 
-    let mut runner = Runner::new();
+    // Lox calls recurse on the host stack, give the interpreter enough room to
+    // reach its own call depth limit before the native stack overflows.
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(move || {
+            let mut runner = Runner::new();
+            runner.set_mode(mode);
+            runner.set_auto_semicolons(auto_semicolons);
+            runner.set_profile(profile);
+            runner.set_warn_shadowing(warn_shadowing);
+            runner.set_warn_unreachable(warn_unreachable);
+            runner.set_script_args(script_args);
 
-    let script_path = args.get(1);
+            match script_path {
+                Some(script) if script == "-" => runner.run_stdin(),
+                Some(script) => runner.run_file(&script),
+                // A piped script, as in `cat script.lox | rlox`
+                None if !io::stdin().is_terminal() => runner.run_stdin(),
+                None => runner.run_prompt(),
+            }
+        })
+        .unwrap();
 
-    if let Some(script) = script_path {
-        runner.run_file(script);
-    } else {
-        runner.run_prompt();
+    if interpreter.join().is_err() {
+        process::exit(70);
     }
 }
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: rlox [--dump-ast | --dump-tokens | --format] [--auto-semicolons] [--profile] [--warn-shadowing] [--warn-unreachable] [script | -] [args...]"
+    );
+    process::exit(64);
+}
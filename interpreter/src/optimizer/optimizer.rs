@@ -0,0 +1,584 @@
+use crate::ast::expr::Expr;
+use crate::ast::stmt::Stmt;
+use crate::ast::token::Token;
+use crate::ast::tokentype::{Literal, TokenType};
+use crate::interpreter::{Interpreter, Object};
+use std::{io, rc::Rc};
+
+/// Rewrites a parsed program so that it runs faster, without changing what
+/// it does. It runs before the `Resolver`, which binds the variables of the
+/// rewritten statements.
+///
+/// - Operations on literals are folded into the literal they evaluate to,
+///   `2 * 3` into `6`, unless they fail, so that the error is still raised
+///   when the program runs. `!`, `and`, `or` and `xor` are only folded
+///   on `true`, `false` and `nil`, which are alike under every `Truthiness`,
+///   and numbers aren't concatenated to strings, since how they are written
+///   depends on the interpreter's `NumberFormat`.
+/// - Reads of a local variable declared with a literal, or with an
+///   expression folded into one, are replaced with the literal, which may be
+///   folded in turn. Variables that are assigned, or used by a function
+///   declared in their scope, are left alone, and so are globals, which
+///   programs run later in the same interpreter may assign.
+pub struct Optimizer {
+    // Evaluates the folded operations, exactly as running them would
+    interpreter: Interpreter<'static>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::with_writer(io::sink()),
+        }
+    }
+
+    pub fn optimize(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    // The statements of a local scope, followed by the value of a block
+    // expression
+    fn scope(&mut self, stmts: &mut [Stmt], mut value: Option<&mut Expr>) {
+        for index in 0..stmts.len() {
+            self.stmt(&mut stmts[index]);
+
+            let (declaration, rest) = stmts[index..].split_first_mut().unwrap();
+            let Some((name, literal)) = constant(declaration) else {
+                continue;
+            };
+
+            let changed = rest.iter().any(|stmt| changes(stmt, name))
+                || value
+                    .as_deref()
+                    .is_some_and(|value| changes_in(value, name));
+            if !changed && !substitute_stmts(rest, name, literal) {
+                if let Some(value) = value.as_deref_mut() {
+                    substitute(value, name, literal);
+                }
+            }
+        }
+
+        if let Some(value) = value {
+            self.expr(value);
+        }
+    }
+
+    // Parsed functions aren't shared yet
+    fn function(&mut self, defaults: &mut Rc<[Option<Expr>]>, body: &mut Rc<Stmt>) {
+        if let Some(defaults) = Rc::get_mut(defaults) {
+            for default in defaults.iter_mut().flatten() {
+                self.expr(default);
+            }
+        }
+        if let Some(Stmt::Block(stmts, _)) = Rc::get_mut(body) {
+            self.scope(stmts, None);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Print(expr, _) | Stmt::Expression(expr, _) | Stmt::Return(_, expr, _) => {
+                self.expr(expr)
+            }
+            Stmt::VarDeclaration(_, initializer, _) => {
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+            }
+            Stmt::ConstDeclaration(_, initializer, _) => self.expr(initializer),
+            Stmt::Function(_, _, defaults, body, _) => self.function(defaults, body),
+            Stmt::Block(stmts, _) => self.scope(stmts, None),
+            Stmt::If(expr, stmt_then, stmt_else, _) => {
+                self.expr(expr);
+                self.stmt(stmt_then);
+                if let Some(stmt_else) = stmt_else {
+                    self.stmt(stmt_else);
+                }
+            }
+            Stmt::While(expr, body, increment, ..) => {
+                self.expr(expr);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::ForIn(_, expr, body, ..) => {
+                self.expr(expr);
+                self.stmt(body);
+            }
+            Stmt::Break(..) | Stmt::Continue(..) => (),
+            Stmt::Switch(expr, cases, default, _) => {
+                self.expr(expr);
+                for (value, stmts) in cases {
+                    self.expr(value);
+                    self.scope(stmts, None);
+                }
+                if let Some(stmts) = default {
+                    self.scope(stmts, None);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::BinaryExpr(left, _, right, _) | Expr::LogicalExpr(left, _, right, _) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::GroupingExpr(inner, _) | Expr::UnaryExpr(_, inner, _) => self.expr(inner),
+            Expr::AssignExpr(_, value, _) | Expr::Get(value, ..) => self.expr(value),
+            Expr::Call(callee, _, args, named, _) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+                for (_, value) in named {
+                    self.expr(value);
+                }
+            }
+            Expr::Block(stmts, value, _) => self.scope(stmts, value.as_deref_mut()),
+            Expr::List(_, elements, _) => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Map(_, entries, _) => {
+                for (_, value) in entries {
+                    self.expr(value);
+                }
+            }
+            Expr::Index(object, _, index, _) => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::SetIndex(object, _, index, value, _) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::Lambda(_, _, defaults, body, _) => self.function(defaults, body),
+            Expr::LiteralExpr(..) | Expr::VariableExpr(..) => (),
+        }
+
+        // What is truthy depends on the interpreter that runs the program
+        let foldable = match expr {
+            Expr::BinaryExpr(left, operator, right, _) => {
+                is_literal(left) && is_literal(right) && !stringifies_number(left, operator, right)
+            }
+            Expr::LogicalExpr(left, _, right, _) => {
+                is_boolean_or_nil(left) && is_boolean_or_nil(right)
+            }
+            Expr::UnaryExpr(operator, inner, _) if operator.token_type == TokenType::Bang => {
+                is_boolean_or_nil(inner)
+            }
+            Expr::GroupingExpr(inner, _) | Expr::UnaryExpr(_, inner, _) => is_literal(inner),
+            _ => false,
+        };
+
+        if foldable {
+            if let Some(literal) = self.interpreter.evaluate(expr).ok().and_then(to_literal) {
+                *expr = Expr::LiteralExpr(literal, expr.span());
+            }
+        }
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr, Expr::LiteralExpr(..))
+}
+
+// Whether `left operator right` is a concatenation that writes a number
+fn stringifies_number(left: &Expr, operator: &Token, right: &Expr) -> bool {
+    let is_number = |expr: &Expr| matches!(expr, Expr::LiteralExpr(Literal::Number(_), _));
+    operator.token_type == TokenType::Plus && is_number(left) != is_number(right)
+}
+
+fn is_boolean_or_nil(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::LiteralExpr(Literal::Boolean(_) | Literal::Nil, _)
+    )
+}
+
+// Lists, maps and functions have no literal, nor identity to keep
+fn to_literal(value: Object) -> Option<Literal> {
+    match value {
+        Object::Number(number) => Some(Literal::Number(number)),
+        Object::String(string) => Some(Literal::String(string)),
+        Object::Boolean(boolean) => Some(Literal::Boolean(boolean)),
+        Object::Nil => Some(Literal::Nil),
+        _ => None,
+    }
+}
+
+// The name and value of a variable declared with a literal
+fn constant(stmt: &Stmt) -> Option<(&str, &Literal)> {
+    match stmt {
+        Stmt::VarDeclaration(identifier, Some(Expr::LiteralExpr(literal, _)), _)
+        | Stmt::ConstDeclaration(identifier, Expr::LiteralExpr(literal, _), _) => {
+            Some((&identifier.lexeme, literal))
+        }
+        _ => None,
+    }
+}
+
+// Whether the variable `name` may not keep its value through `stmt`: it is
+// assigned there, or used by a function declared there, which may assign it
+// or outlive the scope. Either one counts even when it is another variable
+// with the same name.
+fn changes(stmt: &Stmt, name: &str) -> bool {
+    let mut changed = false;
+    each_expr(stmt, false, &mut |expr, in_function| {
+        changed = changed || changes_expr(expr, name, in_function)
+    });
+    changed
+}
+
+fn changes_in(expr: &Expr, name: &str) -> bool {
+    let mut changed = false;
+    each_sub_expr(expr, false, &mut |expr, in_function| {
+        changed = changed || changes_expr(expr, name, in_function)
+    });
+    changed
+}
+
+fn changes_expr(expr: &Expr, name: &str, in_function: bool) -> bool {
+    match expr {
+        Expr::AssignExpr(identifier, ..) => &*identifier.lexeme == name,
+        Expr::VariableExpr(identifier, _) => in_function && &*identifier.lexeme == name,
+        _ => false,
+    }
+}
+
+// Calls `f` with each expression in `stmt`, nested ones included, and whether
+// it is in a function declared there
+fn each_expr(stmt: &Stmt, in_function: bool, f: &mut impl FnMut(&Expr, bool)) {
+    match stmt {
+        Stmt::Print(expr, _) | Stmt::Expression(expr, _) | Stmt::Return(_, expr, _) => {
+            each_sub_expr(expr, in_function, f)
+        }
+        Stmt::VarDeclaration(_, initializer, _) => {
+            if let Some(initializer) = initializer {
+                each_sub_expr(initializer, in_function, f);
+            }
+        }
+        Stmt::ConstDeclaration(_, initializer, _) => each_sub_expr(initializer, in_function, f),
+        Stmt::Function(_, _, defaults, body, _) => {
+            for default in defaults.iter().flatten() {
+                each_sub_expr(default, true, f);
+            }
+            each_expr(body, true, f);
+        }
+        Stmt::Block(stmts, _) => {
+            for stmt in stmts {
+                each_expr(stmt, in_function, f);
+            }
+        }
+        Stmt::If(expr, stmt_then, stmt_else, _) => {
+            each_sub_expr(expr, in_function, f);
+            each_expr(stmt_then, in_function, f);
+            if let Some(stmt_else) = stmt_else {
+                each_expr(stmt_else, in_function, f);
+            }
+        }
+        Stmt::While(expr, body, increment, ..) => {
+            each_sub_expr(expr, in_function, f);
+            each_expr(body, in_function, f);
+            if let Some(increment) = increment {
+                each_sub_expr(increment, in_function, f);
+            }
+        }
+        Stmt::ForIn(_, expr, body, ..) => {
+            each_sub_expr(expr, in_function, f);
+            each_expr(body, in_function, f);
+        }
+        Stmt::Break(..) | Stmt::Continue(..) => (),
+        Stmt::Switch(expr, cases, default, _) => {
+            each_sub_expr(expr, in_function, f);
+            for (value, stmts) in cases {
+                each_sub_expr(value, in_function, f);
+                for stmt in stmts {
+                    each_expr(stmt, in_function, f);
+                }
+            }
+            for stmt in default.iter().flatten() {
+                each_expr(stmt, in_function, f);
+            }
+        }
+    }
+}
+
+fn each_sub_expr(expr: &Expr, in_function: bool, f: &mut impl FnMut(&Expr, bool)) {
+    f(expr, in_function);
+
+    match expr {
+        Expr::BinaryExpr(left, _, right, _) | Expr::LogicalExpr(left, _, right, _) => {
+            each_sub_expr(left, in_function, f);
+            each_sub_expr(right, in_function, f);
+        }
+        Expr::GroupingExpr(inner, _)
+        | Expr::UnaryExpr(_, inner, _)
+        | Expr::AssignExpr(_, inner, _)
+        | Expr::Get(inner, ..) => each_sub_expr(inner, in_function, f),
+        Expr::Call(callee, _, args, named, _) => {
+            each_sub_expr(callee, in_function, f);
+            for arg in args.iter().chain(named.iter().map(|(_, value)| value)) {
+                each_sub_expr(arg, in_function, f);
+            }
+        }
+        Expr::Block(stmts, value, _) => {
+            for stmt in stmts {
+                each_expr(stmt, in_function, f);
+            }
+            if let Some(value) = value {
+                each_sub_expr(value, in_function, f);
+            }
+        }
+        Expr::List(_, elements, _) => {
+            for element in elements {
+                each_sub_expr(element, in_function, f);
+            }
+        }
+        Expr::Map(_, entries, _) => {
+            for (_, value) in entries {
+                each_sub_expr(value, in_function, f);
+            }
+        }
+        Expr::Index(object, _, index, _) => {
+            each_sub_expr(object, in_function, f);
+            each_sub_expr(index, in_function, f);
+        }
+        Expr::SetIndex(object, _, index, value, _) => {
+            each_sub_expr(object, in_function, f);
+            each_sub_expr(index, in_function, f);
+            each_sub_expr(value, in_function, f);
+        }
+        Expr::Lambda(_, _, defaults, body, _) => {
+            for default in defaults.iter().flatten() {
+                each_sub_expr(default, true, f);
+            }
+            each_expr(body, true, f);
+        }
+        Expr::LiteralExpr(..) | Expr::VariableExpr(..) => (),
+    }
+}
+
+// Replaces the reads of `name` in `stmts` with `literal`, until a declaration
+// shadows it. Returns whether one did.
+fn substitute_stmts(stmts: &mut [Stmt], name: &str, literal: &Literal) -> bool {
+    stmts
+        .iter_mut()
+        .any(|stmt| substitute_stmt(stmt, name, literal))
+}
+
+// Returns whether `stmt` declares another `name`, which the rest of the
+// statements around it see instead. Its initializer isn't replaced in, so
+// that reading it there is still reported.
+fn substitute_stmt(stmt: &mut Stmt, name: &str, literal: &Literal) -> bool {
+    match stmt {
+        Stmt::VarDeclaration(identifier, ..)
+        | Stmt::ConstDeclaration(identifier, ..)
+        | Stmt::Function(identifier, ..)
+            if &*identifier.lexeme == name =>
+        {
+            return true;
+        }
+        Stmt::Print(expr, _) | Stmt::Expression(expr, _) | Stmt::Return(_, expr, _) => {
+            substitute(expr, name, literal)
+        }
+        Stmt::VarDeclaration(_, initializer, _) => {
+            if let Some(initializer) = initializer {
+                substitute(initializer, name, literal);
+            }
+        }
+        Stmt::ConstDeclaration(_, initializer, _) => substitute(initializer, name, literal),
+        // Functions that use the variable keep it
+        Stmt::Function(..) | Stmt::Break(..) | Stmt::Continue(..) => (),
+        Stmt::Block(stmts, _) => {
+            substitute_stmts(stmts, name, literal);
+        }
+        Stmt::If(expr, stmt_then, stmt_else, _) => {
+            substitute(expr, name, literal);
+            substitute_stmt(stmt_then, name, literal);
+            if let Some(stmt_else) = stmt_else {
+                substitute_stmt(stmt_else, name, literal);
+            }
+        }
+        Stmt::While(expr, body, increment, ..) => {
+            substitute(expr, name, literal);
+            // Unless the body declares it, the increment still reads it
+            if !substitute_stmt(body, name, literal) {
+                if let Some(increment) = increment {
+                    substitute(increment, name, literal);
+                }
+            }
+        }
+        Stmt::ForIn(identifier, iterable, body, ..) => {
+            substitute(iterable, name, literal);
+            if &*identifier.lexeme != name {
+                substitute_stmt(body, name, literal);
+            }
+        }
+        Stmt::Switch(expr, cases, default, _) => {
+            substitute(expr, name, literal);
+            for (value, stmts) in cases {
+                substitute(value, name, literal);
+                substitute_stmts(stmts, name, literal);
+            }
+            if let Some(stmts) = default {
+                substitute_stmts(stmts, name, literal);
+            }
+        }
+    }
+
+    false
+}
+
+fn substitute(expr: &mut Expr, name: &str, literal: &Literal) {
+    match expr {
+        Expr::VariableExpr(identifier, span) if &*identifier.lexeme == name => {
+            *expr = Expr::LiteralExpr(literal.clone(), *span);
+        }
+        Expr::BinaryExpr(left, _, right, _) | Expr::LogicalExpr(left, _, right, _) => {
+            substitute(left, name, literal);
+            substitute(right, name, literal);
+        }
+        Expr::GroupingExpr(inner, _)
+        | Expr::UnaryExpr(_, inner, _)
+        | Expr::AssignExpr(_, inner, _)
+        | Expr::Get(inner, ..) => substitute(inner, name, literal),
+        Expr::Call(callee, _, args, named, _) => {
+            substitute(callee, name, literal);
+            for arg in args
+                .iter_mut()
+                .chain(named.iter_mut().map(|(_, value)| value))
+            {
+                substitute(arg, name, literal);
+            }
+        }
+        Expr::Block(stmts, value, _) => {
+            if !substitute_stmts(stmts, name, literal) {
+                if let Some(value) = value {
+                    substitute(value, name, literal);
+                }
+            }
+        }
+        Expr::List(_, elements, _) => {
+            for element in elements {
+                substitute(element, name, literal);
+            }
+        }
+        Expr::Map(_, entries, _) => {
+            for (_, value) in entries {
+                substitute(value, name, literal);
+            }
+        }
+        Expr::Index(object, _, index, _) => {
+            substitute(object, name, literal);
+            substitute(index, name, literal);
+        }
+        Expr::SetIndex(object, _, index, value, _) => {
+            substitute(object, name, literal);
+            substitute(index, name, literal);
+            substitute(value, name, literal);
+        }
+        // Functions that use the variable keep it
+        Expr::Lambda(..) | Expr::LiteralExpr(..) | Expr::VariableExpr(..) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{AstPrinter, Parser};
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        Parser::new(Scanner::new(source).scan_tokens()).parse()
+    }
+
+    // `source` optimized, then printed
+    fn optimized(source: &str) -> String {
+        let mut statements = parse(source);
+        Optimizer::new().optimize(&mut statements);
+        AstPrinter.print_stmts(&statements)
+    }
+
+    fn printed(source: &str) -> String {
+        AstPrinter.print_stmts(&parse(source))
+    }
+
+    #[test]
+    fn folds_operations_on_literals() {
+        assert_eq!(
+            optimized("print 1 + 2 * 3; print \"a\" + nil; print (2); print !nil or x;"),
+            printed("print 7; print \"anil\"; print 2; print true or x;")
+        );
+        assert_eq!(optimized("print -(1 + 1);"), "(print -2)");
+
+        // Failing operations are left to fail when they run
+        let source = "print 1 - nil; print -\"a\"; print 1..3;";
+        assert_eq!(optimized(source), printed(source));
+
+        // Whether `0` is truthy is up to the interpreter
+        let source = "print !0; print 1 and \"\"; print nil or 0;";
+        assert_eq!(optimized(source), printed(source));
+
+        // And how numbers are written
+        let source = "print \"a\" + 1; print 0.5 + nil;";
+        assert_eq!(optimized(source), printed(source));
+    }
+
+    #[test]
+    fn propagates_local_constants() {
+        assert_eq!(
+            optimized("{ var size = 10; var limit = size * size; print limit + 1; }"),
+            printed("{ var size = 10; var limit = 100; print 101; }")
+        );
+        assert_eq!(
+            optimized("fun f(x) { const n = 2; return x * n; }"),
+            printed("fun f(x) { const n = 2; return x * 2; }")
+        );
+        assert_eq!(
+            optimized("var b = { var a = 1; a + 1 };"),
+            printed("var b = { var a = 1; 2 };")
+        );
+
+        // Up to the declarations shadowing them
+        assert_eq!(
+            optimized(
+                "{ var a = 1; { var b = a; var a = \"x\"; print a; } \
+                 for (a in [2]) print a; print a; }"
+            ),
+            printed(
+                "{ var a = 1; { var b = 1; var a = \"x\"; print \"x\"; } \
+                 for (a in [2]) print a; print 1; }"
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_variables_that_may_change() {
+        for source in [
+            // Assigned, even in a branch that may not run
+            "{ var a = 1; if (x) a = 2; print a; }",
+            // Captured by a function
+            "{ var a = 1; fun f() { return a; } print a; }",
+            // Not a literal
+            "{ var a = [1]; print a; }",
+            // Globals
+            "var a = 1; print a;",
+        ] {
+            assert_eq!(optimized(source), printed(source), "{}", source);
+        }
+    }
+}
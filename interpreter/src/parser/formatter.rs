@@ -0,0 +1,932 @@
+use crate::ast::expr::{Expr, Visitor as ExprVisitor};
+use crate::ast::span::Span;
+use crate::ast::stmt::{Stmt, Visitor as StmtVisitor};
+use crate::ast::token::Token;
+use crate::ast::tokentype::{Literal, TokenType};
+use crate::error::{RuntimeError, Signal};
+use std::rc::Rc;
+
+/// Prints the AST back as Lox source, in a canonical layout: one statement
+/// per line, nested statements indented, single spaces around binary
+/// operators and after commas, and a blank line around function
+/// declarations.
+///
+/// ```text
+/// fun add(a, b) {
+///     return a + b;
+/// }
+///
+/// for (var i = 0; i < 3; i = i + 1)
+///     print add(i, 1);
+/// ```
+///
+/// Formatting formatted source gives it back unchanged, and it parses to the
+/// same program as the original. Comments are not in the AST, so they are
+/// dropped unless given with `set_comments`.
+pub struct Formatter {
+    indent_width: usize,
+    depth: usize,
+    // The span of the statement being visited
+    span: Span,
+    comments: Vec<Comment>,
+    // Position in `comments` of the first one not printed yet
+    next_comment: usize,
+}
+
+struct Comment {
+    text: String,
+    offset: usize,
+    // Where the token before the comment ends, when it is on the same line
+    after: Option<usize>,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Self {
+            indent_width: 4,
+            depth: 0,
+            span: Span::default(),
+            comments: vec![],
+            next_comment: 0,
+        }
+    }
+
+    /// How many spaces each nesting level is indented by, 4 by default.
+    pub fn set_indent_width(&mut self, indent_width: usize) {
+        self.indent_width = indent_width;
+    }
+
+    /// Prints the `Comment` tokens among `tokens`, which should be those the
+    /// formatted statements were parsed from, scanned with
+    /// `Scanner::set_preserve_comments`.
+    ///
+    /// A comment following a statement on the line it ends on stays after
+    /// it. Others go on their own lines before the statement they are in or
+    /// precede, or at the end of the block they close.
+    pub fn set_comments(&mut self, tokens: &[Token]) {
+        self.comments.clear();
+        self.next_comment = 0;
+        let mut previous: Option<&Token> = None;
+
+        for token in tokens {
+            if token.token_type != TokenType::Comment {
+                if !token.lexeme.is_empty() {
+                    previous = Some(token);
+                }
+                continue;
+            }
+
+            // Tokens record the line they end on
+            let line = token.line - token.lexeme.matches('\n').count();
+            self.comments.push(Comment {
+                text: token.lexeme.trim_end().to_string(),
+                offset: token.offset,
+                after: previous
+                    .filter(|previous| previous.line == line)
+                    .map(|previous| previous.span().end),
+            });
+        }
+    }
+
+    /// Formats a program, ending it with a line break.
+    pub fn format_stmts(&mut self, stmts: &[Stmt]) -> String {
+        let mut result = self.stmts(stmts);
+        for comment in self.take_comments(usize::MAX) {
+            if !result.is_empty() {
+                result.push('\n');
+            }
+            result.push_str(&comment);
+        }
+
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result
+    }
+
+    fn indent(&self) -> String {
+        " ".repeat(self.depth * self.indent_width)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        expr.accept(self).unwrap_or_default()
+    }
+
+    // The statement, along with the comments before and in it, and after it
+    // on its last line
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        let span = stmt.span();
+        let mut result = String::new();
+
+        let leading = self.take_comments(span.start);
+        let formatted = self.code(stmt);
+        // Those in its expressions, which no nested statement printed
+        let inner = self.take_comments(span.end);
+        for comment in leading.into_iter().chain(inner) {
+            result.push_str(&format!("{}\n{}", comment, self.indent()));
+        }
+        result.push_str(&formatted);
+
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.after == Some(span.end))
+        {
+            result.push_str(&format!(" {}", comment.text));
+            self.next_comment += 1;
+        }
+
+        result
+    }
+
+    // The statement alone
+    fn code(&mut self, stmt: &Stmt) -> String {
+        match for_loop(stmt) {
+            Some(for_loop) => self.for_loop(for_loop),
+            None => {
+                self.span = stmt.span();
+                stmt.accept(self).unwrap_or_default()
+            }
+        }
+    }
+
+    // The comments starting before `offset` that weren't printed yet
+    fn take_comments(&mut self, offset: usize) -> Vec<String> {
+        let mut taken = vec![];
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.offset < offset)
+        {
+            taken.push(comment.text.clone());
+            self.next_comment += 1;
+        }
+        taken
+    }
+
+    // The comments starting before `offset` on their own lines at the current
+    // depth, as the statements of `stmts` after the last one
+    fn closing_comments(&mut self, offset: usize, mut stmts: String) -> String {
+        for comment in self.take_comments(offset) {
+            if !stmts.is_empty() {
+                stmts.push_str(&format!("\n{}", self.indent()));
+            }
+            stmts.push_str(&comment);
+        }
+        stmts
+    }
+
+    // The statements on their own lines at the current depth, the first one
+    // not indented
+    fn stmts(&mut self, stmts: &[Stmt]) -> String {
+        let mut result = String::new();
+
+        for (index, stmt) in stmts.iter().enumerate() {
+            if index > 0 {
+                let previous = &stmts[index - 1];
+                let blank =
+                    matches!(previous, Stmt::Function(..)) || matches!(stmt, Stmt::Function(..));
+                result.push_str(if blank { "\n\n" } else { "\n" });
+                result.push_str(&self.indent());
+            }
+            result.push_str(&self.stmt(stmt));
+        }
+
+        result
+    }
+
+    fn block(&mut self, stmts: &[Stmt], span: Span) -> String {
+        self.depth += 1;
+        let formatted = self.stmts(stmts);
+        let formatted = self.closing_comments(span.end, formatted);
+        let body = format!("{{\n{}{}", self.indent(), formatted);
+        self.depth -= 1;
+
+        if formatted.is_empty() {
+            return "{}".to_string();
+        }
+        format!("{}\n{}}}", body, self.indent())
+    }
+
+    fn parameters(&mut self, parameters: &[Token], defaults: &[Option<Expr>]) -> String {
+        let mut formatted = vec![];
+        for (parameter, default) in parameters.iter().zip(defaults.iter()) {
+            formatted.push(match default {
+                Some(default) => format!("{} = {}", parameter.lexeme, self.expr(default)),
+                None => parameter.lexeme.to_string(),
+            });
+        }
+        formatted.join(", ")
+    }
+
+    // The body of a loop or an `if`, after its header: a block goes on the
+    // same line, other statements on the next one
+    fn body(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Block(stmts, span) if for_loop(stmt).is_none() => {
+                format!(" {}", self.block(stmts, *span))
+            }
+            _ => {
+                self.depth += 1;
+                let body = format!("\n{}{}", self.indent(), self.stmt(stmt));
+                self.depth -= 1;
+                body
+            }
+        }
+    }
+
+    fn for_loop(&mut self, for_loop: ForLoop) -> String {
+        let initializer = match for_loop.initializer {
+            // On the line of the loop, so without the comments
+            Some(initializer) => self.code(initializer),
+            None => ";".to_string(),
+        };
+        let condition = match for_loop.condition {
+            Some(condition) => format!(" {};", self.expr(condition)),
+            None => ";".to_string(),
+        };
+        let increment = match for_loop.increment {
+            Some(increment) => format!(" {}", self.expr(increment)),
+            None => String::new(),
+        };
+
+        format!(
+            "{}for ({}{}{}){}",
+            label(for_loop.label),
+            initializer,
+            condition,
+            increment,
+            self.body(for_loop.body)
+        )
+    }
+
+    fn arguments(&mut self, exprs: &[Expr]) -> Vec<String> {
+        exprs.iter().map(|expr| self.expr(expr)).collect()
+    }
+}
+
+// The expression of a concise function body, which the parser made into a
+// block returning it, both with the same span
+fn concise_value(body: &Stmt) -> Option<&Expr> {
+    match body {
+        Stmt::Block(stmts, span) => match stmts.as_slice() {
+            [Stmt::Return(_, value, return_span)] if return_span == span => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The parts of a `for` loop. The parser turns one into a `while` loop that
+// keeps its increment and label, in a block with the initializer when there
+// is one. Those nodes all get the span of the whole loop, so they can't be
+// confused with ones written in the source.
+struct ForLoop<'a> {
+    initializer: Option<&'a Stmt>,
+    condition: Option<&'a Expr>,
+    increment: Option<&'a Expr>,
+    body: &'a Stmt,
+    label: Option<&'a Token>,
+}
+
+// Before the keyword of a labeled loop
+fn label(label: Option<&Token>) -> String {
+    label.map_or(String::new(), |label| format!("{}: ", label.lexeme))
+}
+
+fn jump(keyword: &str, label: Option<&Token>) -> String {
+    match label {
+        Some(label) => format!("{} {};", keyword, label.lexeme),
+        None => format!("{};", keyword),
+    }
+}
+
+fn for_loop(stmt: &Stmt) -> Option<ForLoop<'_>> {
+    let (initializer, stmt) = match stmt {
+        Stmt::Block(stmts, span) => match stmts.as_slice() {
+            [initializer, stmt @ Stmt::While(.., loop_span)] if loop_span == span => {
+                (Some(initializer), stmt)
+            }
+            _ => return None,
+        },
+        stmt => (None, stmt),
+    };
+
+    let Stmt::While(condition, body, increment, label, span) = stmt else {
+        return None;
+    };
+
+    let condition = match condition {
+        Expr::LiteralExpr(Literal::Boolean(true), condition_span) if condition_span == span => None,
+        condition => Some(condition),
+    };
+
+    let increment = increment.as_ref();
+
+    // Without any of those, it is as well written as a `while` loop
+    if initializer.is_none() && condition.is_some() && increment.is_none() {
+        return None;
+    }
+
+    Some(ForLoop {
+        initializer,
+        condition,
+        increment,
+        body,
+        label: label.as_ref(),
+    })
+}
+
+// Quotes a string so that it scans back to `value`: with double quotes, or
+// single ones when it contains double quotes, or as a raw string when it has
+// both or a backslash at the end of a line, which would continue the string
+fn quote(value: &str) -> String {
+    let continues = value.contains("\\\n");
+
+    if !value.contains('"') && !continues {
+        return format!("\"{}\"", value);
+    }
+    if !value.contains('\'') && !continues {
+        return format!("'{}'", value);
+    }
+
+    let mut hashes = 1;
+    while value.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{}\"{}\"{}", hashes, value, hashes)
+}
+
+impl ExprVisitor<String> for Formatter {
+    fn visit_literal_expr(&mut self, literal: &Literal) -> Result<String, RuntimeError> {
+        Ok(match literal {
+            Literal::String(value) => quote(value),
+            Literal::Number(value) => value.to_string(),
+            Literal::Boolean(value) => value.to_string(),
+            Literal::Nil => "nil".to_string(),
+        })
+    }
+
+    fn visit_binary_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<String, RuntimeError> {
+        let (left, right) = (self.expr(left), self.expr(right));
+
+        // Ranges read as one value, like slices' `list[1..3]`
+        match operator.lexeme.as_ref() {
+            ".." | "..=" => Ok(format!("{}{}{}", left, operator.lexeme, right)),
+            _ => Ok(format!("{} {} {}", left, operator.lexeme, right)),
+        }
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, RuntimeError> {
+        Ok(format!("({})", self.expr(expr)))
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<String, RuntimeError> {
+        Ok(format!("{}{}", operator.lexeme, self.expr(right)))
+    }
+
+    fn visit_variable_expr(&mut self, identifier: &Token) -> Result<String, RuntimeError> {
+        Ok(identifier.lexeme.to_string())
+    }
+
+    fn visit_assign_expr(
+        &mut self,
+        identifier: &Token,
+        value: &Expr,
+    ) -> Result<String, RuntimeError> {
+        Ok(format!("{} = {}", identifier.lexeme, self.expr(value)))
+    }
+
+    fn visit_logical_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.visit_binary_expr(left, operator, right)
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        args: &[Expr],
+        named: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let callee = self.expr(callee);
+        let mut arguments = self.arguments(args);
+        for (name, value) in named {
+            arguments.push(format!("{}: {}", name.lexeme, self.expr(value)));
+        }
+
+        // The parser made `x |> f(y)` into `f(x, y)`, with the `|>` as paren
+        if paren.token_type == TokenType::PipeGreater {
+            let piped = arguments.remove(0);
+            if arguments.is_empty() {
+                return Ok(format!("{} |> {}", piped, callee));
+            }
+            return Ok(format!("{} |> {}({})", piped, callee, arguments.join(", ")));
+        }
+
+        Ok(format!("{}({})", callee, arguments.join(", ")))
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        stmts: &[Stmt],
+        value: Option<&Expr>,
+    ) -> Result<String, RuntimeError> {
+        self.depth += 1;
+        let mut lines = stmts.iter().map(|stmt| self.stmt(stmt)).collect::<Vec<_>>();
+        lines.extend(value.map(|value| self.expr(value)));
+        let indent = self.indent();
+        self.depth -= 1;
+
+        let mut result = "{".to_string();
+        for line in lines {
+            result.push_str(&format!("\n{}{}", indent, line));
+        }
+        result.push_str(&format!("\n{}}}", self.indent()));
+        Ok(result)
+    }
+
+    fn visit_list_expr(
+        &mut self,
+        _bracket: &Token,
+        elements: &[Expr],
+    ) -> Result<String, RuntimeError> {
+        Ok(format!("[{}]", self.arguments(elements).join(", ")))
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let mut formatted = vec![];
+        for (key, value) in entries {
+            let key = match &key.literal {
+                Some(Literal::String(key)) => quote(key),
+                _ => key.lexeme.to_string(),
+            };
+            formatted.push(format!("{}: {}", key, self.expr(value)));
+        }
+
+        Ok(format!("{{{}}}", formatted.join(", ")))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, RuntimeError> {
+        Ok(format!("{}[{}]", self.expr(object), self.expr(index)))
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<String, RuntimeError> {
+        let target = self.visit_index_expr(object, bracket, index)?;
+        Ok(format!("{} = {}", target, self.expr(value)))
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<String, RuntimeError> {
+        Ok(format!("{}.{}", self.expr(object), name.lexeme))
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keyword: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<String, RuntimeError> {
+        let parameters = self.parameters(parameters, defaults);
+        match concise_value(body) {
+            Some(value) => Ok(format!("fun ({}) => {}", parameters, self.expr(value))),
+            None => Ok(format!("fun ({}){}", parameters, self.body(body))),
+        }
+    }
+}
+
+impl StmtVisitor<String> for Formatter {
+    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<String, Signal> {
+        Ok(format!("print {};", self.expr(expr)))
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<String, Signal> {
+        Ok(format!("{};", self.expr(expr)))
+    }
+
+    fn visit_var_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: Option<&Expr>,
+    ) -> Result<String, Signal> {
+        Ok(match initializer {
+            Some(initializer) => format!("var {} = {};", identifier.lexeme, self.expr(initializer)),
+            None => format!("var {};", identifier.lexeme),
+        })
+    }
+
+    fn visit_const_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: &Expr,
+    ) -> Result<String, Signal> {
+        Ok(format!(
+            "const {} = {};",
+            identifier.lexeme,
+            self.expr(initializer)
+        ))
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<String, Signal> {
+        let span = self.span;
+        Ok(self.block(stmts, span))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt_then: &Stmt,
+        stmt_else: &Option<Box<Stmt>>,
+    ) -> Result<String, Signal> {
+        let block = matches!(stmt_then, Stmt::Block(..)) && for_loop(stmt_then).is_none();
+        let mut result = format!("if ({}){}", self.expr(expr), self.body(stmt_then));
+
+        if let Some(stmt_else) = stmt_else {
+            // After a block, `else` goes on the line of its closing brace
+            if block {
+                result.push(' ');
+            } else {
+                result.push_str(&format!("\n{}", self.indent()));
+            }
+
+            match **stmt_else {
+                Stmt::If(..) => result.push_str(&format!("else {}", self.stmt(stmt_else))),
+                _ => result.push_str(&format!("else{}", self.body(stmt_else))),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt: &Stmt,
+        _increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        // Loops with an increment are `for` loops, see `Formatter::code`
+        Ok(format!(
+            "{}while ({}){}",
+            self::label(label),
+            self.expr(expr),
+            self.body(stmt)
+        ))
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        identifier: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<String, Signal> {
+        let parameters = self.parameters(parameters, defaults);
+        match concise_value(body) {
+            Some(value) => Ok(format!(
+                "fun {}({}) => {};",
+                identifier.lexeme,
+                parameters,
+                self.expr(value)
+            )),
+            None => Ok(format!(
+                "fun {}({}){}",
+                identifier.lexeme,
+                parameters,
+                self.body(body)
+            )),
+        }
+    }
+
+    fn visit_return_stmt(&mut self, token: &Token, expr: &Expr) -> Result<String, Signal> {
+        // The parser gives a bare `return` a `nil` at the keyword
+        match expr {
+            Expr::LiteralExpr(Literal::Nil, span) if *span == token.span() => {
+                Ok("return;".to_string())
+            }
+            _ => Ok(format!("return {};", self.expr(expr))),
+        }
+    }
+
+    fn visit_break_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        Ok(jump("break", label))
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        Ok(jump("continue", label))
+    }
+
+    fn visit_switch_stmt(
+        &mut self,
+        expr: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: Option<&[Stmt]>,
+    ) -> Result<String, Signal> {
+        let span = self.span;
+        let mut result = format!("switch ({}) {{", self.expr(expr));
+
+        let labeled = cases
+            .iter()
+            .map(|(value, stmts)| (Some(value), stmts.as_slice()))
+            .chain(default.map(|stmts| (None, stmts)))
+            .collect::<Vec<_>>();
+
+        self.depth += 1;
+        for (index, (value, stmts)) in labeled.iter().enumerate() {
+            let label = match value {
+                Some(value) => format!("case {}:", self.expr(value)),
+                None => "default:".to_string(),
+            };
+            result.push_str(&format!("\n{}{}", self.indent(), label));
+
+            self.depth += 1;
+            let mut formatted = self.stmts(stmts);
+            // Comments at the end go in the last case
+            if index == labeled.len() - 1 {
+                formatted = self.closing_comments(span.end, formatted);
+            }
+            if !formatted.is_empty() {
+                result.push_str(&format!("\n{}{}", self.indent(), formatted));
+            }
+            self.depth -= 1;
+        }
+        self.depth -= 1;
+
+        result.push_str(&format!("\n{}}}", self.indent()));
+        Ok(result)
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        identifier: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        Ok(format!(
+            "{}for ({} in {}){}",
+            self::label(label),
+            identifier.lexeme,
+            self.expr(iterable),
+            self.body(body)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorReporter;
+    use crate::parser::{AstPrinter, Parser};
+    use crate::scanner::Scanner;
+    use std::{fs, path::Path};
+
+    fn parse(source: &str, reporter: &ErrorReporter) -> Vec<Stmt> {
+        let mut scanner = Scanner::new(source);
+        scanner.set_error_reporter(reporter);
+        let mut parser = Parser::new(scanner.scan_tokens());
+        parser.set_error_reporter(reporter);
+        parser.parse()
+    }
+
+    fn format(source: &str) -> String {
+        Formatter::new().format_stmts(&parse(source, &ErrorReporter::new()))
+    }
+
+    // Formats with the comments, and checks that they are all kept
+    fn format_with_comments(source: &str, reporter: &ErrorReporter) -> String {
+        let mut scanner = Scanner::new(source);
+        scanner.set_error_reporter(reporter);
+        scanner.set_preserve_comments(true);
+        let tokens = scanner.scan_tokens();
+
+        let mut formatter = Formatter::new();
+        formatter.set_comments(&tokens);
+        let mut parser = Parser::new(tokens.clone());
+        parser.set_error_reporter(reporter);
+        let formatted = formatter.format_stmts(&parser.parse());
+
+        let comments = tokens
+            .iter()
+            .filter(|token| token.token_type == TokenType::Comment)
+            .map(|token| token.lexeme.trim_end());
+        let mut rest = formatted.as_str();
+        for comment in comments {
+            let found = rest.find(comment).expect(comment);
+            rest = &rest[found + comment.len()..];
+        }
+
+        formatted
+    }
+
+    #[test]
+    fn formats_a_program() {
+        let source = "fun   fib(n){if(n<2)return n;else{return fib(n-1)+fib(n -2);}}\n\
+            var xs=[ 1,2 ,3];  for(var i=0;i<3;i=i+1)print xs[i]*-fib(i);\n\
+            for(;;){ xs[0] = {\"a\":'say \"hi\"'}; return; }\n\
+            switch(x){case 1: print 1; default:}\n\
+            var y = { print 1; (y) };";
+
+        assert_eq!(
+            format(source),
+            "fun fib(n) {\n\
+             \x20   if (n < 2)\n\
+             \x20       return n;\n\
+             \x20   else {\n\
+             \x20       return fib(n - 1) + fib(n - 2);\n\
+             \x20   }\n\
+             }\n\
+             \n\
+             var xs = [1, 2, 3];\n\
+             for (var i = 0; i < 3; i = i + 1)\n\
+             \x20   print xs[i] * -fib(i);\n\
+             for (;;) {\n\
+             \x20   xs[0] = {\"a\": 'say \"hi\"'};\n\
+             \x20   return;\n\
+             }\n\
+             switch (x) {\n\
+             \x20   case 1:\n\
+             \x20       print 1;\n\
+             \x20   default:\n\
+             }\n\
+             var y = {\n\
+             \x20   print 1;\n\
+             \x20   (y)\n\
+             };\n"
+        );
+    }
+
+    #[test]
+    fn concise_bodies_stay_concise() {
+        let source = "fun square(x)=>x*x; var f=fun(a,b=1)=>a+b; var g=fun(){return 1;};";
+
+        assert_eq!(
+            format(source),
+            "fun square(x) => x * x;\n\
+             \n\
+             var f = fun (a, b = 1) => a + b;\n\
+             var g = fun () {\n\
+             \x20   return 1;\n\
+             };\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "// Adds\n// numbers\nfun add(a, b) { return a + b; } // done\n\
+            var x = add(1, /* two */ 2);\n\
+            if (x) { print x; // positive\n /* end */ } else print 0; // zero\n\
+            for (var i = 0; i < 2; i = i + 1) {\n    // nothing\n}\n\
+            switch (x) { case 1: print 1; // one\n default: print 2;\n // last\n }\n\
+            /* the\n   end */";
+        let reporter = ErrorReporter::new();
+        let formatted = format_with_comments(source, &reporter);
+
+        assert_eq!(
+            formatted,
+            "// Adds\n\
+             // numbers\n\
+             fun add(a, b) {\n\
+             \x20   return a + b;\n\
+             } // done\n\
+             \n\
+             /* two */\n\
+             var x = add(1, 2);\n\
+             if (x) {\n\
+             \x20   print x; // positive\n\
+             \x20   /* end */\n\
+             } else\n\
+             \x20   print 0; // zero\n\
+             for (var i = 0; i < 2; i = i + 1) {\n\
+             \x20   // nothing\n\
+             }\n\
+             switch (x) {\n\
+             \x20   case 1:\n\
+             \x20       print 1; // one\n\
+             \x20   default:\n\
+             \x20       print 2;\n\
+             \x20       // last\n\
+             }\n\
+             /* the\n   end */\n"
+        );
+        assert_eq!(format_with_comments(&formatted, &reporter), formatted);
+        assert!(!reporter.has_error());
+    }
+
+    #[test]
+    fn indent_width_is_configurable() {
+        let statements = parse("while (true) { if (x) print 1; }", &ErrorReporter::new());
+        let mut formatter = Formatter::new();
+        formatter.set_indent_width(2);
+
+        assert_eq!(
+            formatter.format_stmts(&statements),
+            "while (true) {\n  if (x)\n    print 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn strings_are_quoted_to_scan_back_the_same() {
+        assert_eq!(quote("plain"), "\"plain\"");
+        assert_eq!(quote("say \"hi\""), "'say \"hi\"'");
+        assert_eq!(quote("it's \"x\""), "r#\"it's \"x\"\"#");
+        assert_eq!(quote("a \"# b '"), "r##\"a \"# b '\"##");
+        assert_eq!(quote("C:\\\n"), "r#\"C:\\\n\"#");
+    }
+
+    // Every conformance script that parses formats to a program that parses
+    // the same, with its comments, and formatting it again changes nothing
+    #[test]
+    fn formatting_round_trips_the_test_scripts() {
+        fn scripts(dir: &Path, found: &mut Vec<std::path::PathBuf>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    scripts(&path, found);
+                } else if path.extension().is_some_and(|extension| extension == "lox") {
+                    found.push(path);
+                }
+            }
+        }
+
+        let mut paths = vec![];
+        scripts(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts"),
+            &mut paths,
+        );
+        let mut formatted_scripts = 0;
+
+        for path in paths {
+            let source = fs::read_to_string(&path).unwrap();
+            let mut reporter = ErrorReporter::new();
+            reporter.set_print(false);
+            let statements = parse(&source, &reporter);
+            if reporter.has_error() {
+                continue;
+            }
+
+            let formatted = format_with_comments(&source, &reporter);
+            let reparsed = parse(&formatted, &reporter);
+            assert!(
+                !reporter.has_error(),
+                "{:?} formats to\n{}",
+                path,
+                formatted
+            );
+            assert_eq!(
+                AstPrinter.print_stmts(&reparsed),
+                AstPrinter.print_stmts(&statements),
+                "{:?}",
+                path
+            );
+            assert_eq!(
+                format_with_comments(&formatted, &reporter),
+                formatted,
+                "{:?}",
+                path
+            );
+            formatted_scripts += 1;
+        }
+
+        assert!(formatted_scripts > 10);
+    }
+}
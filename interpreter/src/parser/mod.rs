@@ -1,10 +1,18 @@
+mod formatter;
+#[allow(clippy::module_inception)]
 mod parser;
+mod printer;
+mod rpn;
 
+pub use formatter::Formatter;
 pub use parser::Parser;
+pub use printer::AstPrinter;
+pub use rpn::RpnPrinter;
 
 fn _example() {
     use crate::ast::{
         expr::{bexpr, gexpr, lexpr, uexpr},
+        span::Span,
         token::Token,
         tokentype::{Literal, TokenType},
     };
@@ -12,9 +20,12 @@ fn _example() {
     let _expression = bexpr(
         uexpr(
             Token::new(TokenType::Minus, "-", None, 1),
-            lexpr(Literal::Number(123.0)),
+            lexpr(Literal::Number(123.0), Span::new(1, 4)),
         ),
         Token::new(TokenType::Star, "*", None, 1),
-        gexpr(lexpr(Literal::Number(45.67))),
+        gexpr(
+            lexpr(Literal::Number(45.67), Span::new(8, 13)),
+            Span::new(7, 14),
+        ),
     );
 }
@@ -1,10 +1,12 @@
 use std::cell::Cell;
+use std::rc::Rc;
 
 use crate::ast::expr::{aexpr, cexpr, lgexpr, vexpr};
 use crate::ast::stmt::{fstmt, ifstmt, vdstmt, wstmt};
 use crate::{
     ast::{
         expr::{bexpr, gexpr, lexpr, uexpr, Expr},
+        span::Span,
         stmt::{estmt, pstmt, Stmt},
         token::Token,
         tokentype::{Literal, TokenType},
@@ -19,7 +21,16 @@ pub struct Parser<'a> {
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    /// Parses `tokens`, which should end with an `Eof` token like the
+    /// scanner's do. One is added when they don't. `Comment` tokens are
+    /// skipped.
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.retain(|token| token.token_type != TokenType::Comment);
+        if tokens.last().map(|token| token.token_type) != Some(TokenType::Eof) {
+            let line = tokens.last().map_or(1, |token| token.line);
+            tokens.push(Token::new(TokenType::Eof, "", None, line));
+        }
+
         Self {
             _reporter: None,
             _current: Cell::new(0),
@@ -32,67 +43,109 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse(&self) -> Vec<Stmt> {
-        let mut statements = Vec::new();
+        self.statements().collect()
+    }
 
-        while !self.is_at_end() {
-            match self.declaration() {
-                Ok(stmt) => {
-                    statements.push(stmt);
-                }
-                Err(e) => {
-                    self.error(&e.token, e.message.as_str());
-                    self.synchronize();
+    /// Parses the program one top-level statement at a time, as the iterator
+    /// is advanced. Like `parse`, it reports errors and skips the statements
+    /// that have them.
+    pub fn statements(&self) -> impl Iterator<Item = Stmt> + '_ {
+        std::iter::from_fn(move || {
+            while !self.is_at_end() {
+                match self.declaration() {
+                    Ok(stmt) => return Some(stmt),
+                    Err(e) => {
+                        self.error(&e.token, e.message.as_str());
+                        self.synchronize();
+                    }
                 }
             }
-        }
 
-        statements
+            None
+        })
     }
 
     /**
-    * Parse grammar rule: declaration    → statement
-                                           | varDecl ;
-    */
+     * Parse grammar rule: declaration    → statement
+     *                                      | varDecl
+     *                                      | constDecl ;
+     */
     fn declaration(&self) -> Result<Stmt, ParseError> {
-        if self.match_token(vec![TokenType::Fun]) {
+        // `fun (` starts an anonymous function, in an expression statement
+        if self.check(TokenType::Fun) && !self.check_next(TokenType::LeftParen) {
+            self.advance();
             return self.fun_decl_stmt("function");
         }
 
-        if self.match_token(vec![TokenType::Var]) {
+        if self.match_token(&[TokenType::Var]) {
             return self.var_decl_stmt();
         }
 
+        if self.match_token(&[TokenType::Const]) {
+            return self.const_decl_stmt();
+        }
+
         self.statement()
     }
 
     /**
-    * Parse grammar rule: statement      → exprStmt
-                                           | printStmt ;
-    */
+     * Parse grammar rule: statement      → exprStmt
+     *                                      | printStmt
+     *                                      | switchStmt
+     *                                      | breakStmt
+     *                                      | continueStmt
+     *                                      | IDENTIFIER ":" ( whileStmt | forStmt | forInStmt ) ;
+     */
     fn statement(&self) -> Result<Stmt, ParseError> {
-        if self.match_token(vec![TokenType::For]) {
-            return self.for_stmt();
+        // A label names the loop after it, for `break` and `continue`
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+            let label = self.advance().clone();
+            self.advance();
+
+            if self.match_token(&[TokenType::While]) {
+                return self.while_stmt(Some(label));
+            }
+            if self.match_token(&[TokenType::For]) {
+                return self.for_stmt(Some(label));
+            }
+            return Err(ParseError {
+                token: self.peek().clone(),
+                message: "Expect loop after label.".to_string(),
+            });
         }
 
-        if self.match_token(vec![TokenType::If]) {
+        if self.match_token(&[TokenType::For]) {
+            return self.for_stmt(None);
+        }
+
+        if self.match_token(&[TokenType::If]) {
             return self.if_stmt();
         }
 
-        if self.match_token(vec![TokenType::Print]) {
+        if self.match_token(&[TokenType::Print]) {
             return self.print_stmt();
         }
 
-        if self.match_token(vec![TokenType::While]) {
-            return self.while_stmt();
+        if self.match_token(&[TokenType::While]) {
+            return self.while_stmt(None);
+        }
+
+        if self.match_token(&[TokenType::Break, TokenType::Continue]) {
+            return self.jump_stmt();
         }
 
-        if self.match_token(vec![TokenType::Return]) {
+        if self.match_token(&[TokenType::Return]) {
             return self.return_stmt();
         }
 
-        if self.match_token(vec![TokenType::LeftBrace]) {
+        if self.match_token(&[TokenType::Switch]) {
+            return self.switch_stmt();
+        }
+
+        if self.match_token(&[TokenType::LeftBrace]) {
+            let start = self.previous().offset;
             let stmts = self.block()?;
-            return Ok(Stmt::Block(stmts));
+            return Ok(Stmt::Block(stmts, self.span_from(start)));
         }
 
         self.expression_stmt()
@@ -105,8 +158,17 @@ impl<'a> Parser<'a> {
         let mut stmts = vec![];
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            let stmt = self.declaration()?;
-            stmts.push(stmt);
+            match self.declaration() {
+                Ok(stmt) => stmts.push(stmt),
+                // Recover inside the block, so its '}' isn't left to end the
+                // enclosing statements
+                Err(e) => {
+                    self.error(&e.token, e.message.as_str());
+                    if !self.check(TokenType::RightBrace) {
+                        self.synchronize();
+                    }
+                }
+            }
         }
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
@@ -115,10 +177,11 @@ impl<'a> Parser<'a> {
     }
 
     /**
-    * Parse grammar rule: ifStmt         → "if" "(" expression ")" statement
-                                            ( "else" statement )? ;
-    */
+     * Parse grammar rule: ifStmt         → "if" "(" expression ")" statement
+     *                                      ( "else" statement )? ;
+     */
     fn if_stmt(&self) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
@@ -127,30 +190,39 @@ impl<'a> Parser<'a> {
 
         let mut else_branch: Option<Stmt> = None;
 
-        if self.match_token(vec![TokenType::Else]) {
+        if self.match_token(&[TokenType::Else]) {
             else_branch = Some(self.statement()?);
         }
 
-        Ok(ifstmt(condition, then_branch, else_branch))
+        Ok(ifstmt(
+            condition,
+            then_branch,
+            else_branch,
+            self.span_from(start),
+        ))
     }
 
     /**
-    * Parse grammar rule: forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
-                                            expression? ";"
-                                            expression? ")" statement ;
-    */
-    fn for_stmt(&self) -> Result<Stmt, ParseError> {
+     * Parse grammar rule: forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
+     *                                      expression? ";"
+     *                                      expression? ")" statement ;
+     */
+    fn for_stmt(&self, label: Option<Token>) -> Result<Stmt, ParseError> {
+        let start = label.as_ref().unwrap_or(self.previous()).offset;
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
-        let mut initializer: Option<Stmt> = None;
-        if self.match_token(vec![TokenType::Semicolon]) {
-            initializer = None;
-        } else if self.match_token(vec![TokenType::Var]) {
-            initializer = Some(self.var_decl_stmt()?);
-        } else {
-            initializer = Some(self.expression_stmt()?);
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+            return self.for_in_stmt(start, label);
         }
 
+        let initializer: Option<Stmt> = if self.match_token(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_token(&[TokenType::Var]) {
+            Some(self.var_decl_stmt()?)
+        } else {
+            Some(self.expression_stmt()?)
+        };
+
         let mut condition: Option<Expr> = None;
         if !self.check(TokenType::Semicolon) {
             condition = Some(self.expression()?);
@@ -163,36 +235,138 @@ impl<'a> Parser<'a> {
         }
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
+        let body = self.statement()?;
+        // The statements the loop turns into all span the whole loop
+        let span = self.span_from(start);
+
+        let condition = condition.unwrap_or_else(|| lexpr(Literal::Boolean(true), span));
+        let mut body = wstmt(condition, body, increment, label, span);
 
-        if let Some(i) = increment {
-            body = Stmt::Block(vec![body, estmt(i)]);
+        if let Some(init) = initializer {
+            body = Stmt::Block(vec![init, body], span);
         }
 
-        if let Some(c) = condition {
-            body = wstmt(c, body);
-        } else {
-            body = wstmt(lexpr(Literal::Boolean(true)), body);
+        Ok(body)
+    }
+
+    /**
+     * Parse grammar rule: forInStmt      → "for" "(" IDENTIFIER "in" expression ")" statement ;
+     */
+    fn for_in_stmt(&self, start: usize, label: Option<Token>) -> Result<Stmt, ParseError> {
+        let identifier = self.advance().clone();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for-in clause.")?;
+
+        let body = self.statement()?;
+        Ok(Stmt::ForIn(
+            identifier,
+            iterable,
+            Box::new(body),
+            label,
+            self.span_from(start),
+        ))
+    }
+
+    /**
+     * Parse grammar rule: switchStmt     → "switch" "(" expression ")"
+     *                                      "{" switchCase* defaultCase? "}" ;
+     *                     switchCase     → "case" expression ":" declaration* ;
+     *                     defaultCase    → "default" ":" declaration* ;
+     */
+    fn switch_stmt(&self) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'switch'.")?;
+        let scrutinee = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after switch value.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before switch cases.")?;
+
+        let mut cases = vec![];
+        let mut default = None;
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if default.is_some() {
+                return Err(ParseError {
+                    token: self.peek().clone(),
+                    message: "Default case must be the last one.".to_string(),
+                });
+            }
+
+            if self.match_token(&[TokenType::Case]) {
+                let value = self.expression()?;
+                self.consume(TokenType::Colon, "Expect ':' after case value.")?;
+                cases.push((value, self.case_body()?));
+            } else if self.match_token(&[TokenType::Default]) {
+                self.consume(TokenType::Colon, "Expect ':' after 'default'.")?;
+                default = Some(self.case_body()?);
+            } else {
+                return Err(ParseError {
+                    token: self.peek().clone(),
+                    message: "Expect 'case' or 'default'.".to_string(),
+                });
+            }
         }
 
-        if let Some(init) = initializer {
-            body = Stmt::Block(vec![init, body]);
+        self.consume(TokenType::RightBrace, "Expect '}' after switch cases.")?;
+
+        Ok(Stmt::Switch(
+            scrutinee,
+            cases,
+            default,
+            self.span_from(start),
+        ))
+    }
+
+    fn case_body(&self) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = vec![];
+
+        while !self.check(TokenType::Case)
+            && !self.check(TokenType::Default)
+            && !self.check(TokenType::RightBrace)
+            && !self.is_at_end()
+        {
+            stmts.push(self.declaration()?);
         }
 
-        Ok(body)
+        Ok(stmts)
     }
 
     /**
      * Parse grammar rule: whileStmt      → "while" "(" expression ")" statement ;
      */
-    fn while_stmt(&self) -> Result<Stmt, ParseError> {
+    fn while_stmt(&self, label: Option<Token>) -> Result<Stmt, ParseError> {
+        let start = label.as_ref().unwrap_or(self.previous()).offset;
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after while condition.")?;
 
         let body = self.statement()?;
 
-        Ok(wstmt(condition, body))
+        Ok(wstmt(condition, body, None, label, self.span_from(start)))
+    }
+
+    /**
+     * Parse grammar rule: breakStmt      → "break" IDENTIFIER? ";" ;
+     *                     continueStmt   → "continue" IDENTIFIER? ";" ;
+     */
+    fn jump_stmt(&self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let label = if self.match_token(&[TokenType::Identifier]) {
+            Some(self.previous().clone())
+        } else {
+            None
+        };
+
+        self.consume(
+            TokenType::Semicolon,
+            format!("Expect ';' after '{}'.", keyword.lexeme).as_str(),
+        )?;
+
+        let span = self.span_from(keyword.offset);
+        Ok(match keyword.token_type {
+            TokenType::Break => Stmt::Break(keyword.clone(), label, span),
+            _ => Stmt::Continue(keyword.clone(), label, span),
+        })
     }
 
     /**
@@ -200,21 +374,27 @@ impl<'a> Parser<'a> {
      */
     fn return_stmt(&self) -> Result<Stmt, ParseError> {
         let token = self.previous();
-        let mut return_expr = lexpr(Literal::Nil);
+        let mut return_expr = lexpr(Literal::Nil, token.span());
 
-        if !self.match_token(vec![TokenType::Semicolon]) {
+        if !self.check(TokenType::Semicolon) {
             return_expr = self.expression()?;
         }
 
         self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
 
-        Ok(Stmt::Return(token.clone(), return_expr))
+        Ok(Stmt::Return(
+            token.clone(),
+            return_expr,
+            self.span_from(token.offset),
+        ))
     }
 
     /** Parse gramma rule: funDecl        → "fun" function ;
-     *                     function       → IDENTIFIER "(" parameters? ")" block ;
+     *                     function       → IDENTIFIER "(" parameters? ")" functionBody ;
+     *                     functionBody   → block | "=>" expression ";" ;
      */
     fn fun_decl_stmt(&self, kind: &str) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
         let name = self.consume(
             TokenType::Identifier,
             format!("Expect {} name.", kind).as_str(),
@@ -224,8 +404,61 @@ impl<'a> Parser<'a> {
             TokenType::LeftParen,
             format!("Expect '(' after {} name.", kind).as_str(),
         )?;
+        let (parameters, defaults) = self.parameters()?;
+
+        let body = if self.match_token(&[TokenType::Arrow]) {
+            let body = self.concise_body()?;
+            self.consume(TokenType::Semicolon, "Expect ';' after function body.")?;
+            body
+        } else {
+            self.block_body(kind)?
+        };
+
+        Ok(fstmt(
+            name.clone(),
+            parameters,
+            defaults,
+            body,
+            self.span_from(start),
+        ))
+    }
 
+    /**
+     * Parse grammar rule: lambda         → "fun" "(" parameters? ")" lambdaBody ;
+     *                     lambdaBody     → block | "=>" expression ;
+     *
+     * A concise body ends with its expression, the statement around the
+     * lambda has the ';'.
+     */
+    fn lambda_expr(&self) -> Result<Expr, ParseError> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+        let (parameters, defaults) = self.parameters()?;
+
+        let body = if self.match_token(&[TokenType::Arrow]) {
+            self.concise_body()?
+        } else {
+            self.block_body("function")?
+        };
+
+        Ok(Expr::Lambda(
+            keyword.clone(),
+            parameters,
+            Rc::from(defaults),
+            Rc::new(body),
+            self.span_from(keyword.offset),
+        ))
+    }
+
+    /**
+     * Parse grammar rule: parameters     → parameter ( "," parameter )* ;
+     *                     parameter      → IDENTIFIER ( "=" expression )? ;
+     *
+     * Also consumes the ')' after them.
+     */
+    fn parameters(&self) -> Result<(Vec<Token>, Vec<Option<Expr>>), ParseError> {
         let mut parameters = vec![];
+        let mut defaults = vec![];
 
         if !self.check(TokenType::RightParen) {
             loop {
@@ -233,37 +466,63 @@ impl<'a> Parser<'a> {
                     self.error(self.peek(), "Can't have more than 255 parameters");
                 }
 
-                parameters.push(
-                    self.consume(TokenType::Identifier, "Expect parameter name.")?
-                        .clone(),
-                );
+                let parameter = self.consume(TokenType::Identifier, "Expect parameter name.")?;
+
+                let default = if self.match_token(&[TokenType::Equal]) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                // Omitted arguments are filled from the end
+                if default.is_none() && defaults.iter().any(Option::is_some) {
+                    self.error(
+                        parameter,
+                        "Parameters without a default can't follow ones with a default.",
+                    );
+                }
+
+                parameters.push(parameter.clone());
+                defaults.push(default);
 
-                if !self.match_token(vec![TokenType::Comma]) {
+                if !self.match_token(&[TokenType::Comma]) {
                     break;
                 }
             }
         }
 
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok((parameters, defaults))
+    }
 
-        self.consume(
+    // A concise body, after its '=>', is a block that returns its expression.
+    // Both get the same span, which tells them from a block written in the
+    // source.
+    fn concise_body(&self) -> Result<Stmt, ParseError> {
+        let arrow = self.previous().clone();
+        let value = self.expression()?;
+        let span = self.span_from(arrow.offset);
+        Ok(Stmt::Block(vec![Stmt::Return(arrow, value, span)], span))
+    }
+
+    fn block_body(&self, kind: &str) -> Result<Stmt, ParseError> {
+        let brace = self.consume(
             TokenType::LeftBrace,
-            format!("Expect '{{' to start {} body.", kind).as_str(),
+            format!("Expect '{{' or '=>' to start {} body.", kind).as_str(),
         )?;
-
         let body = self.block()?;
-
-        Ok(fstmt(name.clone(), parameters, Stmt::Block(body)))
+        Ok(Stmt::Block(body, self.span_from(brace.offset)))
     }
 
     /**
      * Parse grammar rule: varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
      */
     fn var_decl_stmt(&self) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
         let token = self.consume(TokenType::Identifier, "Expect variable name.")?;
         let mut expr = None;
 
-        if self.match_token(vec![TokenType::Equal]) {
+        if self.match_token(&[TokenType::Equal]) {
             expr = Some(self.expression()?);
         }
 
@@ -272,17 +531,46 @@ impl<'a> Parser<'a> {
             "Expect ';' after variable declaration.",
         )?;
 
-        Ok(vdstmt(token.clone(), expr))
+        Ok(vdstmt(token.clone(), expr, self.span_from(start)))
+    }
+
+    /**
+     * Parse grammar rule: constDecl      → "const" IDENTIFIER "=" expression ";" ;
+     */
+    fn const_decl_stmt(&self) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
+        let token = self.consume(TokenType::Identifier, "Expect constant name.")?;
+
+        if !self.match_token(&[TokenType::Equal]) {
+            return Err(ParseError {
+                token: token.clone(),
+                message: format!("Constant '{}' must be initialized.", token.lexeme),
+            });
+        }
+
+        let expr = self.expression()?;
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after constant declaration.",
+        )?;
+
+        Ok(Stmt::ConstDeclaration(
+            token.clone(),
+            expr,
+            self.span_from(start),
+        ))
     }
 
     /**
      * Parse grammar rule: printStmt      → "print" expression ";" ;
      */
     fn print_stmt(&self) -> Result<Stmt, ParseError> {
+        let start = self.previous().offset;
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
 
-        Ok(pstmt(value))
+        Ok(pstmt(value, self.span_from(start)))
     }
 
     /**
@@ -292,7 +580,8 @@ impl<'a> Parser<'a> {
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
 
-        Ok(estmt(expr))
+        let span = self.span_from(expr.span().start);
+        Ok(estmt(expr, span))
     }
 
     /**
@@ -303,18 +592,28 @@ impl<'a> Parser<'a> {
     }
 
     /**
-    * Parse grammar rule: assignment     → IDENTIFIER "=" assignment
-                                            | equality ;
-    */
+     * Parse grammar rule: assignment     → IDENTIFIER "=" assignment
+     *                                      | equality ;
+     */
     fn assignment(&self) -> Result<Expr, ParseError> {
-        let expr = self.or()?;
+        let expr = self.pipe()?;
 
-        if self.match_token(vec![TokenType::Equal]) {
+        if self.match_token(&[TokenType::Equal]) {
             let equals = self.previous();
             let value = self.assignment()?;
 
             return match expr {
-                Expr::VariableExpr(ref token) => Ok(aexpr(token.clone(), value)),
+                Expr::VariableExpr(ref token, _) => Ok(aexpr(token.clone(), value)),
+                Expr::Index(object, bracket, index, span) => {
+                    let span = span.to(value.span());
+                    Ok(Expr::SetIndex(
+                        object,
+                        bracket,
+                        index,
+                        Box::new(value),
+                        span,
+                    ))
+                }
                 _ => Err(ParseError {
                     token: equals.clone(),
                     message: "Invalid assignment target.".to_string(),
@@ -326,12 +625,70 @@ impl<'a> Parser<'a> {
     }
 
     /**
-     * Parse grammar rule: logic_or       → logic_and ( "or" logic_and )* ;
+     * Parse grammar rule: pipe           → coalesce ( "|>" coalesce )* ;
+     *
+     * `x |> f` is the call `f(x)`, and `x |> f(y)` is `f(x, y)`: the left side
+     * becomes the first argument, so `x |> (f(y))` is how to call what `f(y)`
+     * returns. The call keeps the `|>` as its paren, so errors point at it and
+     * the formatter can print the pipe back.
+     */
+    fn pipe(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.coalesce()?;
+
+        while self.match_token(&[TokenType::PipeGreater]) {
+            let pipe = self.previous().clone();
+            let right = self.coalesce()?;
+            let span = expr.span().to(right.span());
+
+            expr = match right {
+                Expr::Call(callee, _, mut args, named, _) => {
+                    args.insert(0, expr);
+                    Expr::Call(callee, pipe, args, named, span)
+                }
+                callee => Expr::Call(Box::new(callee), pipe, vec![expr], vec![], span),
+            };
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: coalesce       → logic_or ( "??" logic_or )* ;
+     *
+     * `a ?? b` is `b` only when `a` is nil, unlike `or`, which also skips
+     * `false`.
+     */
+    fn coalesce(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.or()?;
+
+        while self.match_token(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous();
+            let right = self.or()?;
+            expr = lgexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: logic_or       → logic_xor ( "or" logic_xor )* ;
      */
     fn or(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.xor()?;
+
+        while self.match_token(&[TokenType::Or]) {
+            let operator = self.previous();
+            let right = self.xor()?;
+            expr = lgexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: logic_xor      → logic_and ( "xor" logic_and )* ;
+     */
+    fn xor(&self) -> Result<Expr, ParseError> {
         let mut expr = self.and()?;
 
-        while self.match_token(vec![TokenType::Or]) {
+        while self.match_token(&[TokenType::Xor]) {
             let operator = self.previous();
             let right = self.and()?;
             expr = lgexpr(expr, operator.clone(), right);
@@ -345,7 +702,7 @@ impl<'a> Parser<'a> {
     fn and(&self) -> Result<Expr, ParseError> {
         let mut expr = self.equality()?;
 
-        while self.match_token(vec![TokenType::And]) {
+        while self.match_token(&[TokenType::And]) {
             let operator = self.previous();
             let right = self.equality()?;
             expr = lgexpr(expr, operator.clone(), right);
@@ -359,7 +716,7 @@ impl<'a> Parser<'a> {
     fn equality(&self) -> Result<Expr, ParseError> {
         let mut expr = self.comparison()?;
 
-        while self.match_token(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
+        while self.match_token(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
             let right = self.comparison()?;
             expr = bexpr(expr, operator.clone(), right);
@@ -368,20 +725,105 @@ impl<'a> Parser<'a> {
     }
 
     /**
-     * Parse grammar rule: comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+     * Parse grammar rule: comparison     → range ( ( ">" | ">=" | "<" | "<=" ) range )? ;
+     *
+     * `1 < x < 10` would compare a boolean to a number, so a second comparison
+     * operator is an error instead.
      */
     fn comparison(&self) -> Result<Expr, ParseError> {
-        let mut expr = self.term()?;
-
-        while self.match_token(vec![
+        const OPERATORS: [TokenType; 4] = [
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
-        ]) {
+        ];
+
+        let mut expr = self.range()?;
+
+        if self.match_token(&OPERATORS) {
+            let operator = self.previous();
+            let right = self.range()?;
+            expr = bexpr(expr, operator.clone(), right);
+
+            if self.match_token(&OPERATORS) {
+                return Err(ParseError {
+                    token: self.previous().clone(),
+                    message: "Chained comparison is not allowed; use 'and'.".to_string(),
+                });
+            }
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: range          → bit_or ( ( ".." | "..=" ) bit_or )? ;
+     */
+    fn range(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_or()?;
+
+        if self.match_token(&[TokenType::DotDot, TokenType::DotDotEqual]) {
+            let operator = self.previous();
+            let right = self.bit_or()?;
+            expr = bexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: bit_or         → bit_xor ( "|" bit_xor )* ;
+     *
+     * Bitwise operators bind tighter than comparisons, unlike C's, so
+     * `flags & MASK == 0` compares the masked flags.
+     */
+    fn bit_or(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_xor()?;
+
+        while self.match_token(&[TokenType::Pipe]) {
+            let operator = self.previous();
+            let right = self.bit_xor()?;
+            expr = bexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: bit_xor        → bit_and ( "^" bit_and )* ;
+     */
+    fn bit_xor(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_and()?;
+
+        while self.match_token(&[TokenType::Caret]) {
+            let operator = self.previous();
+            let right = self.bit_and()?;
+            expr = bexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: bit_and        → shift ( "&" shift )* ;
+     */
+    fn bit_and(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.shift()?;
+
+        while self.match_token(&[TokenType::Ampersand]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = bexpr(expr, operator.clone(), right);
+        }
+        Ok(expr)
+    }
+
+    /**
+     * Parse grammar rule: shift          → term ( ( "<<" | ">>" ) term )* ;
+     */
+    fn shift(&self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+
+        while self.match_token(&[TokenType::LessLess, TokenType::GreaterGreater]) {
             let operator = self.previous();
             let right = self.term()?;
-            expr = bexpr(expr, operator.clone(), right)
+            expr = bexpr(expr, operator.clone(), right);
         }
         Ok(expr)
     }
@@ -392,7 +834,7 @@ impl<'a> Parser<'a> {
     fn term(&self) -> Result<Expr, ParseError> {
         let mut expr = self.factor()?;
 
-        while self.match_token(vec![TokenType::Minus, TokenType::Plus]) {
+        while self.match_token(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
             let right = self.factor()?;
             expr = bexpr(expr, operator.clone(), right);
@@ -406,7 +848,7 @@ impl<'a> Parser<'a> {
     fn factor(&self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
 
-        while self.match_token(vec![TokenType::Slash, TokenType::Star]) {
+        while self.match_token(&[TokenType::Slash, TokenType::Star]) {
             let operator = self.previous();
             let right = self.unary()?;
             expr = bexpr(expr, operator.clone(), right);
@@ -415,27 +857,55 @@ impl<'a> Parser<'a> {
     }
 
     /**
-     * Parse grammar rule: unary          → ( "!" | "-" ) unary
-     *                                      | primary ;
+     * Parse grammar rule: unary          → ( "!" | "-" | "~" ) unary
+     *                                      | power ;
      */
     fn unary(&self) -> Result<Expr, ParseError> {
-        if self.match_token(vec![TokenType::Bang, TokenType::Minus]) {
+        if self.match_token(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let operator = self.previous();
             let right = self.unary()?;
             return Ok(uexpr(operator.clone(), right));
         }
-        self.call()
+        self.power()
+    }
+
+    /**
+     * Parse grammar rule: power          → call ( "**" unary )? ;
+     *
+     * Right-associative, and binds tighter than a unary operator on its left,
+     * so `-2 ** 2` is `-(2 ** 2)`.
+     */
+    fn power(&self) -> Result<Expr, ParseError> {
+        let expr = self.call()?;
+
+        if self.match_token(&[TokenType::StarStar]) {
+            let operator = self.previous();
+            let right = self.unary()?;
+            return Ok(bexpr(expr, operator.clone(), right));
+        }
+        Ok(expr)
     }
 
     /**
-     * Parse grammar rule: call           → primary ( "(" arguments? ")" )* ;z
+     * Parse grammar rule: call           → primary ( "(" arguments? ")" | "[" expression "]"
+     *                                              | "." IDENTIFIER )* ;
      */
     fn call(&self) -> Result<Expr, ParseError> {
         let mut expr = self.primary()?;
 
         loop {
-            if self.match_token(vec![TokenType::LeftParen]) {
+            if self.match_token(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                let span = expr.span().to(bracket.span());
+                expr = Expr::Index(Box::new(expr), bracket.clone(), Box::new(index), span);
+            } else if self.match_token(&[TokenType::Dot]) {
+                let name =
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                let span = expr.span().to(name.span());
+                expr = Expr::Get(Box::new(expr), name.clone(), span);
             } else {
                 break;
             }
@@ -445,73 +915,105 @@ impl<'a> Parser<'a> {
     }
 
     /**
-     * Parse grammar rule: arguments      → expression ( "," expression )* ;
+     * Parse grammar rule: arguments      → argument ( "," argument )* ;
+     *                     argument       → ( IDENTIFIER ":" )? expression ;
+     *
+     * Named arguments may only follow positional ones.
      */
     fn finish_call(&self, callee: Expr) -> Result<Expr, ParseError> {
         let mut args = vec![];
+        let mut named: Vec<(Token, Expr)> = vec![];
 
         if !self.check(TokenType::RightParen) {
             loop {
-                if args.len() > 255 {
+                if args.len() + named.len() > 255 {
                     return Err(ParseError {
                         token: self.peek().clone(),
                         message: "Can't have more than 255 arguments.".to_string(),
                     });
                 }
 
-                args.push(self.expression()?);
+                if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+                    let name = self.advance().clone();
+                    self.advance();
+                    named.push((name, self.expression()?));
+                } else if !named.is_empty() {
+                    return Err(ParseError {
+                        token: self.peek().clone(),
+                        message: "Positional arguments must come before named arguments."
+                            .to_string(),
+                    });
+                } else {
+                    args.push(self.expression()?);
+                }
 
-                if !self.match_token(vec![TokenType::Comma]) {
+                if !self.match_token(&[TokenType::Comma]) {
                     break;
                 }
             }
         }
 
         let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
-        Ok(cexpr(callee, paren.clone(), args))
+        Ok(cexpr(callee, paren.clone(), args, named))
     }
 
     /**
      * Parse grammer rule: primary        → NUMBER | STRING | "true" | "false" | "nil"
      *                                      | "(" expression ")"
-     *                                      | IDENTIFIER ;
+     *                                      | list | map | blockExpr
+     *                                      | lambda | IDENTIFIER ;
      */
     fn primary(&self) -> Result<Expr, ParseError> {
-        if self.match_token(vec![
+        if self.match_token(&[
             TokenType::True,
             TokenType::False,
             TokenType::Nil,
             TokenType::Number,
             TokenType::String,
             TokenType::LeftParen,
+            TokenType::LeftBrace,
+            TokenType::LeftBracket,
             TokenType::Identifier,
+            TokenType::Fun,
         ]) {
             let previous = self.previous();
 
             match previous.token_type {
                 TokenType::True => {
-                    return Ok(lexpr(Literal::Boolean(true)));
+                    return Ok(lexpr(Literal::Boolean(true), previous.span()));
                 }
                 TokenType::False => {
-                    return Ok(lexpr(Literal::Boolean(false)));
+                    return Ok(lexpr(Literal::Boolean(false), previous.span()));
                 }
                 TokenType::Nil => {
-                    return Ok(lexpr(Literal::Nil));
-                }
-                TokenType::String | TokenType::Number => {
-                    let literal = previous.literal.clone();
-                    return Ok(lexpr(literal.unwrap()));
+                    return Ok(lexpr(Literal::Nil, previous.span()));
                 }
+                TokenType::String | TokenType::Number => match &previous.literal {
+                    Some(literal) => return Ok(lexpr(literal.clone(), previous.span())),
+                    None => {
+                        return Err(ParseError {
+                            token: previous.clone(),
+                            message: "Expect a literal value.".to_string(),
+                        })
+                    }
+                },
                 TokenType::Identifier => return Ok(vexpr(previous.clone())),
                 TokenType::LeftParen => {
                     let expr = self.expression()?;
+                    self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
 
-                    // Panics if unwraps on Err
-                    self.consume(TokenType::RightParen, "Expect ')' after expression.")
-                        .unwrap();
-
-                    return Ok(gexpr(expr));
+                    return Ok(gexpr(expr, self.span_from(previous.offset)));
                 }
+                TokenType::LeftBracket => return self.list_expr(),
+                TokenType::Fun => return self.lambda_expr(),
+                // A '{' followed by '}' or by a string and a ':' starts a map
+                TokenType::LeftBrace
+                    if self.check(TokenType::RightBrace)
+                        || (self.check(TokenType::String) && self.check_next(TokenType::Colon)) =>
+                {
+                    return self.map_expr()
+                }
+                TokenType::LeftBrace => return self.block_expr(),
                 _ => {}
             }
         }
@@ -522,9 +1024,118 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn match_token(&self, types: Vec<TokenType>) -> bool {
+    /**
+     * Parse grammar rule: list           → "[" ( expression ( "," expression )* )? "]" ;
+     */
+    fn list_expr(&self) -> Result<Expr, ParseError> {
+        let bracket = self.previous().clone();
+        let mut elements = vec![];
+
+        if !self.check(TokenType::RightBracket) {
+            loop {
+                elements.push(self.expression()?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+        let span = self.span_from(bracket.offset);
+        Ok(Expr::List(bracket, elements, span))
+    }
+
+    /**
+     * Parse grammar rule: map            → "{" ( entry ( "," entry )* )? "}" ;
+     *                     entry          → STRING ":" expression ;
+     *
+     * `{}` is an empty map, not an empty block expression.
+     */
+    fn map_expr(&self) -> Result<Expr, ParseError> {
+        let brace = self.previous().clone();
+        let mut entries = vec![];
+
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let key = self
+                    .consume(TokenType::String, "Expect string as map key.")?
+                    .clone();
+                self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                entries.push((key, self.expression()?));
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+        let span = self.span_from(brace.offset);
+        Ok(Expr::Map(brace, entries, span))
+    }
+
+    /**
+     * Parse grammar rule: blockExpr      → "{" declaration* expression? "}" ;
+     *
+     * A block in statement position is a statement, so block expressions
+     * only start where an expression is expected, as in `var x = { ... };`.
+     */
+    fn block_expr(&self) -> Result<Expr, ParseError> {
+        use crate::ast::tokentype::TokenType::*;
+
+        let start = self.previous().offset;
+        let mut stmts = vec![];
+        let mut value = None;
+
+        while !self.check(RightBrace) && !self.is_at_end() {
+            // Statements, labeled loops included, but not anonymous functions
+            if matches!(
+                self.peek().token_type,
+                Var | Const
+                    | Print
+                    | If
+                    | While
+                    | For
+                    | Return
+                    | Break
+                    | Continue
+                    | Switch
+                    | LeftBrace
+            ) || (self.check(Identifier) && self.check_next(Colon))
+                || (self.check(Fun) && !self.check_next(LeftParen))
+            {
+                stmts.push(self.declaration()?);
+                continue;
+            }
+
+            let expr = self.expression()?;
+
+            // An expression without a ';' before the '}' is the block's value
+            if self.check(RightBrace) {
+                value = Some(Box::new(expr));
+                break;
+            }
+
+            self.consume(Semicolon, "Expect ';' after value.")?;
+            let span = self.span_from(expr.span().start);
+            stmts.push(estmt(expr, span));
+        }
+
+        self.consume(RightBrace, "Expect '}' after block.")?;
+
+        Ok(Expr::Block(stmts, value, self.span_from(start)))
+    }
+
+    // The span from `start`, where a rule's first token is, to the end of the
+    // last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.previous().span().end)
+    }
+
+    fn match_token(&self, types: &[TokenType]) -> bool {
         for token_type in types {
-            if self.check(token_type) {
+            if self.check(*token_type) {
                 self.advance();
                 return true;
             }
@@ -551,6 +1162,9 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // Skips tokens until the start of the next statement: after a ';', or
+    // before a keyword that starts a statement, or a brace that starts or
+    // ends a block
     fn synchronize(&self) {
         use crate::ast::tokentype::TokenType::*;
         self.advance();
@@ -561,7 +1175,8 @@ impl<'a> Parser<'a> {
             }
 
             match self.peek().token_type {
-                Class | Fun | Var | For | If | While | Print | Return => {
+                Class | Fun | Var | Const | For | If | While | Print | Return | Break
+                | Continue | Switch | LeftBrace | RightBrace => {
                     return;
                 }
                 _ => {
@@ -578,6 +1193,13 @@ impl<'a> Parser<'a> {
         self.peek().token_type == token_type
     }
 
+    fn check_next(&self, token_type: TokenType) -> bool {
+        match self.tokens.get(self._current.get() + 1) {
+            Some(token) => token.token_type == token_type,
+            None => false,
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
@@ -587,7 +1209,9 @@ impl<'a> Parser<'a> {
     }
 
     fn previous(&self) -> &Token {
-        self.tokens.get(self._current.get() - 1).unwrap()
+        self.tokens
+            .get(self._current.get().saturating_sub(1))
+            .unwrap()
     }
 
     fn error(&self, token: &Token, message: &str) {
@@ -599,3 +1223,76 @@ impl<'a> Parser<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn malformed_tokens_are_errors_not_panics() {
+        assert!(Parser::new(vec![]).parse().is_empty());
+
+        let reporter = ErrorReporter::new();
+        let mut parser = Parser::new(vec![
+            Token::new(TokenType::Print, "print", None, 1),
+            Token::new(TokenType::Number, "1", None, 1),
+        ]);
+        parser.set_error_reporter(&reporter);
+        parser.parse();
+
+        let diagnostics = reporter.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Expect a literal value.");
+    }
+
+    #[test]
+    fn statements_are_parsed_one_at_a_time() {
+        let reporter = ErrorReporter::new();
+        let mut parser = Parser::new(Scanner::new("print 1; var = 2; print 3;").collect());
+        parser.set_error_reporter(&reporter);
+        let mut statements = parser.statements();
+
+        assert!(matches!(statements.next(), Some(Stmt::Print(..))));
+        assert!(!reporter.has_error());
+
+        assert!(matches!(statements.next(), Some(Stmt::Print(..))));
+        assert_eq!(reporter.diagnostics()[0].message, "Expect variable name.");
+        assert!(statements.next().is_none());
+    }
+
+    #[test]
+    fn spans_cover_the_parsed_text() {
+        let source =
+            "var total = (1 + 2) * f(x, [3]);\nif (total > 0) {\n  print \"é\" + total;\n}\n";
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        let text = |span: Span| span.text(source);
+
+        assert_eq!(
+            text(statements[0].span()),
+            "var total = (1 + 2) * f(x, [3]);"
+        );
+        let Stmt::VarDeclaration(_, Some(ref initializer), _) = statements[0] else {
+            panic!("expected a variable declaration");
+        };
+        assert_eq!(text(initializer.span()), "(1 + 2) * f(x, [3])");
+        let Expr::BinaryExpr(ref left, _, ref right, _) = *initializer else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(text(left.span()), "(1 + 2)");
+        assert_eq!(text(right.span()), "f(x, [3])");
+
+        assert_eq!(
+            text(statements[1].span()),
+            "if (total > 0) {\n  print \"é\" + total;\n}"
+        );
+        let Stmt::If(ref condition, ref then_branch, _, _) = statements[1] else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(text(condition.span()), "total > 0");
+        let Stmt::Block(ref body, _) = **then_branch else {
+            panic!("expected a block");
+        };
+        assert_eq!(text(body[0].span()), "print \"é\" + total;");
+    }
+}
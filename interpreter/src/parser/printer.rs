@@ -1,8 +1,21 @@
-use crate::ast::expr::{Expr, Visitor};
+use crate::ast::expr::{Expr, Visitor as ExprVisitor};
+use crate::ast::stmt::{Stmt, Visitor as StmtVisitor};
 use crate::ast::token::Token;
 use crate::ast::tokentype::Literal;
-use crate::error::RuntimeError;
+use crate::error::{RuntimeError, Signal};
+use std::rc::Rc;
 
+/// Prints the AST as s-expressions.
+///
+/// Expressions print on a single line, e.g. `(* (group (+ 1 2)) 3)`.
+/// Statements print one per line and their nested statements go on the
+/// following lines, indented by two spaces:
+///
+/// ```text
+/// (fun add (a b)
+///   (block
+///     (return (+ a b))))
+/// ```
 pub struct AstPrinter;
 impl AstPrinter {
     pub fn print(&mut self, expr: &Expr) -> String {
@@ -13,7 +26,15 @@ impl AstPrinter {
         "".to_string()
     }
 
-    fn parenthesize(&mut self, name: &String, expr: Vec<&Expr>) -> Result<String, RuntimeError> {
+    pub fn print_stmts(&mut self, stmts: &[Stmt]) -> String {
+        stmts
+            .iter()
+            .filter_map(|stmt| stmt.accept(self).ok())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn parenthesize(&mut self, name: &str, expr: Vec<&Expr>) -> Result<String, RuntimeError> {
         let mut result = String::new();
         result.push('(');
         result.push_str(name);
@@ -27,8 +48,55 @@ impl AstPrinter {
         result.push(')');
         Ok(result)
     }
+
+    fn nest(&mut self, head: String, stmts: Vec<&Stmt>) -> Result<String, Signal> {
+        let mut children = vec![];
+        for stmt in stmts {
+            children.push(stmt.accept(self)?);
+        }
+        Ok(Self::nest_lines(head, children))
+    }
+
+    fn nest_lines(head: String, children: Vec<String>) -> String {
+        let mut result = format!("({}", head);
+        for child in children {
+            for line in child.lines() {
+                result.push_str("\n  ");
+                result.push_str(line);
+            }
+        }
+        result.push(')');
+        result
+    }
+
+    fn parameters(
+        &mut self,
+        parameters: &[Token],
+        defaults: &[Option<Expr>],
+    ) -> Result<String, RuntimeError> {
+        let mut printed = vec![];
+        for (parameter, default) in parameters.iter().zip(defaults.iter()) {
+            printed.push(match default {
+                Some(default) => format!("(= {} {})", parameter.lexeme, default.accept(self)?),
+                None => parameter.lexeme.to_string(),
+            });
+        }
+        Ok(printed.join(" "))
+    }
+
+    // Before the head of a labeled loop
+    fn label(label: Option<&Token>) -> String {
+        label.map_or(String::new(), |label| format!("{}: ", label.lexeme))
+    }
+
+    fn jump(keyword: &str, label: Option<&Token>) -> String {
+        match label {
+            Some(label) => format!("({} {})", keyword, label.lexeme),
+            None => format!("({})", keyword),
+        }
+    }
 }
-impl Visitor<String> for AstPrinter {
+impl ExprVisitor<String> for AstPrinter {
     fn visit_binary_expr(
         &mut self,
         left: &Expr,
@@ -50,11 +118,7 @@ impl Visitor<String> for AstPrinter {
     }
 
     fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, RuntimeError> {
-        if let Expr::GroupingExpr(ref expression) = expr {
-            self.parenthesize(&"group".to_string(), vec![expression])
-        } else {
-            panic!("Expected GroupingExpr")
-        }
+        self.parenthesize("group", vec![expr])
     }
 
     fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<String, RuntimeError> {
@@ -62,7 +126,7 @@ impl Visitor<String> for AstPrinter {
     }
 
     fn visit_variable_expr(&mut self, identifier: &Token) -> Result<String, RuntimeError> {
-        todo!()
+        Ok(identifier.lexeme.to_string())
     }
 
     fn visit_assign_expr(
@@ -70,6 +134,392 @@ impl Visitor<String> for AstPrinter {
         identifier: &Token,
         value: &Expr,
     ) -> Result<String, RuntimeError> {
-        todo!()
+        self.parenthesize(&format!("= {}", identifier.lexeme), vec![value])
+    }
+
+    fn visit_logical_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.parenthesize(&operator.lexeme, vec![left, right])
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        _paren: &Token,
+        args: &[Expr],
+        named: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let mut exprs = vec![callee];
+        exprs.extend(args);
+        let mut call = self.parenthesize("call", exprs)?;
+
+        call.pop();
+        for (name, value) in named {
+            call.push_str(&format!(" {}: {}", name.lexeme, value.accept(self)?));
+        }
+        call.push(')');
+        Ok(call)
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        stmts: &[Stmt],
+        value: Option<&Expr>,
+    ) -> Result<String, RuntimeError> {
+        // Expressions print on one line, so nested statements do too
+        let mut result = "(block".to_string();
+        for printed in stmts.iter().filter_map(|stmt| stmt.accept(self).ok()) {
+            for line in printed.lines() {
+                result.push(' ');
+                result.push_str(line.trim_start());
+            }
+        }
+        if let Some(value) = value {
+            result.push(' ');
+            result.push_str(&value.accept(self)?);
+        }
+        result.push(')');
+        Ok(result)
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keyword: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<String, RuntimeError> {
+        let mut result = format!("(fun ({})", self.parameters(parameters, defaults)?);
+        if let Ok(printed) = body.accept(self) {
+            for line in printed.lines() {
+                result.push(' ');
+                result.push_str(line.trim_start());
+            }
+        }
+        result.push(')');
+        Ok(result)
+    }
+
+    fn visit_list_expr(
+        &mut self,
+        _bracket: &Token,
+        elements: &[Expr],
+    ) -> Result<String, RuntimeError> {
+        self.parenthesize("list", elements.iter().collect())
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let mut result = "(map".to_string();
+        for (key, value) in entries {
+            result.push_str(&format!(" {}: {}", key.lexeme, value.accept(self)?));
+        }
+        result.push(')');
+        Ok(result)
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.parenthesize("index", vec![object, index])
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<String, RuntimeError> {
+        let target = self.parenthesize("index", vec![object, index])?;
+        Ok(format!("(= {} {})", target, value.accept(self)?))
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<String, RuntimeError> {
+        Ok(format!("(. {} {})", object.accept(self)?, name.lexeme))
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<String, Signal> {
+        Ok(self.parenthesize("print", vec![expr])?)
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<String, Signal> {
+        Ok(self.parenthesize("expr", vec![expr])?)
+    }
+
+    fn visit_var_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: Option<&Expr>,
+    ) -> Result<String, Signal> {
+        let name = format!("var {}", identifier.lexeme);
+        Ok(self.parenthesize(&name, initializer.into_iter().collect())?)
+    }
+
+    fn visit_const_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: &Expr,
+    ) -> Result<String, Signal> {
+        let name = format!("const {}", identifier.lexeme);
+        Ok(self.parenthesize(&name, vec![initializer])?)
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<String, Signal> {
+        self.nest("block".to_string(), stmts.iter().collect())
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt_then: &Stmt,
+        stmt_else: &Option<Box<Stmt>>,
+    ) -> Result<String, Signal> {
+        let head = format!("if {}", expr.accept(self)?);
+        let mut branches = vec![stmt_then];
+        branches.extend(stmt_else.as_deref());
+        self.nest(head, branches)
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt: &Stmt,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        let head = format!("{}while {}", Self::label(label), expr.accept(self)?);
+        let mut children = vec![stmt.accept(self)?];
+        if let Some(increment) = increment {
+            children.push(self.parenthesize("increment", vec![increment])?);
+        }
+        Ok(Self::nest_lines(head, children))
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        identifier: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        let head = format!(
+            "{}for {} in {}",
+            Self::label(label),
+            identifier.lexeme,
+            iterable.accept(self)?
+        );
+        self.nest(head, vec![body])
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        identifier: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<String, Signal> {
+        let parameters = self.parameters(parameters, defaults)?;
+        let head = format!("fun {} ({})", identifier.lexeme, parameters);
+        self.nest(head, vec![body.as_ref()])
+    }
+
+    fn visit_return_stmt(&mut self, _token: &Token, expr: &Expr) -> Result<String, Signal> {
+        Ok(self.parenthesize("return", vec![expr])?)
+    }
+
+    fn visit_break_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        Ok(Self::jump("break", label))
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Signal> {
+        Ok(Self::jump("continue", label))
+    }
+
+    fn visit_switch_stmt(
+        &mut self,
+        expr: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: Option<&[Stmt]>,
+    ) -> Result<String, Signal> {
+        let mut children = vec![];
+        for (value, stmts) in cases {
+            let head = format!("case {}", value.accept(self)?);
+            children.push(self.nest(head, stmts.iter().collect())?);
+        }
+        if let Some(stmts) = default {
+            children.push(self.nest("default".to_string(), stmts.iter().collect())?);
+        }
+
+        let head = format!("switch {}", expr.accept(self)?);
+        Ok(Self::nest_lines(head, children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn print(source: &str) -> String {
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        AstPrinter.print_stmts(&statements)
+    }
+
+    #[test]
+    fn prints_expressions() {
+        assert_eq!(
+            print("a = -(1 + 2) * f(x, \"s\") or nil;"),
+            "(expr (= a (or (* (- (group (+ 1 2))) (call f x \"s\")) nil)))"
+        );
+    }
+
+    #[test]
+    fn xor_binds_between_or_and_and() {
+        assert_eq!(
+            print("a or b xor c and d xor e;"),
+            "(expr (or a (xor (xor b (and c d)) e)))"
+        );
+    }
+
+    #[test]
+    fn coalescing_binds_between_pipes_and_or() {
+        assert_eq!(print("a ?? b or c;"), "(expr (?? a (or b c)))");
+        assert_eq!(print("a or b ?? c;"), "(expr (?? (or a b) c))");
+        assert_eq!(print("a ?? b ?? c;"), "(expr (?? (?? a b) c))");
+        assert_eq!(print("a ?? b |> f;"), "(expr (call f (?? a b)))");
+    }
+
+    #[test]
+    fn exponents_are_right_associative_and_bind_tightly() {
+        assert_eq!(print("2 ** 3 ** 2;"), "(expr (** 2 (** 3 2)))");
+        assert_eq!(print("2 * 3 ** 2;"), "(expr (* 2 (** 3 2)))");
+        assert_eq!(print("-2 ** -f(x);"), "(expr (- (** 2 (- (call f x)))))");
+    }
+
+    #[test]
+    fn calls_and_gets_chain_left_to_right() {
+        assert_eq!(
+            print("a.b().c(1)[0].d;"),
+            "(expr (. (index (call (. (call (. a b)) c) 1) 0) d))"
+        );
+        assert_eq!(print("-a.b ** 2;"), "(expr (- (** (. a b) 2)))");
+    }
+
+    #[test]
+    fn named_arguments_follow_positional_ones() {
+        assert_eq!(
+            print("f(1, height: 2, width: x);"),
+            "(expr (call f 1 height: 2 width: x))"
+        );
+    }
+
+    #[test]
+    fn prints_parameter_defaults() {
+        assert_eq!(
+            print("fun f(a, b = 1 + 2) {}"),
+            "(fun f (a (= b (+ 1 2)))\n  (block))"
+        );
+    }
+
+    #[test]
+    fn concise_functions_return_their_expression() {
+        assert_eq!(
+            print("fun square(x) => x * x;"),
+            print("fun square(x) { return x * x; }")
+        );
+        assert_eq!(
+            print("map(xs, fun (x, y = 1) => x * y);"),
+            "(expr (call map xs (fun (x (= y 1)) (block (return (* x y))))))"
+        );
+        assert_eq!(
+            print("var f = fun () => 1;"),
+            print("var f = fun () { return 1; };")
+        );
+    }
+
+    #[test]
+    fn pipes_are_calls_with_the_left_side_first() {
+        assert_eq!(print("x |> f |> g(1);"), print("g(f(x), 1);"));
+        assert_eq!(print("a = x |> f;"), "(expr (= a (call f x)))");
+    }
+
+    #[test]
+    fn prints_switch_cases() {
+        assert_eq!(
+            print("switch (x) { case 1: print 1; print 2; default: }"),
+            "\
+(switch x
+  (case 1
+    (print 1)
+    (print 2))
+  (default))"
+        );
+    }
+
+    #[test]
+    fn block_expressions_print_on_one_line() {
+        assert_eq!(
+            print("var x = { var t = f(); if (t) print t; t * 2 };"),
+            "(var x (block (var t (call f)) (if t (print t)) (* t 2)))"
+        );
+    }
+
+    #[test]
+    fn prints_collections_and_indexing() {
+        assert_eq!(
+            print("m[\"k\"] = [1, {\"a\": x, \"b\": {}}][0];"),
+            "(expr (= (index m \"k\") (index (list 1 (map \"a\": x \"b\": (map))) 0)))"
+        );
+        assert_eq!(print("var b = { 1 };"), "(var b (block 1))");
+    }
+
+    #[test]
+    fn prints_statements_with_indentation() {
+        let source = "
+            var total;
+            fun add(a, b) { return a + b; }
+            if (true) print 1; else { print 2; }
+            for (var i = 0; i < 2; i = i + 1) total = add(total, i);
+        ";
+
+        assert_eq!(
+            print(source),
+            "\
+(var total)
+(fun add (a b)
+  (block
+    (return (+ a b))))
+(if true
+  (print 1)
+  (block
+    (print 2)))
+(block
+  (var i 0)
+  (while (< i 2)
+    (expr (= total (call add total i)))
+    (increment (= i (+ i 1)))))"
+        );
     }
 }
@@ -0,0 +1,208 @@
+use crate::ast::expr::{Expr, Visitor};
+use crate::ast::stmt::Stmt;
+use crate::ast::token::Token;
+use crate::ast::tokentype::{Literal, TokenType};
+use crate::error::RuntimeError;
+use std::rc::Rc;
+
+/// Prints expressions in reverse Polish notation, so `(1 + 2) * 3` becomes
+/// `1 2 + 3 *`. Unary minus prints as `neg` to tell it apart from
+/// subtraction, and calls print their callee and arguments followed by
+/// `call/n`, where `n` is how many arguments they pass, so `f(1, g(2))`
+/// becomes `f 1 g 2 call/1 call/2`. Named arguments print as their value
+/// followed by `name:`, and count as one argument each. Block
+/// expressions print only their value, followed by `block`. Lists and maps
+/// print their elements followed by `list` or `map`, map values followed by
+/// their `"key":`, and indexing prints as `index`, or `index=` when assigning.
+/// Anonymous functions print as `fun`.
+pub struct RpnPrinter;
+impl RpnPrinter {
+    pub fn print(&mut self, expr: &Expr) -> String {
+        if let Ok(value) = expr.accept(self) {
+            return value;
+        }
+
+        "".to_string()
+    }
+
+    fn postfix(&mut self, operator: &str, exprs: Vec<&Expr>) -> Result<String, RuntimeError> {
+        let mut parts = vec![];
+        for expr in exprs {
+            parts.push(expr.accept(self)?);
+        }
+        parts.push(operator.to_string());
+        Ok(parts.join(" "))
+    }
+}
+impl Visitor<String> for RpnPrinter {
+    fn visit_literal_expr(&mut self, literal: &Literal) -> Result<String, RuntimeError> {
+        let literal_string = match literal {
+            Literal::String(ref s) => format!("\"{}\"", s),
+            Literal::Number(ref n) => n.to_string(),
+            Literal::Nil => "nil".to_string(),
+            Literal::Boolean(ref b) => b.to_string(),
+        };
+
+        Ok(literal_string)
+    }
+
+    fn visit_binary_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.postfix(&operator.lexeme, vec![left, right])
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, RuntimeError> {
+        expr.accept(self)
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<String, RuntimeError> {
+        match operator.token_type {
+            TokenType::Minus => self.postfix("neg", vec![right]),
+            _ => self.postfix(&operator.lexeme, vec![right]),
+        }
+    }
+
+    fn visit_variable_expr(&mut self, identifier: &Token) -> Result<String, RuntimeError> {
+        Ok(identifier.lexeme.to_string())
+    }
+
+    fn visit_assign_expr(
+        &mut self,
+        identifier: &Token,
+        value: &Expr,
+    ) -> Result<String, RuntimeError> {
+        Ok(format!("{} {} =", value.accept(self)?, identifier.lexeme))
+    }
+
+    fn visit_logical_expr(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.postfix(&operator.lexeme, vec![left, right])
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        _paren: &Token,
+        args: &[Expr],
+        named: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let mut parts = vec![callee.accept(self)?];
+        for arg in args {
+            parts.push(arg.accept(self)?);
+        }
+        for (name, value) in named {
+            parts.push(format!("{} {}:", value.accept(self)?, name.lexeme));
+        }
+        parts.push(format!("call/{}", args.len() + named.len()));
+        Ok(parts.join(" "))
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        _stmts: &[Stmt],
+        value: Option<&Expr>,
+    ) -> Result<String, RuntimeError> {
+        match value {
+            Some(value) => self.postfix("block", vec![value]),
+            None => Ok("nil block".to_string()),
+        }
+    }
+
+    fn visit_list_expr(
+        &mut self,
+        _bracket: &Token,
+        elements: &[Expr],
+    ) -> Result<String, RuntimeError> {
+        self.postfix("list", elements.iter().collect())
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<String, RuntimeError> {
+        let mut parts = vec![];
+        for (key, value) in entries {
+            parts.push(format!("{} {}:", value.accept(self)?, key.lexeme));
+        }
+        parts.push("map".to_string());
+        Ok(parts.join(" "))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.postfix("index", vec![object, index])
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<String, RuntimeError> {
+        self.postfix("index=", vec![object, index, value])
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<String, RuntimeError> {
+        Ok(format!("{} {} .", object.accept(self)?, name.lexeme))
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keyword: &Token,
+        _parameters: &[Token],
+        _defaults: &Rc<[Option<Expr>]>,
+        _body: &Rc<Stmt>,
+    ) -> Result<String, RuntimeError> {
+        Ok("fun".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::stmt::Stmt;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn rpn(source: &str) -> String {
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        match &statements[0] {
+            Stmt::Expression(expr, _) => RpnPrinter.print(expr),
+            stmt => panic!("expected an expression statement, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn prints_postfix() {
+        assert_eq!(rpn("(1 + 2) * 3;"), "1 2 + 3 *");
+        assert_eq!(rpn("1 + 2 * 3;"), "1 2 3 * +");
+        assert_eq!(rpn("(1 - 2) - (3 - 4);"), "1 2 - 3 4 - -");
+        assert_eq!(rpn("-a / !b;"), "a neg b ! /");
+        assert_eq!(
+            rpn("x = a == nil or \"s\" != b and true;"),
+            "a nil == \"s\" b != true and or x ="
+        );
+        assert_eq!(rpn("f(1, g(2))(3);"), "f 1 g 2 call/1 call/2 3 call/1");
+        assert_eq!(rpn("f(g(1, 2));"), "f g 1 2 call/2 call/1");
+        assert_eq!(rpn("f(g(1), 2);"), "f g 1 call/1 2 call/2");
+        assert_eq!(rpn("f(1, to: x)();"), "f 1 x to: call/2 call/0");
+        assert_eq!(
+            rpn("a[0] = [1, {\"k\": 2}][1];"),
+            "a 0 1 2 \"k\": map list 1 index index="
+        );
+    }
+}
@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod resolver;
+
+pub use resolver::Resolver;
@@ -0,0 +1,1014 @@
+use crate::ast::expr::{Expr, Visitor as ExprVisitor};
+use crate::ast::span::Span;
+use crate::ast::stmt::{Stmt, Visitor as StmtVisitor};
+use crate::ast::token::Token;
+use crate::ast::tokentype::{Literal, TokenType};
+use crate::error::{ErrorReporter, RuntimeError, Signal};
+use crate::interpreter::{Arity, Interpreter};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+struct Variable {
+    slot: usize,
+    line: usize,
+    defined: bool,
+    constant: bool,
+}
+
+// A function declared with `fun`, which calls naming it are checked against
+struct KnownFunction {
+    arity: Arity,
+    // Assigned or declared again, so calls may not be to this function
+    reassigned: bool,
+    // The calls with the wrong number of arguments, reported when the scope
+    // ends if the variable still holds the function then
+    mismatches: Vec<(Token, Span, String)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+}
+
+/// Static pass run between parsing and interpreting. It binds every variable
+/// reference to the local scope that declares it, telling the interpreter the
+/// (depth, slot) to read it from, or leaves it to the globals.
+pub struct Resolver<'a, 'b> {
+    interpreter: &'a mut Interpreter<'b>,
+    scopes: Vec<HashMap<Rc<str>, Variable>>,
+    // The functions declared in the globals, then in each of `scopes`
+    functions: Vec<HashMap<Rc<str>, KnownFunction>>,
+    // The mismatched calls to functions whose scope ended
+    arity_errors: Vec<(Token, Span, String)>,
+    current_function: FunctionType,
+    // Inside a block expression of the current function, where `return`
+    // can't be used: expressions can't unwind the statements around them
+    in_block_expr: bool,
+    // The labels of the loops around the statement being resolved, innermost
+    // last, up to the enclosing function or block expression
+    loops: Vec<Option<Rc<str>>>,
+    warn_shadowing: bool,
+    warn_unreachable: bool,
+    // The node being resolved, which errors are reported in
+    span: Span,
+    _reporter: Option<&'a ErrorReporter>,
+}
+
+impl<'a, 'b> Resolver<'a, 'b> {
+    pub fn new(interpreter: &'a mut Interpreter<'b>) -> Self {
+        Self {
+            interpreter,
+            scopes: Vec::new(),
+            functions: vec![HashMap::new()],
+            arity_errors: Vec::new(),
+            current_function: FunctionType::None,
+            in_block_expr: false,
+            loops: Vec::new(),
+            warn_shadowing: false,
+            warn_unreachable: false,
+            span: Span::default(),
+            _reporter: None,
+        }
+    }
+
+    pub fn set_error_reporter(&mut self, reporter: &'a ErrorReporter) {
+        self._reporter = Some(reporter);
+    }
+
+    /// Warns when a local declaration shadows a variable of an enclosing
+    /// local scope. Off by default, since shadowing is often intended.
+    pub fn set_warn_shadowing(&mut self, warn_shadowing: bool) {
+        self.warn_shadowing = warn_shadowing;
+    }
+
+    /// Warns about statements that can never run, as they follow a `return`,
+    /// `break` or `continue` in the same block, once per block. Off by
+    /// default.
+    pub fn set_warn_unreachable(&mut self, warn_unreachable: bool) {
+        self.warn_unreachable = warn_unreachable;
+    }
+
+    /// Resolves a program. Calls to a function declared with `fun` are
+    /// checked to pass it as many arguments as it takes, unless the variable
+    /// naming it is assigned another value.
+    pub fn resolve(&mut self, stmts: &[Stmt]) {
+        self.resolve_stmts(stmts);
+
+        for function in self.functions[0].values_mut() {
+            let mismatches = std::mem::take(&mut function.mismatches);
+            if !function.reassigned {
+                self.arity_errors.extend(mismatches);
+            }
+        }
+
+        // In the order of the calls
+        let mut arity_errors = std::mem::take(&mut self.arity_errors);
+        arity_errors.sort_by_key(|(_, span, _)| span.start);
+        for (paren, span, message) in arity_errors {
+            Self::report_in(self._reporter, &paren, span, &message);
+        }
+    }
+
+    fn resolve_stmts(&mut self, stmts: &[Stmt]) {
+        let mut returned = None;
+        let mut warned = false;
+
+        for stmt in stmts {
+            if let (Some(keyword), false, true) = (returned, warned, self.warn_unreachable) {
+                self.warn_unreachable_code(stmt, keyword);
+                warned = true;
+            }
+
+            self.resolve_stmt(stmt);
+            returned = returned.or_else(|| always_returns(stmt));
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        let enclosing = std::mem::replace(&mut self.span, stmt.span());
+        // Resolution errors are reported as they are found
+        let _ = stmt.accept(self);
+        self.span = enclosing;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        let enclosing = std::mem::replace(&mut self.span, expr.span());
+        let _ = expr.accept(self);
+        self.span = enclosing;
+    }
+
+    fn resolve_function(&mut self, parameters: &[Token], body: &Stmt, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        let enclosing_block_expr = self.in_block_expr;
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        self.current_function = function_type;
+        self.in_block_expr = false;
+        self.begin_scope();
+
+        for parameter in parameters {
+            self.declare(parameter);
+            self.define(parameter);
+        }
+
+        // The body shares the parameters' scope, as Function::call runs it
+        match body {
+            Stmt::Block(stmts, _) => self.resolve_stmts(stmts),
+            _ => self.resolve_stmts(std::slice::from_ref(body)),
+        }
+
+        self.end_scope();
+        self.current_function = enclosing_function;
+        self.in_block_expr = enclosing_block_expr;
+        self.loops = enclosing_loops;
+    }
+
+    fn resolve_loop_body(&mut self, body: &Stmt, label: Option<&Token>) {
+        self.loops.push(label.map(|label| Rc::clone(&label.lexeme)));
+        self.resolve_stmt(body);
+        self.loops.pop();
+    }
+
+    fn resolve_jump(&mut self, keyword: &Token, label: Option<&Token>) {
+        match label {
+            None if self.loops.is_empty() => self.error(
+                keyword,
+                &format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            ),
+            Some(label) if !self.loops.iter().flatten().any(|l| *l == label.lexeme) => {
+                self.error(label, &format!("No loop labeled '{}'.", label.lexeme))
+            }
+            _ => (),
+        }
+    }
+
+    fn resolve_local(&mut self, identifier: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(variable) = scope.get(&identifier.lexeme) {
+                self.interpreter.resolve(identifier, depth, variable.slot);
+                return;
+            }
+        }
+
+        self.interpreter.resolve_global(identifier);
+    }
+
+    // The function declared with `fun` that `identifier` names, if any
+    fn known_function(&mut self, identifier: &Token) -> Option<&mut KnownFunction> {
+        let scope = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(&identifier.lexeme))
+            .map_or(0, |index| index + 1);
+
+        self.functions[scope].get_mut(&identifier.lexeme)
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.functions.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+
+        let functions = self.functions.pop().unwrap_or_default();
+        for function in functions.into_values() {
+            if !function.reassigned {
+                self.arity_errors.extend(function.mismatches);
+            }
+        }
+    }
+
+    fn declare(&mut self, identifier: &Token) {
+        if self.warn_shadowing {
+            self.warn_if_shadowing(identifier);
+        }
+
+        let Some(scope) = self.scopes.last_mut() else {
+            // Globals can be declared again
+            if let Some(function) = self.functions[0].get_mut(&identifier.lexeme) {
+                function.reassigned = true;
+            }
+            return;
+        };
+
+        if scope.contains_key(&identifier.lexeme) {
+            self.error(
+                identifier,
+                "Already a variable with this name in this scope.",
+            );
+            return;
+        }
+
+        let slot = scope.len();
+        scope.insert(
+            Rc::clone(&identifier.lexeme),
+            Variable {
+                slot,
+                line: identifier.line,
+                defined: false,
+                constant: false,
+            },
+        );
+    }
+
+    fn define(&mut self, identifier: &Token) {
+        if let Some(variable) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&identifier.lexeme))
+        {
+            variable.defined = true;
+        }
+    }
+
+    // Local constants are checked here, global ones when they are assigned
+    fn make_constant(&mut self, identifier: &Token) {
+        if let Some(variable) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&identifier.lexeme))
+        {
+            variable.constant = true;
+        }
+    }
+
+    fn warn_if_shadowing(&self, identifier: &Token) {
+        let Some((_, enclosing)) = self.scopes.split_last() else {
+            return;
+        };
+
+        if let Some(shadowed) = enclosing
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&identifier.lexeme))
+        {
+            let message = format!(
+                "'{}' shadows the variable declared on line {}.",
+                identifier.lexeme, shadowed.line
+            );
+            self.warning(identifier, &message);
+        }
+    }
+
+    // Warns about `stmt`, which follows the `return`, `break` or `continue`
+    // of `keyword`
+    fn warn_unreachable_code(&self, stmt: &Stmt, keyword: &Token) {
+        let span = stmt.span();
+        let message = format!("Unreachable code after '{}'.", keyword.lexeme);
+
+        // Statements don't keep their first token, but the first one they
+        // have is usually on the line they start on, which gives the column
+        // they start at. Those of literals only have none, they are reported
+        // at the keyword.
+        let token = match first_token(stmt) {
+            Some(token) => {
+                let mut token = token.clone();
+                let before = token.offset.saturating_sub(span.start);
+                token.column = token.column.saturating_sub(before);
+                token
+            }
+            None => keyword.clone(),
+        };
+
+        match self._reporter {
+            Some(reporter) => reporter.warning_in(&token, span, &message),
+
+            // Reporter does not exist, print to stderr
+            None => eprintln!("[Warning]: {}", message),
+        }
+    }
+
+    fn warning(&self, token: &Token, message: &str) {
+        match self._reporter {
+            Some(reporter) => reporter.warning(token, message),
+
+            // Reporter does not exist, print to stderr
+            None => eprintln!("[Warning]: {}", message),
+        }
+    }
+
+    fn error(&self, token: &Token, message: &str) {
+        Self::report_in(self._reporter, token, self.span, message);
+    }
+
+    fn report_in(reporter: Option<&ErrorReporter>, token: &Token, span: Span, message: &str) {
+        match reporter {
+            Some(reporter) => reporter.error_in(token, span, message),
+
+            // Reporter does not exist, print to stderr
+            None => eprintln!("[Error]: {}", message),
+        }
+    }
+}
+
+// The keyword of the `return`, `break` or `continue` that running the
+// statement always ends with, if any
+fn always_returns(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+        Stmt::Return(keyword, ..) | Stmt::Break(keyword, ..) | Stmt::Continue(keyword, ..) => {
+            Some(keyword)
+        }
+        Stmt::Block(stmts, _) => stmts.iter().find_map(always_returns),
+        Stmt::If(_, stmt_then, Some(stmt_else), _) => {
+            always_returns(stmt_else).and(always_returns(stmt_then))
+        }
+        _ => None,
+    }
+}
+
+// The first token of the statement that the AST keeps, if any: not the
+// keywords starting most statements, nor those of literals
+fn first_token(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+        Stmt::Print(expr, _) | Stmt::Expression(expr, _) => first_expr_token(expr),
+        Stmt::VarDeclaration(identifier, ..)
+        | Stmt::ConstDeclaration(identifier, ..)
+        | Stmt::Function(identifier, ..)
+        | Stmt::ForIn(identifier, ..)
+        | Stmt::Return(identifier, ..)
+        | Stmt::Break(identifier, ..)
+        | Stmt::Continue(identifier, ..) => Some(identifier),
+        Stmt::Block(stmts, _) => stmts.iter().find_map(first_token),
+        Stmt::If(expr, stmt_then, stmt_else, _) => first_expr_token(expr)
+            .or_else(|| first_token(stmt_then))
+            .or_else(|| stmt_else.as_deref().and_then(first_token)),
+        Stmt::While(expr, body, ..) => first_expr_token(expr).or_else(|| first_token(body)),
+        Stmt::Switch(expr, cases, default, _) => first_expr_token(expr)
+            .or_else(|| {
+                cases.iter().find_map(|(value, stmts)| {
+                    first_expr_token(value).or_else(|| stmts.iter().find_map(first_token))
+                })
+            })
+            .or_else(|| default.iter().flatten().find_map(first_token)),
+    }
+}
+
+fn first_expr_token(expr: &Expr) -> Option<&Token> {
+    match expr {
+        Expr::LiteralExpr(..) => None,
+        Expr::AssignExpr(token, ..)
+        | Expr::UnaryExpr(token, ..)
+        | Expr::VariableExpr(token, _)
+        | Expr::List(token, ..)
+        | Expr::Map(token, ..) => Some(token),
+        Expr::BinaryExpr(left, operator, ..) | Expr::LogicalExpr(left, operator, ..) => {
+            first_expr_token(left).or(Some(operator))
+        }
+        Expr::GroupingExpr(expr, _) => first_expr_token(expr),
+        // A pipe's first argument comes before it
+        Expr::Call(_, paren, args, ..) if paren.token_type == TokenType::PipeGreater => {
+            first_expr_token(&args[0]).or(Some(paren))
+        }
+        Expr::Call(callee, paren, ..) => first_expr_token(callee).or(Some(paren)),
+        Expr::Index(object, bracket, ..) | Expr::SetIndex(object, bracket, ..) => {
+            first_expr_token(object).or(Some(bracket))
+        }
+        Expr::Get(object, name, _) => first_expr_token(object).or(Some(name)),
+        Expr::Lambda(keyword, ..) => Some(keyword),
+        Expr::Block(stmts, value, _) => stmts
+            .iter()
+            .find_map(first_token)
+            .or_else(|| value.as_deref().and_then(first_expr_token)),
+    }
+}
+
+impl ExprVisitor<()> for Resolver<'_, '_> {
+    fn visit_literal_expr(&mut self, _literal: &Literal) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    fn visit_binary_expr(
+        &mut self,
+        left: &Expr,
+        _operator: &Token,
+        right: &Expr,
+    ) -> Result<(), RuntimeError> {
+        self.resolve_expr(left);
+        self.resolve_expr(right);
+        Ok(())
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(expr);
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, _operator: &Token, right: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(right);
+        Ok(())
+    }
+
+    fn visit_variable_expr(&mut self, identifier: &Token) -> Result<(), RuntimeError> {
+        let declared_only = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&identifier.lexeme))
+            .is_some_and(|variable| !variable.defined);
+
+        if declared_only {
+            self.error(
+                identifier,
+                "Can't read local variable in its own initializer.",
+            );
+        }
+
+        self.resolve_local(identifier);
+        Ok(())
+    }
+
+    fn visit_assign_expr(&mut self, identifier: &Token, value: &Expr) -> Result<(), RuntimeError> {
+        self.resolve_expr(value);
+
+        let variable = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&identifier.lexeme));
+        if variable.is_some_and(|variable| variable.constant) {
+            self.error(
+                identifier,
+                &format!("Cannot assign to constant '{}'.", identifier.lexeme),
+            );
+        }
+        if let Some(function) = self.known_function(identifier) {
+            function.reassigned = true;
+        }
+
+        self.resolve_local(identifier);
+        Ok(())
+    }
+
+    fn visit_logical_expr(
+        &mut self,
+        left: &Expr,
+        _operator: &Token,
+        right: &Expr,
+    ) -> Result<(), RuntimeError> {
+        self.resolve_expr(left);
+        self.resolve_expr(right);
+        Ok(())
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        args: &[Expr],
+        named: &[(Token, Expr)],
+    ) -> Result<(), RuntimeError> {
+        self.resolve_expr(callee);
+        for arg in args {
+            self.resolve_expr(arg);
+        }
+        for (_, value) in named {
+            self.resolve_expr(value);
+        }
+
+        // Named arguments are bound to parameters when the call runs
+        if let (Expr::VariableExpr(identifier, _), []) = (callee, named) {
+            let span = self.span;
+            if let Some(function) = self.known_function(identifier) {
+                if !function.arity.accepts(args.len()) {
+                    let message = format!("Expected {} but got {}.", function.arity, args.len());
+                    function.mismatches.push((paren.clone(), span, message));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        stmts: &[Stmt],
+        value: Option<&Expr>,
+    ) -> Result<(), RuntimeError> {
+        // Nor can `break` and `continue` unwind them
+        let enclosing_block_expr = self.in_block_expr;
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        self.in_block_expr = true;
+        self.begin_scope();
+
+        self.resolve_stmts(stmts);
+        if let Some(value) = value {
+            self.resolve_expr(value);
+        }
+
+        self.end_scope();
+        self.in_block_expr = enclosing_block_expr;
+        self.loops = enclosing_loops;
+        Ok(())
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elements: &[Expr]) -> Result<(), RuntimeError> {
+        for element in elements {
+            self.resolve_expr(element);
+        }
+        Ok(())
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        entries: &[(Token, Expr)],
+    ) -> Result<(), RuntimeError> {
+        for (_, value) in entries {
+            self.resolve_expr(value);
+        }
+        Ok(())
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+    ) -> Result<(), RuntimeError> {
+        self.resolve_expr(object);
+        self.resolve_expr(index);
+        Ok(())
+    }
+
+    fn visit_set_index_expr(
+        &mut self,
+        object: &Expr,
+        _bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+    ) -> Result<(), RuntimeError> {
+        self.resolve_expr(object);
+        self.resolve_expr(index);
+        self.resolve_expr(value);
+        Ok(())
+    }
+
+    fn visit_lambda_expr(
+        &mut self,
+        _keyword: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<(), RuntimeError> {
+        for default in defaults.iter().flatten() {
+            self.resolve_expr(default);
+        }
+
+        self.resolve_function(parameters, body, FunctionType::Function);
+        Ok(())
+    }
+
+    fn visit_get_expr(&mut self, object: &Expr, _name: &Token) -> Result<(), RuntimeError> {
+        self.resolve_expr(object);
+        Ok(())
+    }
+}
+
+impl StmtVisitor<()> for Resolver<'_, '_> {
+    fn visit_print_stmt(&mut self, expr: &Expr) -> Result<(), Signal> {
+        self.resolve_expr(expr);
+        Ok(())
+    }
+
+    fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<(), Signal> {
+        self.resolve_expr(expr);
+        Ok(())
+    }
+
+    fn visit_var_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: Option<&Expr>,
+    ) -> Result<(), Signal> {
+        self.declare(identifier);
+        if let Some(initializer) = initializer {
+            self.resolve_expr(initializer);
+        }
+        self.define(identifier);
+        Ok(())
+    }
+
+    fn visit_const_declaration_stmt(
+        &mut self,
+        identifier: &Token,
+        initializer: &Expr,
+    ) -> Result<(), Signal> {
+        self.declare(identifier);
+        self.make_constant(identifier);
+        self.resolve_expr(initializer);
+        self.define(identifier);
+        Ok(())
+    }
+
+    fn visit_block_stmt(&mut self, stmts: &[Stmt]) -> Result<(), Signal> {
+        self.begin_scope();
+        self.resolve_stmts(stmts);
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt_then: &Stmt,
+        stmt_else: &Option<Box<Stmt>>,
+    ) -> Result<(), Signal> {
+        self.resolve_expr(expr);
+        self.resolve_stmt(stmt_then);
+        if let Some(stmt_else) = stmt_else {
+            self.resolve_stmt(stmt_else);
+        }
+        Ok(())
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        expr: &Expr,
+        stmt: &Stmt,
+        increment: Option<&Expr>,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        self.resolve_expr(expr);
+        self.resolve_loop_body(stmt, label);
+        if let Some(increment) = increment {
+            self.resolve_expr(increment);
+        }
+        Ok(())
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        identifier: &Token,
+        iterable: &Expr,
+        body: &Stmt,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        self.resolve_expr(iterable);
+
+        // Each iteration has a scope for the loop variable, around the body
+        self.begin_scope();
+        self.declare(identifier);
+        self.define(identifier);
+        self.resolve_loop_body(body, label);
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_break_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), Signal> {
+        self.resolve_jump(keyword, label);
+        Ok(())
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<(), Signal> {
+        self.resolve_jump(keyword, label);
+        Ok(())
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        identifier: &Token,
+        parameters: &[Token],
+        defaults: &Rc<[Option<Expr>]>,
+        body: &Rc<Stmt>,
+    ) -> Result<(), Signal> {
+        // Define eagerly so the function can refer to itself recursively
+        self.declare(identifier);
+        self.define(identifier);
+
+        // Declaring a global function again is like assigning it
+        let redeclared =
+            self.scopes.is_empty() && self.functions[0].contains_key(&identifier.lexeme);
+        self.functions.last_mut().unwrap().insert(
+            Rc::clone(&identifier.lexeme),
+            KnownFunction {
+                arity: Arity::of_parameters(defaults),
+                reassigned: redeclared,
+                mismatches: vec![],
+            },
+        );
+
+        // Defaults are evaluated in the function's closure, not its body
+        for default in defaults.iter().flatten() {
+            self.resolve_expr(default);
+        }
+
+        self.resolve_function(parameters, body, FunctionType::Function);
+        Ok(())
+    }
+
+    fn visit_switch_stmt(
+        &mut self,
+        expr: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: Option<&[Stmt]>,
+    ) -> Result<(), Signal> {
+        self.resolve_expr(expr);
+
+        // Each case runs in its own scope, like a block
+        for (value, stmts) in cases {
+            self.resolve_expr(value);
+            self.visit_block_stmt(stmts)?;
+        }
+        if let Some(stmts) = default {
+            self.visit_block_stmt(stmts)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, token: &Token, expr: &Expr) -> Result<(), Signal> {
+        if self.current_function == FunctionType::None {
+            self.error(token, "Can't return from top-level code.");
+        } else if self.in_block_expr {
+            self.error(token, "Can't return from inside a block expression.");
+        }
+
+        self.resolve_expr(expr);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Diagnostic, Severity};
+    use crate::eval;
+    use crate::interpreter::Object;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    // What resolving `source` reports, after `configure` turns on warnings
+    fn resolve_diagnostics(source: &str, configure: impl FnOnce(&mut Resolver)) -> Vec<Diagnostic> {
+        let reporter = ErrorReporter::new();
+        let statements = Parser::new(Scanner::new(source).scan_tokens()).parse();
+        let mut interpreter = Interpreter::new();
+        let mut resolver = Resolver::new(&mut interpreter);
+        resolver.set_error_reporter(&reporter);
+        configure(&mut resolver);
+        resolver.resolve(&statements);
+        reporter.diagnostics()
+    }
+
+    fn messages_of(diagnostics: Vec<Diagnostic>) -> Vec<String> {
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn closures_bind_to_the_scope_they_were_declared_in() {
+        let source = "
+            var a = \"global\";
+            var first;
+            var second;
+            {
+                fun show() { return a; }
+                first = show();
+                var a = \"block\";
+                second = show();
+            }
+            first + \" \" + second;
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Object::String("global global".to_string()))
+        );
+    }
+
+    #[test]
+    fn locals_shadow_and_assign_by_slot() {
+        let source = "
+            var total = 0;
+            {
+                var a = 1;
+                var b = 2;
+                fun add(x) { total = total + x + a; b = b + 1; }
+                add(10);
+                add(b);
+                total = total * b;
+            }
+            total;
+        ";
+        assert_eq!(eval(source), Ok(Object::Number(60.0)));
+    }
+
+    #[test]
+    fn reports_static_errors() {
+        let diagnostics = eval("{ var a = 1; var a = 2; }").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Already a variable with this name in this scope."
+        );
+
+        let diagnostics = eval("{ var a = a; }").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Can't read local variable in its own initializer."
+        );
+    }
+
+    #[test]
+    fn rejects_top_level_return() {
+        for source in ["return 1;", "{ if (true) return; }"] {
+            let diagnostics = eval(source).unwrap_err();
+            assert_eq!(diagnostics[0].message, "Can't return from top-level code.");
+        }
+
+        assert_eq!(
+            eval("fun f() { { return 1; } } f();"),
+            Ok(Object::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn checks_the_arity_of_declared_functions() {
+        let messages = |source: &str| messages_of(resolve_diagnostics(source, |_| {}));
+
+        assert_eq!(
+            messages("fun f(a, b = 1) {} f(); f(1); f(1, 2, 3);"),
+            vec![
+                "Expected 1 to 2 arguments but got 0.",
+                "Expected 1 to 2 arguments but got 3."
+            ]
+        );
+        assert_eq!(
+            messages("{ fun f() {} fun g() { f(1); } }"),
+            vec!["Expected 0 arguments but got 1."]
+        );
+
+        // Those calls may be to another function, or bind named arguments
+        assert!(messages("fun f() {} fun g() { f(1); } f = clock;").is_empty());
+        assert!(messages("fun f() {} f(1); var f = 1;").is_empty());
+        assert!(messages("fun f() {} { var f = len; f(1); }").is_empty());
+        assert!(messages("fun f(a) {} f(a: 1);").is_empty());
+    }
+
+    #[test]
+    fn warns_about_unreachable_code_when_asked() {
+        let warnings = |source: &str, warn_unreachable: bool| -> Vec<(usize, usize, String)> {
+            resolve_diagnostics(source, |resolver| {
+                resolver.set_warn_unreachable(warn_unreachable)
+            })
+            .into_iter()
+            .map(|warning| {
+                assert_eq!(warning.severity, Severity::Warning);
+                assert_eq!(warning.message, "Unreachable code after 'return'.");
+                let text = warning.span.unwrap().text(source).to_string();
+                (warning.line, warning.column, text)
+            })
+            .collect()
+        };
+        let source = "
+fun f(a) {
+    return a;
+    print a; a = 1;
+}
+fun g(a) {
+    if (a) {
+        return 1;
+        var b;
+    } else return 2;
+    { return 3; }
+    print 4;
+}
+fun h(a) {
+    if (a) return 1;
+    print a;
+    while (a) { return; }
+    print a;
+}
+";
+
+        // Once per block, at the first statement that doesn't run
+        assert_eq!(
+            warnings(source, true),
+            vec![
+                (4, 5, "print a;".to_string()),
+                (9, 9, "var b;".to_string()),
+                (11, 5, "{ return 3; }".to_string()),
+            ]
+        );
+        assert!(warnings(source, false).is_empty());
+
+        // Without a token in the statement, the warning is at the `return`
+        assert_eq!(
+            warnings("fun f() {\n    return;\n    print \"never\";\n}", true),
+            vec![(2, 5, "print \"never\";".to_string())]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_need_a_loop() {
+        let messages = |source: &str| {
+            messages_of(resolve_diagnostics(source, |resolver| {
+                resolver.set_warn_unreachable(true)
+            }))
+        };
+
+        assert!(messages("outer: while (true) { for (x in []) break outer; }").is_empty());
+        assert!(messages("for (x in []) switch (x) { case 1: continue; }").is_empty());
+        assert_eq!(
+            messages("break;"),
+            vec!["Can't use 'break' outside of a loop."]
+        );
+        // Nor out of functions and block expressions in a loop
+        assert_eq!(
+            messages("while (true) { fun f() { continue; } var a = { break; 1 }; }"),
+            vec![
+                "Can't use 'continue' outside of a loop.",
+                "Can't use 'break' outside of a loop."
+            ]
+        );
+        assert_eq!(
+            messages("while (true) { map([], fun (x) { break; }); }"),
+            vec!["Can't use 'break' outside of a loop."]
+        );
+        assert_eq!(
+            messages("a: while (true) {} while (true) { break a; }"),
+            vec!["No loop labeled 'a'."]
+        );
+        assert_eq!(
+            messages("while (true) { continue; print 1; }"),
+            vec!["Unreachable code after 'continue'."]
+        );
+    }
+
+    #[test]
+    fn warns_about_shadowing_when_asked() {
+        let diagnostics = |source: &str, warn_shadowing: bool| {
+            resolve_diagnostics(source, |resolver| {
+                resolver.set_warn_shadowing(warn_shadowing)
+            })
+        };
+        let source = "
+            var a = 0;
+            {
+                var a = 1;
+                fun f(a) {
+                    { var b = a; }
+                    var b;
+                }
+            }
+        ";
+
+        let warnings = diagnostics(source, true);
+        let messages = warnings
+            .iter()
+            .map(|warning| (warning.line, warning.message.as_str()))
+            .collect::<Vec<_>>();
+        // Globals aren't in a scope, and `b` is declared after the block ends
+        assert_eq!(
+            messages,
+            vec![(5, "'a' shadows the variable declared on line 4.")]
+        );
+        assert!(warnings.iter().all(|w| w.severity == Severity::Warning));
+
+        assert!(diagnostics(source, false).is_empty());
+    }
+}
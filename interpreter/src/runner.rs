@@ -1,50 +1,205 @@
-use crate::error::ErrorReporter;
-use crate::interpreter::Interpreter;
-use crate::parser::Parser;
-use crate::scanner::Scanner;
-use std::{fs, io, io::Write, process};
+use rlox::error::ErrorReporter;
+use rlox::interpreter::Interpreter;
+use rlox::optimizer::Optimizer;
+use rlox::parser::{AstPrinter, Formatter, Parser};
+use rlox::resolver::Resolver;
+use rlox::scanner::Scanner;
+use std::{fs, io, io::Read, io::Write, process};
+
+/// What the runner does with each parsed source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Interpret the program.
+    Run,
+    /// Print the program's statements with `AstPrinter::print_stmts` instead
+    /// of interpreting them: one s-expression per top-level statement, nested
+    /// statements on their own lines indented by two spaces.
+    DumpAst,
+    /// Only scan the source and print one token per line using `Token`'s
+    /// `Debug` format: `[line] (TokenType) lexeme literal`.
+    DumpTokens,
+    /// Print the program formatted with `Formatter`, comments included,
+    /// instead of interpreting it.
+    Format,
+    /// Print the parsed program as a JSON array of statements. Every node is
+    /// an object whose `type` field names its kind.
+    #[cfg(feature = "serde")]
+    DumpJson,
+}
 
 pub struct Runner {
     error_reporter: ErrorReporter,
+    mode: Mode,
+    auto_semicolons: bool,
+    profile: bool,
+    warn_shadowing: bool,
+    warn_unreachable: bool,
+    script_args: Vec<String>,
 }
 
 impl Runner {
     pub fn new() -> Self {
+        // Diagnostics are printed by `run`, along with the source they point at
+        let mut error_reporter = ErrorReporter::new();
+        error_reporter.set_print(false);
+
         Self {
-            error_reporter: ErrorReporter::new(),
+            error_reporter,
+            mode: Mode::Run,
+            auto_semicolons: false,
+            profile: false,
+            warn_shadowing: false,
+            warn_unreachable: false,
+            script_args: Vec::new(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Lets scripts omit semicolons at the end of lines, see
+    /// `Scanner::set_auto_semicolons`.
+    pub fn set_auto_semicolons(&mut self, auto_semicolons: bool) {
+        self.auto_semicolons = auto_semicolons;
+    }
+
+    /// Prints the hottest node kinds and the time spent in calls to stderr
+    /// when the program ends.
+    pub fn set_profile(&mut self, profile: bool) {
+        self.profile = profile;
+    }
+
+    /// Warns about local variables that shadow others, see
+    /// `Resolver::set_warn_shadowing`.
+    pub fn set_warn_shadowing(&mut self, warn_shadowing: bool) {
+        self.warn_shadowing = warn_shadowing;
+    }
+
+    /// Warns about statements after a `return`, `break` or `continue`, see
+    /// `Resolver::set_warn_unreachable`.
+    pub fn set_warn_unreachable(&mut self, warn_unreachable: bool) {
+        self.warn_unreachable = warn_unreachable;
+    }
+
+    /// The arguments scripts get from `args()`.
+    pub fn set_script_args(&mut self, script_args: Vec<String>) {
+        self.script_args = script_args;
+    }
+
+    fn interpreter(&self) -> Interpreter<'_> {
+        let mut interpreter = if self.profile {
+            Interpreter::with_profiling(io::stdout())
+        } else {
+            Interpreter::new()
+        };
+        interpreter.set_error_reporter(&self.error_reporter);
+        interpreter.set_process_exit(true);
+        interpreter.set_args(self.script_args.clone());
+        interpreter
+    }
+
+    fn print_profile(&self, interpreter: &Interpreter) {
+        if let Some(profile) = interpreter.profile() {
+            eprint!("{}", profile);
         }
     }
 
     fn run(&self, source: String, interpreter: &mut Interpreter) {
-        let mut scanner = Scanner::new(&source);
+        let reported = self.error_reporter.diagnostics().len();
+
+        self.run_source(&source, interpreter);
+
+        for diagnostic in &self.error_reporter.diagnostics()[reported..] {
+            eprintln!("{}", diagnostic.render(&source));
+        }
+    }
+
+    fn run_source(&self, source: &str, interpreter: &mut Interpreter) {
+        let mut scanner = Scanner::new(source);
         scanner.set_error_reporter(&self.error_reporter);
+        scanner.set_interner(interpreter.interner());
+        scanner.set_auto_semicolons(self.auto_semicolons);
+        scanner.set_preserve_comments(self.mode == Mode::Format);
         let tokens = scanner.scan_tokens();
 
+        if self.mode == Mode::DumpTokens {
+            for token in tokens {
+                println!("{:?}", token);
+            }
+            return;
+        }
+
         // Error while scanning
         if self.error_reporter.has_error() {
             return;
         }
 
+        let mut formatter = Formatter::new();
+        if self.mode == Mode::Format {
+            formatter.set_comments(&tokens);
+        }
+
         let mut parser = Parser::new(tokens);
         parser.set_error_reporter(&self.error_reporter);
 
-        let statements = parser.parse();
+        let mut statements = parser.parse();
 
         // Error while parsing
         if self.error_reporter.has_error() {
             return;
         }
 
-        interpreter.interpret(statements);
+        if self.mode == Mode::DumpAst {
+            println!("{}", AstPrinter.print_stmts(&statements));
+            return;
+        }
+
+        if self.mode == Mode::Format {
+            print!("{}", formatter.format_stmts(&statements));
+            return;
+        }
+
+        #[cfg(feature = "serde")]
+        if self.mode == Mode::DumpJson {
+            println!("{}", serde_json::to_string_pretty(&statements).unwrap());
+            return;
+        }
+
+        Optimizer::new().optimize(&mut statements);
+
+        let mut resolver = Resolver::new(interpreter);
+        resolver.set_error_reporter(&self.error_reporter);
+        resolver.set_warn_shadowing(self.warn_shadowing);
+        resolver.set_warn_unreachable(self.warn_unreachable);
+        resolver.resolve(&statements);
+
+        // Error while resolving
+        if self.error_reporter.has_error() {
+            return;
+        }
+
+        interpreter.run(statements);
     }
 
     pub fn run_file(&self, file: &String) {
         let file_bytes = fs::read(file).unwrap();
         let file_str = String::from_utf8(file_bytes).unwrap();
-        let mut interpreter = Interpreter::new();
-        interpreter.set_error_reporter(&self.error_reporter);
+        self.run_script(file_str);
+    }
 
-        self.run(file_str, &mut interpreter);
+    /// Reads all of stdin and runs it like a script file.
+    pub fn run_stdin(&self) {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).unwrap();
+        self.run_script(source);
+    }
+
+    fn run_script(&self, source: String) {
+        let mut interpreter = self.interpreter();
+
+        self.run(source, &mut interpreter);
+        self.print_profile(&interpreter);
 
         if self.error_reporter.has_error() {
             process::exit(65);
@@ -56,8 +211,7 @@ impl Runner {
     }
 
     pub fn run_prompt(&mut self) {
-        let mut interpreter = Interpreter::new();
-        interpreter.set_error_reporter(&self.error_reporter);
+        let mut interpreter = self.interpreter();
 
         loop {
             print!("> ");
@@ -69,6 +223,7 @@ impl Runner {
             assert_eq!(line.pop(), Some('\n'));
 
             if line.is_empty() {
+                self.print_profile(&interpreter);
                 break;
             }
 
@@ -77,3 +232,39 @@ impl Runner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_clears_errors_between_prompt_lines() {
+        let runner = Runner::new();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_error_reporter(&runner.error_reporter);
+
+        runner.run("print undefined;".to_string(), &mut interpreter);
+        assert!(runner.error_reporter.has_runtime_error());
+        runner.error_reporter.reset();
+
+        runner.run("print 1 + 2;".to_string(), &mut interpreter);
+        assert!(!runner.error_reporter.has_error());
+        assert!(!runner.error_reporter.has_runtime_error());
+    }
+
+    #[test]
+    fn auto_semicolons_are_opt_in() {
+        let source = "var a = 1 +\n  2\nprint a\n".to_string();
+        let mut runner = Runner::new();
+        let mut interpreter = Interpreter::with_writer(io::sink());
+
+        runner.run(source.clone(), &mut interpreter);
+        assert!(runner.error_reporter.has_error());
+        runner.error_reporter.reset();
+
+        runner.set_auto_semicolons(true);
+        runner.run(source, &mut interpreter);
+        assert!(!runner.error_reporter.has_error());
+        assert!(!runner.error_reporter.has_runtime_error());
+    }
+}
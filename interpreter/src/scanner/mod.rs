@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod scanner;
 
-pub use scanner::Scanner;
+pub use scanner::{ScanStats, Scanner};
@@ -1,52 +1,281 @@
+use crate::ast::symbol::Interner;
 use crate::ast::{
     keywords::get_keyword_token_type,
     token::Token,
     tokentype::{Literal, TokenType},
 };
 use crate::error::ErrorReporter;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Summary of a scanned program, from `Scanner::stats`.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanStats {
+    /// How many tokens of each type were scanned, including the final `Eof`.
+    pub token_counts: HashMap<TokenType, usize>,
+    pub lines: usize,
+    /// How many times each identifier appears.
+    pub identifiers: HashMap<String, usize>,
+}
 
 pub struct Scanner<'a> {
-    source: &'a str,
     tokens: Vec<Token>,
 
     _reporter: Option<&'a ErrorReporter>,
+    _interner: Option<Rc<RefCell<Interner>>>,
+    _keywords: HashMap<String, TokenType>,
     _source: Vec<char>,
     _start: usize,
     _current: usize,
     _line: usize,
+    _line_start: usize,
+    _column: usize,
+    _auto_semicolons: bool,
+    _preserve_comments: bool,
+    _brackets: Brackets,
+    // Position in `tokens` of the next token the iterator returns
+    _next: usize,
+    _scanned_eof: bool,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
-            source,
             tokens: Vec::new(),
 
             _source: source.chars().collect::<Vec<char>>(),
             _reporter: None,
+            _interner: None,
+            _keywords: HashMap::new(),
             _start: 0,
             _current: 0,
             _line: 1,
+            _line_start: 0,
+            _column: 1,
+            _auto_semicolons: false,
+            _preserve_comments: false,
+            _brackets: Brackets::default(),
+            _next: 0,
+            _scanned_eof: false,
         }
     }
 
+    /// Creates a scanner whose `keywords` take precedence over the built-in
+    /// ones, so they can add new keywords or aliases (`"function"` to
+    /// `TokenType::Fun`). Mapping a word to `TokenType::Identifier` makes it
+    /// an ordinary name again.
+    pub fn with_keywords(source: &'a str, keywords: HashMap<String, TokenType>) -> Self {
+        let mut scanner = Self::new(source);
+        scanner._keywords = keywords;
+        scanner
+    }
+
     pub fn set_error_reporter(&mut self, reporter: &'a ErrorReporter) {
         self._reporter = Some(reporter);
     }
 
+    /// Interns identifiers with `interner`, which should be the one of the
+    /// `Interpreter` that will run the tokens.
+    pub fn set_interner(&mut self, interner: Rc<RefCell<Interner>>) {
+        self._interner = Some(interner);
+    }
+
+    /// Inserts a `Semicolon` token at each line break, and at the end of the
+    /// source, where a statement can end. Off by default.
+    ///
+    /// A semicolon is inserted when the last token of the line is an
+    /// identifier, a literal (number, string, `true`, `false`, `nil`), `this`,
+    /// `return`, a `)` or a `]`, unless:
+    ///
+    /// - the line break is inside parentheses or brackets, so arguments, `for`
+    ///   clauses and lists can span lines;
+    /// - the `)` closes an `if`, `while`, `for` or `switch` header, or a function's
+    ///   parameters, which are followed by a statement or body.
+    ///
+    /// A line ending with an operator or a `,` continues on the next line, as
+    /// in `1 +` followed by `2`. No semicolon follows `}`: blocks are not
+    /// terminated, and a stray `;` is not a statement in Lox.
+    pub fn set_auto_semicolons(&mut self, auto_semicolons: bool) {
+        self._auto_semicolons = auto_semicolons;
+    }
+
+    /// Adds a `Comment` token for each `// line` and `/* block */` comment,
+    /// with the whole comment as its lexeme, for tools that print the source
+    /// back like `Formatter`. Off by default, as `Parser` skips them anyway.
+    ///
+    /// Comments are ignored by everything else that looks at the previous
+    /// tokens, so they don't change where semicolons are inserted.
+    pub fn set_preserve_comments(&mut self, preserve_comments: bool) {
+        self._preserve_comments = preserve_comments;
+    }
+
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
-            // We are at the beginning of the next lexeme.
-            self._start = self._current;
-            self.scan_token();
+            self.scan_lexeme();
         }
+        self.scan_eof();
 
-        let eof_token = Token::new(TokenType::Eof, "", None, 0);
-        self.tokens.push(eof_token);
+        self.tokens.clone()
+    }
+
+    /// Scans the source again after an edit of `previous_source`, which was
+    /// scanned into `previous` with the same settings: the bytes in `edited`
+    /// were replaced with what is in their place in this scanner's source.
+    /// Returns the tokens `scan_tokens` would, but only scans from the line
+    /// the edit starts on until the tokens line up with the previous ones
+    /// again, and reuses the rest with their positions moved.
+    ///
+    /// Only the scanned text is checked for errors, those in the rest were
+    /// reported when `previous` was scanned.
+    pub fn rescan(
+        &mut self,
+        previous_source: &str,
+        previous: &[Token],
+        edited: Range<usize>,
+    ) -> Vec<Token> {
+        // Char positions of the edited text, in the previous source and in this one
+        let previous_chars = previous_source.chars().collect::<Vec<_>>();
+        let start = previous_source[..edited.start].chars().count();
+        let end = previous_source[..edited.end].chars().count();
+        let new_end = self._source.len() - (previous_chars.len() - end);
+
+        // Tokens before the edited line are kept, except for semicolons
+        // inserted at its line break, which are inserted again
+        let line_start = self.line_start(start);
+        let kept = previous
+            .iter()
+            .rposition(|token| !token.lexeme.is_empty() && token.span().end <= line_start)
+            .map_or(0, |index| index + 1);
+
+        self.tokens.clear();
+        self._brackets = Brackets::default();
+        for token in &previous[..kept] {
+            self._brackets.track(token.token_type, &self.tokens);
+            self.tokens.push(token.clone());
+        }
+
+        (self._current, self._line) = match self.tokens.last() {
+            Some(token) => (token.span().end, token.line),
+            None => (0, 1),
+        };
+        self._line_start = self.line_start(self._current);
+
+        // The brackets open before `previous[next]`
+        let mut next = 0;
+        let mut brackets = Brackets::default();
+
+        while !self.is_at_end() {
+            self.scan_lexeme();
+
+            // Scanning after a comment depends on the tokens before it, so the
+            // scans line up on another token
+            let token = match self.tokens.last() {
+                Some(token)
+                    if !token.lexeme.is_empty()
+                        && token.token_type != TokenType::Comment
+                        && token.offset >= new_end =>
+                {
+                    token
+                }
+                _ => continue,
+            };
+
+            let offset = token.offset - new_end + end;
+            while next < previous.len() && previous[next].offset < offset {
+                brackets.track(previous[next].token_type, &previous[..next]);
+                next += 1;
+            }
+
+            let Some(same) = previous.get(next) else {
+                continue;
+            };
+            let mut after = brackets.clone();
+            after.track(same.token_type, &previous[..next]);
+            let preceding = preceding_type(&self.tokens[..self.tokens.len() - 1]);
+
+            // From the same token, with the same brackets open, scanning the
+            // unedited text gives the same tokens as before
+            if same.offset != offset
+                || same.token_type != token.token_type
+                || same.lexeme != token.lexeme
+                || after != self._brackets
+                || preceding != preceding_type(&previous[..next])
+            {
+                continue;
+            }
+
+            let lines = token.line as isize - same.line as isize;
+            let columns = token.column as isize - same.column as isize;
+            let mut same_line = true;
 
+            for (before, token) in previous[next..].iter().zip(&previous[next + 1..]) {
+                same_line =
+                    same_line && !previous_chars[before.offset..token.offset].contains(&'\n');
+
+                let mut token = token.clone();
+                token.offset = token.offset - end + new_end;
+                token.line = token.line.saturating_add_signed(lines);
+                if same_line {
+                    token.column = token.column.saturating_add_signed(columns);
+                }
+                self.tokens.push(token);
+            }
+
+            self._current = self._source.len();
+            self._scanned_eof = true;
+            return self.tokens.clone();
+        }
+
+        self.scan_eof();
         self.tokens.clone()
     }
 
+    fn scan_lexeme(&mut self) {
+        // We are at the beginning of the next lexeme.
+        self._start = self._current;
+        self._column = self._start - self._line_start + 1;
+        self.scan_token();
+    }
+
+    fn scan_eof(&mut self) {
+        self._start = self._current;
+        self._column = self._current - self._line_start + 1;
+        self.insert_semicolon();
+        let mut eof_token = Token::new(TokenType::Eof, "", None, self._line);
+        eof_token.column = self._column;
+        eof_token.offset = self._start;
+        self.tokens.push(eof_token);
+        self._scanned_eof = true;
+    }
+
+    /// Counts what the last `scan_tokens` call produced.
+    pub fn stats(&self) -> ScanStats {
+        let mut stats = ScanStats {
+            lines: self._source.iter().filter(|&&c| c == '\n').count(),
+            ..Default::default()
+        };
+
+        // The last line may not end with a newline
+        if self._source.last().is_some_and(|&c| c != '\n') {
+            stats.lines += 1;
+        }
+
+        for token in &self.tokens {
+            *stats.token_counts.entry(token.token_type).or_insert(0) += 1;
+
+            if token.token_type == TokenType::Identifier {
+                *stats
+                    .identifiers
+                    .entry(token.lexeme.to_string())
+                    .or_insert(0) += 1;
+            }
+        }
+
+        stats
+    }
+
     fn scan_token(&mut self) {
         let char = self.advance();
 
@@ -54,16 +283,46 @@ impl<'a> Scanner<'a> {
             // Single char tokens
             Some('(') => self.add_token(TokenType::LeftParen),
             Some(')') => self.add_token(TokenType::RightParen),
+            Some('[') => self.add_token(TokenType::LeftBracket),
+            Some(']') => self.add_token(TokenType::RightBracket),
             Some('{') => self.add_token(TokenType::LeftBrace),
             Some('}') => self.add_token(TokenType::RightBrace),
             Some(',') => self.add_token(TokenType::Comma),
-            Some('.') => self.add_token(TokenType::Dot),
             Some('-') => self.add_token(TokenType::Minus),
             Some('+') => self.add_token(TokenType::Plus),
             Some(';') => self.add_token(TokenType::Semicolon),
-            Some('*') => self.add_token(TokenType::Star),
+            Some(':') => self.add_token(TokenType::Colon),
+            Some('&') => self.add_token(TokenType::Ampersand),
+            Some('^') => self.add_token(TokenType::Caret),
+            Some('~') => self.add_token(TokenType::Tilde),
 
             // Single or double char tokens
+            Some('.') => {
+                let token = if !self.match_char('.') {
+                    TokenType::Dot
+                } else if self.match_char('=') {
+                    TokenType::DotDotEqual
+                } else {
+                    TokenType::DotDot
+                };
+                self.add_token(token);
+            }
+            Some('|') => {
+                let token = if self.match_char('>') {
+                    TokenType::PipeGreater
+                } else {
+                    TokenType::Pipe
+                };
+                self.add_token(token);
+            }
+            Some('*') => {
+                let token = if self.match_char('*') {
+                    TokenType::StarStar
+                } else {
+                    TokenType::Star
+                };
+                self.add_token(token);
+            }
             Some('!') => {
                 let token = if self.match_char('=') {
                     TokenType::BangEqual
@@ -75,6 +334,8 @@ impl<'a> Scanner<'a> {
             Some('=') => {
                 let token = if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::Arrow
                 } else {
                     TokenType::Equal
                 };
@@ -83,6 +344,8 @@ impl<'a> Scanner<'a> {
             Some('<') => {
                 let token = if self.match_char('=') {
                     TokenType::LessEqual
+                } else if self.match_char('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -91,6 +354,8 @@ impl<'a> Scanner<'a> {
             Some('>') => {
                 let token = if self.match_char('=') {
                     TokenType::GreaterEqual
+                } else if self.match_char('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -101,12 +366,18 @@ impl<'a> Scanner<'a> {
             Some(' ') => (),
             Some('\r') => (),
             Some('\t') => (),
-            Some('\n') => self._line += 1,
+            Some('\n') => {
+                self.insert_semicolon();
+                self.new_line();
+            }
 
             // Multi char tokens
             Some('/') => {
                 if self.match_char('/') {
                     self.ignore_until_newline();
+                    self.add_comment();
+                } else if self.match_char('*') {
+                    self.scan_block_comment();
                 } else {
                     self.add_token(TokenType::Slash);
                 }
@@ -116,43 +387,128 @@ impl<'a> Scanner<'a> {
 
             None => (),
             _ => {
-                let char = char.unwrap();
+                let char = *char.unwrap();
 
                 if char.is_numeric() {
                     self.scan_number();
                     return;
                 }
 
-                if char.is_alphabetic() || *char == '_' {
+                if char == 'r' {
+                    if let Some(hashes) = self.raw_string_hashes() {
+                        self.scan_raw_string(hashes);
+                        return;
+                    }
+                }
+
+                if char.is_alphabetic() || char == '_' {
                     self.scan_identifier();
                     return;
                 }
 
+                // A single '?' is not a token
+                if char == '?' && self.match_char('?') {
+                    self.add_token(TokenType::QuestionQuestion);
+                    return;
+                }
+
                 let message = format!("Unexpected character: '{}'", char);
-                self.report(self._line, "", &message)
+                self.report(&message)
             }
         }
     }
 
+    // Block comments don't nest, the first `*/` ends them
+    fn scan_block_comment(&mut self) {
+        while (self.peek() != '*' || self.peek_next() != '/') && !self.is_at_end() {
+            if self.advance() == Some(&'\n') {
+                // A comment spanning lines ends a statement like a line break
+                self.insert_semicolon();
+                self.new_line();
+            }
+        }
+
+        if self.is_at_end() {
+            self.report("Unterminated comment.");
+            return;
+        }
+
+        // The closing */.
+        self.advance();
+        self.advance();
+
+        self.add_comment();
+    }
+
     fn scan_string(&mut self, delimmeter: char) {
+        let mut value = String::new();
+
         while self.peek() != delimmeter && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self._line += 1;
+            match self.advance().copied() {
+                // A backslash at the end of a line continues the string on the
+                // next one, without the line break
+                Some('\\') if self.peek() == '\n' => {
+                    self.advance();
+                    self.new_line();
+                }
+                Some('\n') => {
+                    value.push('\n');
+                    self.new_line();
+                }
+                Some(char) => value.push(char),
+                None => (),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            self.report(self._line, "", "Unterminated string.");
+            self.report("Unterminated string.");
             return;
         }
 
         // The closing ".
         self.advance();
 
-        // Trim the surrounding quotes.
-        let value = &self.source[self._start + 1..self._current - 1];
-        self.add_token_literal(TokenType::String, Some(Literal::String(value.to_string())));
+        self.add_token_literal(TokenType::String, Some(Literal::String(value)));
+    }
+
+    // How many `#` follow the `r` of a raw string before its opening quote, or
+    // `None` when the `r` starts an identifier
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let hashes = self._source[self._current..]
+            .iter()
+            .take_while(|&&c| c == '#')
+            .count();
+
+        (self._source.get(self._current + hashes) == Some(&'"')).then_some(hashes)
+    }
+
+    // Raw strings, like `r"C:\path"`, take their contents as written: a
+    // backslash is just a backslash, even at the end of a line. To contain a
+    // `"`, put the same number of `#` around the quotes, as in
+    // `r#"say "hi""#`; the string ends at the first `"` followed by that many
+    // `#`.
+    fn scan_raw_string(&mut self, hashes: usize) {
+        // The hashes and the opening "
+        self._current += hashes + 1;
+
+        let closing = std::iter::once('"')
+            .chain(std::iter::repeat_n('#', hashes))
+            .collect::<Vec<_>>();
+
+        while !self._source[self._current..].starts_with(&closing) {
+            match self.advance() {
+                Some('\n') => self.new_line(),
+                Some(_) => (),
+                None => {
+                    self.report("Unterminated string.");
+                    return;
+                }
+            }
+        }
+
+        let value = self.text(self._start + hashes + 2, self._current);
+        self._current += closing.len();
+        self.add_token_literal(TokenType::String, Some(Literal::String(value)));
     }
 
     fn scan_number(&mut self) {
@@ -170,14 +526,13 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        self.add_token_literal(
-            TokenType::Number,
-            Some(Literal::Number(
-                self.source[self._start..self._current]
-                    .parse::<f64>()
-                    .unwrap(),
-            )),
-        );
+        // `is_numeric` lets in digits that aren't ASCII, like `²`, which
+        // don't parse as a number
+        let text = self.text(self._start, self._current);
+        match text.parse::<f64>() {
+            Ok(number) => self.add_token_literal(TokenType::Number, Some(Literal::Number(number))),
+            Err(_) => self.report(&format!("Invalid number: '{}'", text)),
+        }
     }
 
     fn scan_identifier(&mut self) {
@@ -185,15 +540,22 @@ impl<'a> Scanner<'a> {
             self.advance();
         }
 
-        let text = &self.source[self._start..self._current];
-        let token_type = get_keyword_token_type(text);
-
-        let token = match token_type {
-            Some(token_type) => token_type,
-            None => TokenType::Identifier,
+        let text = self.text(self._start, self._current);
+        let token_type = match self._keywords.get(&text) {
+            Some(&token_type) => token_type,
+            None => get_keyword_token_type(&text).unwrap_or(TokenType::Identifier),
         };
 
-        self.add_token(token);
+        match (token_type, &self._interner) {
+            (TokenType::Identifier, Some(interner)) => {
+                let symbol = interner.borrow_mut().intern(&text);
+                let mut token = Token::identifier(symbol, self._line);
+                token.column = self._column;
+                token.offset = self._start;
+                self.tokens.push(token);
+            }
+            (token_type, _) => self.add_token(token_type),
+        }
     }
 
     fn advance(&mut self) -> Option<&char> {
@@ -232,32 +594,676 @@ impl<'a> Scanner<'a> {
 
     fn peek_next(&self) -> char {
         let next = self._current + 1;
-        if next >= self.source.len() {
+        if next >= self._source.len() {
             return '\0';
         }
         self._source[next]
     }
 
     fn is_at_end(&self) -> bool {
-        self._current >= self.source.len()
+        self._current >= self._source.len()
+    }
+
+    // Positions are char indices, so slice the chars rather than the source bytes
+    fn text(&self, start: usize, end: usize) -> String {
+        self._source[start..end].iter().collect()
     }
 
     fn add_token(&mut self, token: TokenType) {
         self.add_token_literal(token, None);
     }
 
+    fn add_comment(&mut self) {
+        if self._preserve_comments {
+            self.add_token(TokenType::Comment);
+        }
+    }
+
     fn add_token_literal(&mut self, token: TokenType, literal: Option<Literal>) {
-        let lexeme = &self.source[self._start..self._current];
-        let token = Token::new(token, lexeme, literal, self._line);
+        let lexeme = self.text(self._start, self._current);
+        let mut token = Token::new(token, &lexeme, literal, self._line);
+        token.column = self._column;
+        token.offset = self._start;
+        self._brackets.track(token.token_type, &self.tokens);
         self.tokens.push(token);
     }
 
-    fn report(&self, line: usize, place: &str, message: &str) {
+    // Inserts a semicolon at the current position when a statement can end
+    // there, see `set_auto_semicolons`
+    fn insert_semicolon(&mut self) {
+        if !self._auto_semicolons || !self._brackets.open.is_empty() {
+            return;
+        }
+
+        let last = self
+            .tokens
+            .iter()
+            .rfind(|token| token.token_type != TokenType::Comment);
+
+        let ends_statement = match last {
+            Some(token) => match token.token_type {
+                TokenType::RightParen => !self._brackets.closed_header,
+                token_type => matches!(
+                    token_type,
+                    TokenType::Identifier
+                        | TokenType::Number
+                        | TokenType::String
+                        | TokenType::True
+                        | TokenType::False
+                        | TokenType::Nil
+                        | TokenType::This
+                        | TokenType::Return
+                        | TokenType::Break
+                        | TokenType::Continue
+                        | TokenType::RightBracket
+                ),
+            },
+            None => false,
+        };
+
+        if ends_statement {
+            let mut token = Token::new(TokenType::Semicolon, "", None, self._line);
+            token.column = self._column;
+            token.offset = self._start;
+            self.tokens.push(token);
+        }
+    }
+
+    // Where the line containing the char at `position` starts
+    fn line_start(&self, position: usize) -> usize {
+        self._source[..position]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |index| index + 1)
+    }
+
+    fn new_line(&mut self) {
+        self._line += 1;
+        self._line_start = self._current;
+    }
+
+    // Reports an error at the start of the current lexeme
+    fn report(&self, message: &str) {
         match self._reporter {
-            Some(reporter) => reporter.report(line, place, message),
+            Some(reporter) => reporter.report(self._line, self._column, "", message),
 
             // Reporter does not exist, print to stderr
-            None => eprintln!("[line {}] Error {}: {}", line, place, message),
+            None => eprintln!("[line {}] Error: {}", self._line, message),
+        }
+    }
+}
+
+// The open brackets, which decide where semicolons are inserted, see
+// `Scanner::set_auto_semicolons`
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Brackets {
+    // One entry per open '(' or '[', true when it starts an `if`, `while`, `for` or
+    // `switch` header or a function's parameters
+    open: Vec<bool>,
+    closed_header: bool,
+}
+
+impl Brackets {
+    // Keeps track of a token about to be added after the `previous` ones
+    fn track(&mut self, token_type: TokenType, previous: &[Token]) {
+        match token_type {
+            TokenType::LeftParen => self.open.push(opens_header(previous)),
+            TokenType::RightParen => self.closed_header = self.open.pop().unwrap_or(false),
+            TokenType::LeftBracket => self.open.push(false),
+            TokenType::RightBracket => {
+                self.open.pop();
+                self.closed_header = false;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn opens_header(previous: &[Token]) -> bool {
+    let mut previous = previous
+        .iter()
+        .rev()
+        .map(|token| token.token_type)
+        .filter(|&token_type| token_type != TokenType::Comment);
+
+    matches!(
+        (previous.next(), previous.next()),
+        (
+            Some(TokenType::If | TokenType::While | TokenType::For | TokenType::Switch),
+            _
+        ) | (Some(TokenType::Identifier), Some(TokenType::Fun))
+    )
+}
+
+// The type of the last token that isn't a comment
+fn preceding_type(tokens: &[Token]) -> Option<TokenType> {
+    tokens
+        .iter()
+        .rev()
+        .map(|token| token.token_type)
+        .find(|&token_type| token_type != TokenType::Comment)
+}
+
+/// Scans the source lazily, one token per call to `next`, the last one being
+/// `Eof`. Errors are reported as the tokens around them are scanned. Tokens
+/// aren't kept once returned, so `stats` only counts those of `scan_tokens`.
+impl Iterator for Scanner<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while self._next >= self.tokens.len() {
+            if self._scanned_eof {
+                return None;
+            }
+
+            if self.is_at_end() {
+                self.scan_eof();
+            } else {
+                self.scan_lexeme();
+            }
+        }
+
+        let token = self.tokens[self._next].clone();
+        self._next += 1;
+
+        // Scanning only looks back at the last two tokens that aren't comments
+        let mut kept = self._next;
+        let mut code = 0;
+        while kept > 0 && code < 2 {
+            kept -= 1;
+            if self.tokens[kept].token_type != TokenType::Comment {
+                code += 1;
+            }
+        }
+        self.tokens.drain(..kept);
+        self._next -= kept;
+
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_debug_format() {
+        let tokens = Scanner::new("var a = \"hi\";\nprint a + 1.5;").scan_tokens();
+        let dump = tokens
+            .iter()
+            .map(|token| format!("{:?}", token))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            dump,
+            vec![
+                "[1] (Var) var None",
+                "[1] (Identifier) a None",
+                "[1] (Equal) = None",
+                "[1] (String) \"hi\" Some(String(\"hi\"))",
+                "[1] (Semicolon) ; None",
+                "[2] (Print) print None",
+                "[2] (Identifier) a None",
+                "[2] (Plus) + None",
+                "[2] (Number) 1.5 Some(Number(1.5))",
+                "[2] (Semicolon) ; None",
+                "[2] (Eof)  None",
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_share_interned_names() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut scanner = Scanner::new("var a = a + b;");
+        scanner.set_interner(Rc::clone(&interner));
+        let tokens = scanner.scan_tokens();
+
+        let a = interner.borrow_mut().intern("a");
+        assert_eq!(tokens[1].symbol, Some(a.clone()));
+        assert_eq!(tokens[3].symbol, Some(a));
+        assert!(Rc::ptr_eq(&tokens[1].lexeme, &tokens[3].lexeme));
+        assert_eq!(tokens[0].symbol, None);
+        assert_eq!(interner.borrow().len(), 2);
+    }
+
+    #[test]
+    fn tokens_record_columns() {
+        let tokens = Scanner::new("var s = \"a\nb\";\n  print s;").scan_tokens();
+        let positions = tokens
+            .iter()
+            .map(|token| (token.line, token.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1),
+                (1, 5),
+                (1, 7),
+                (2, 9),
+                (2, 3),
+                (3, 3),
+                (3, 9),
+                (3, 10),
+                (3, 11)
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_keywords_override_the_defaults() {
+        let keywords = HashMap::from([
+            ("function".to_string(), TokenType::Fun),
+            ("class".to_string(), TokenType::Identifier),
+        ]);
+        let types = Scanner::with_keywords("function class fun", keywords)
+            .scan_tokens()
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Fun,
+                TokenType::Identifier,
+                TokenType::Fun,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_summarize_the_scanned_program() {
+        let mut scanner = Scanner::new("var a = 1;\nprint a + a;\n");
+        scanner.scan_tokens();
+        let stats = scanner.stats();
+
+        assert_eq!(stats.lines, 2);
+        assert_eq!(stats.identifiers, HashMap::from([("a".to_string(), 3)]));
+        assert_eq!(stats.token_counts[&TokenType::Semicolon], 2);
+        assert_eq!(stats.token_counts[&TokenType::Eof], 1);
+        assert_eq!(stats.token_counts.values().sum::<usize>(), 11);
+
+        let mut scanner = Scanner::new("print 1;");
+        scanner.scan_tokens();
+        assert_eq!(scanner.stats().lines, 1);
+    }
+
+    #[test]
+    fn auto_semicolons_end_lines_that_end_statements() {
+        let types = |source: &str| {
+            let mut scanner = Scanner::new(source);
+            scanner.set_auto_semicolons(true);
+            scanner
+                .scan_tokens()
+                .iter()
+                .map(|token| token.token_type)
+                .collect::<Vec<_>>()
+        };
+        use TokenType::*;
+
+        assert_eq!(
+            types("var a = 1\nprint a"),
+            vec![Var, Identifier, Equal, Number, Semicolon, Print, Identifier, Semicolon, Eof]
+        );
+        // A trailing operator continues the expression
+        assert_eq!(
+            types("print 1 +\n  2\n"),
+            vec![Print, Number, Plus, Number, Semicolon, Eof]
+        );
+        // Existing semicolons and blank lines add nothing
+        assert_eq!(
+            types("f();\n\n"),
+            vec![Identifier, LeftParen, RightParen, Semicolon, Eof]
+        );
+        // Nor do line breaks inside parentheses
+        assert_eq!(
+            types("f(a,\n  b\n)"),
+            vec![Identifier, LeftParen, Identifier, Comma, Identifier, RightParen, Semicolon, Eof]
+        );
+        // Headers are followed by their statement, blocks need no semicolon
+        assert_eq!(
+            types("if (a)\n  print a\nfun f(x)\n{\n  return\n}\n"),
+            vec![
+                If, LeftParen, Identifier, RightParen, Print, Identifier, Semicolon, Fun,
+                Identifier, LeftParen, Identifier, RightParen, LeftBrace, Return, Semicolon,
+                RightBrace, Eof
+            ]
+        );
+        // Off by default
+        assert_eq!(Scanner::new("print a\n").scan_tokens().len(), 3);
+    }
+
+    #[test]
+    fn backslash_newline_continues_strings() {
+        let tokens = Scanner::new("print \"foo\\\nbar\";\nprint \"a\\b\nc\";").scan_tokens();
+
+        let string = |token: &Token| match &token.literal {
+            Some(Literal::String(string)) => string.clone(),
+            literal => panic!("not a string: {:?}", literal),
+        };
+
+        assert_eq!(string(&tokens[1]), "foobar");
+        assert_eq!(tokens[2].line, 2);
+        // Only a backslash right before the line break is special
+        assert_eq!(string(&tokens[4]), "a\\b\nc");
+        assert_eq!(tokens[4].line, 4);
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes() {
+        let strings = |source: &str| {
+            Scanner::new(source)
+                .scan_tokens()
+                .iter()
+                .filter_map(|token| match &token.literal {
+                    Some(Literal::String(string)) => Some(string.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            strings(r#"r"C:\path\no\escapes""#),
+            vec![r"C:\path\no\escapes"]
+        );
+        assert_eq!(strings("r\"a\\\nb\""), vec!["a\\\nb"]);
+        assert_eq!(
+            strings(r###"r#"say "hi""# r##"a "# b"##"###),
+            vec![r#"say "hi""#, r##"a "# b"##]
+        );
+
+        // `r` alone is still an identifier
+        let tokens = Scanner::new("r + r2").scan_tokens();
+        assert_eq!(tokens[0].token_type, TokenType::Identifier);
+        assert_eq!(tokens[2].lexeme.as_ref(), "r2");
+    }
+
+    #[test]
+    fn unterminated_raw_strings_are_errors() {
+        let reporter = ErrorReporter::new();
+        let mut scanner = Scanner::new("r#\"a\"\n");
+        scanner.set_error_reporter(&reporter);
+        scanner.scan_tokens();
+
+        assert!(reporter.has_error());
+        assert_eq!(reporter.diagnostics()[0].message, "Unterminated string.");
+        assert_eq!(reporter.diagnostics()[0].line, 2);
+    }
+
+    #[test]
+    fn numbers_that_do_not_parse_are_errors() {
+        // Too many digits for an f64 is infinity, not an error
+        let digits = "9".repeat(400);
+        let tokens = Scanner::new(&format!("{}.{}", digits, digits)).scan_tokens();
+        assert!(matches!(tokens[0].literal, Some(Literal::Number(n)) if n == f64::INFINITY));
+
+        let reporter = ErrorReporter::new();
+        let mut scanner = Scanner::new("1² + 2 + ٣;");
+        scanner.set_error_reporter(&reporter);
+        let types = scanner
+            .scan_tokens()
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+
+        use TokenType::*;
+        assert_eq!(types, vec![Plus, Number, Plus, Semicolon, Eof]);
+        let messages = reporter
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec!["Invalid number: '1²'", "Invalid number: '٣'"]
+        );
+    }
+
+    #[test]
+    fn dots_scan_as_ranges_after_numbers() {
+        let types = Scanner::new("1..3 a..=b x.y 1.5")
+            .scan_tokens()
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+        use TokenType::*;
+
+        assert_eq!(
+            types,
+            vec![
+                Number,
+                DotDot,
+                Number,
+                Identifier,
+                DotDotEqual,
+                Identifier,
+                Identifier,
+                Dot,
+                Identifier,
+                Number,
+                Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_tokens_only_when_preserved() {
+        let source = "print 1; // one\n/* two\n lines */ print 2 /* three */;";
+        let scan = |preserve_comments: bool| {
+            let mut scanner = Scanner::new(source);
+            scanner.set_preserve_comments(preserve_comments);
+            scanner
+                .scan_tokens()
+                .iter()
+                .map(|token| (token.token_type, token.lexeme.to_string(), token.line))
+                .collect::<Vec<_>>()
+        };
+        use TokenType::*;
+
+        let code = scan(false);
+        assert_eq!(
+            code.iter().map(|token| token.0).collect::<Vec<_>>(),
+            vec![Print, Number, Semicolon, Print, Number, Semicolon, Eof]
+        );
+        assert_eq!(code[3].2, 3);
+
+        let comments = scan(true)
+            .into_iter()
+            .filter(|token| token.0 == Comment)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            vec![
+                (Comment, "// one".to_string(), 1),
+                (Comment, "/* two\n lines */".to_string(), 3),
+                (Comment, "/* three */".to_string(), 3),
+            ]
+        );
+
+        // Nor do they change where semicolons go
+        let mut scanner = Scanner::new("print a // a\nprint b /* b\n */ print c");
+        scanner.set_auto_semicolons(true);
+        scanner.set_preserve_comments(true);
+        let types = scanner
+            .scan_tokens()
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                Print, Identifier, Comment, Semicolon, Print, Identifier, Semicolon, Comment,
+                Print, Identifier, Semicolon, Eof
+            ]
+        );
+
+        let reporter = ErrorReporter::new();
+        let mut scanner = Scanner::new("print 1; /* no end");
+        scanner.set_error_reporter(&reporter);
+        scanner.scan_tokens();
+        assert_eq!(reporter.diagnostics()[0].message, "Unterminated comment.");
+    }
+
+    #[test]
+    fn tokens_are_scanned_lazily() {
+        let reporter = ErrorReporter::new();
+        let mut scanner = Scanner::new("print 1;\nprint @;");
+        scanner.set_error_reporter(&reporter);
+
+        let first = scanner.by_ref().take(3).collect::<Vec<_>>();
+        assert_eq!(first[2].token_type, TokenType::Semicolon);
+        assert!(!reporter.has_error());
+
+        let rest = scanner.map(|token| token.token_type).collect::<Vec<_>>();
+        assert_eq!(
+            rest,
+            vec![TokenType::Print, TokenType::Semicolon, TokenType::Eof]
+        );
+        assert_eq!(
+            reporter.diagnostics()[0].message,
+            "Unexpected character: '@'"
+        );
+
+        // The same tokens as `scan_tokens`, auto semicolons included
+        let mut scanner = Scanner::new("if (a)\n  f(x)\n");
+        scanner.set_auto_semicolons(true);
+        let lazy = scanner
+            .map(|token| format!("{:?}", token))
+            .collect::<Vec<_>>();
+        let mut scanner = Scanner::new("if (a)\n  f(x)\n");
+        scanner.set_auto_semicolons(true);
+        let batch = scanner
+            .scan_tokens()
+            .iter()
+            .map(|token| format!("{:?}", token))
+            .collect::<Vec<_>>();
+        assert_eq!(lazy, batch);
+    }
+
+    // Scans the same source with `rescan` and `scan_tokens`, returning the
+    // tokens of each along with their positions
+    fn rescan_and_scan(
+        previous: &str,
+        edited: Range<usize>,
+        text: &str,
+        auto_semicolons: bool,
+        preserve_comments: bool,
+    ) -> (Vec<String>, Vec<String>) {
+        let source = format!(
+            "{}{}{}",
+            &previous[..edited.start],
+            text,
+            &previous[edited.end..]
+        );
+        let reporter = ErrorReporter::new();
+        let scan = |rescan: bool| {
+            let mut scanner = Scanner::new(previous);
+            scanner.set_auto_semicolons(auto_semicolons);
+            scanner.set_preserve_comments(preserve_comments);
+            scanner.set_error_reporter(&reporter);
+            let tokens = scanner.scan_tokens();
+
+            let mut scanner = Scanner::new(&source);
+            scanner.set_auto_semicolons(auto_semicolons);
+            scanner.set_preserve_comments(preserve_comments);
+            scanner.set_error_reporter(&reporter);
+            let tokens = match rescan {
+                true => scanner.rescan(previous, &tokens, edited.clone()),
+                false => scanner.scan_tokens(),
+            };
+
+            tokens
+                .iter()
+                .map(|t| format!("{:?} {}:{} @{}", t, t.line, t.column, t.offset))
+                .collect::<Vec<_>>()
+        };
+
+        (scan(true), scan(false))
+    }
+
+    #[test]
+    fn rescans_reuse_the_tokens_after_the_edit() {
+        let previous = "var a = 1;\nvar b = \"two\nlines\";\nprint @a;\n";
+        let (rescanned, scanned) = rescan_and_scan(previous, 8..9, "10 +\n 2", false, false);
+        assert_eq!(rescanned, scanned);
+        assert_eq!(scanned[5], "[2] (Number) 2 Some(Number(2.0)) 2:2 @14");
+        assert_eq!(scanned[12], "[5] (Print) print None 5:1 @38");
+
+        // The edit ends a string, so the rest scans differently
+        let (rescanned, scanned) = rescan_and_scan(previous, 16..17, "\"", true, false);
+        assert_eq!(rescanned, scanned);
+
+        // The text after the edit isn't scanned again, nor its errors reported
+        let reporter = ErrorReporter::new();
+        let mut scanner = Scanner::new(previous);
+        scanner.set_error_reporter(&reporter);
+        let tokens = scanner.scan_tokens();
+        assert_eq!(reporter.diagnostics().len(), 1);
+
+        let mut scanner = Scanner::new("var a = 2;\nvar b = \"two\nlines\";\nprint @a;\n");
+        scanner.set_error_reporter(&reporter);
+        scanner.rescan(previous, &tokens, 8..9);
+        assert_eq!(reporter.diagnostics().len(), 1);
+    }
+
+    // Compares `rescan` with `scan_tokens` after random edits of random sources
+    #[test]
+    fn rescans_match_full_scans() {
+        const PIECES: &[&str] = &[
+            "var", "x", "é", "fun", "if", "while", "print", "r", "#", "\"", "'", "\\", "1", "2.5",
+            ".", "..", "(", ")", "[", "]", "{", "}", ";", "=", "==", "+", "//", "/*", "*/", "@",
+            " ", " ", "\n", "\n",
+        ];
+
+        // xorshift, so that failures can be reproduced
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = |below: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize % below
+        };
+
+        for _ in 0..2000 {
+            let mut pieces = |count: usize| {
+                (0..count)
+                    .map(|_| PIECES[random(PIECES.len())])
+                    .collect::<String>()
+            };
+            let previous = pieces(40);
+            let text = pieces(3);
+
+            let boundaries = previous
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(previous.len()))
+                .collect::<Vec<_>>();
+            let mut start = boundaries[random(boundaries.len())];
+            let mut end = boundaries[random(boundaries.len())];
+            if start > end {
+                (start, end) = (end, start);
+            }
+            let auto_semicolons = random(2) == 0;
+            let preserve_comments = random(2) == 0;
+
+            let (rescanned, scanned) = rescan_and_scan(
+                &previous,
+                start..end,
+                &text,
+                auto_semicolons,
+                preserve_comments,
+            );
+            assert_eq!(
+                rescanned,
+                scanned,
+                "replacing {:?} of {:?} with {:?}",
+                start..end,
+                previous,
+                text
+            );
         }
     }
 }
@@ -0,0 +1,103 @@
+//! Entry point for running rlox in the browser, enabled by the `wasm`
+//! feature. Nothing here touches stdin, stdout or the process: output and
+//! errors are returned as text.
+//!
+//! Build the module with
+//! `cargo rustc -p interpreter --lib --release --features wasm --target wasm32-unknown-unknown --crate-type cdylib`
+//! and generate the JavaScript bindings with `wasm-bindgen`.
+
+use crate::error::{ErrorReporter, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::optimizer::Optimizer;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use std::io;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Runs `source` and returns what it printed, followed by its errors, each
+/// rendered with the line of source it points at.
+#[wasm_bindgen]
+pub fn eval_to_string(source: &str) -> String {
+    let mut reporter = ErrorReporter::new();
+    reporter.set_print(false);
+    let mut out = Vec::new();
+
+    let mut interpreter = Interpreter::with_writer(&mut out);
+    interpreter.set_error_reporter(&reporter);
+    // Without input, `readLine` returns nil
+    interpreter.set_reader(io::empty());
+    interpreter.set_filesystem_access(false);
+    // Neither the process, the system clock nor sleeping are available to wasm32-unknown-unknown
+    interpreter.define_native("exit", 1, |_, _| {
+        Err(RuntimeError::native("exit is not available here."))
+    });
+    interpreter.define_native("clock", 0, |_, _| {
+        Err(RuntimeError::native("clock is not available here."))
+    });
+    interpreter.define_native("now", 0, |_, _| {
+        Err(RuntimeError::native("now is not available here."))
+    });
+    interpreter.define_native("sleep", 1, |_, _| {
+        Err(RuntimeError::native("sleep is not available here."))
+    });
+
+    run(source, &reporter, &mut interpreter);
+    drop(interpreter);
+
+    let mut result = String::from_utf8_lossy(&out).into_owned();
+    for diagnostic in reporter.diagnostics() {
+        result.push_str(&diagnostic.render(source));
+        result.push('\n');
+    }
+    result
+}
+
+fn run(source: &str, reporter: &ErrorReporter, interpreter: &mut Interpreter) {
+    let mut scanner = Scanner::new(source);
+    scanner.set_error_reporter(reporter);
+    scanner.set_interner(interpreter.interner());
+    let tokens = scanner.scan_tokens();
+
+    if reporter.has_error() {
+        return;
+    }
+
+    let mut parser = Parser::new(tokens);
+    parser.set_error_reporter(reporter);
+    let mut statements = parser.parse();
+
+    if reporter.has_error() {
+        return;
+    }
+
+    Optimizer::new().optimize(&mut statements);
+
+    let mut resolver = Resolver::new(interpreter);
+    resolver.set_error_reporter(reporter);
+    resolver.resolve(&statements);
+
+    if reporter.has_error() {
+        return;
+    }
+
+    interpreter.run(statements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_output_and_errors() {
+        assert_eq!(eval_to_string("print 1 + 2; print \"a\";"), "3\na\n");
+        assert_eq!(
+            eval_to_string("print 1;\nprint -nil;"),
+            "1\n[line 2, column 7] Error at '-': operands must be numeric for operation\n2 | print -nil;\n  |       ^^^^\n"
+        );
+        assert_eq!(
+            eval_to_string("print readLine(); exit(1);"),
+            "nil\n[line 1, column 25] Error at ')': exit is not available here.\n1 | print readLine(); exit(1);\n  |                   ^^^^^^^\n"
+        );
+    }
+}
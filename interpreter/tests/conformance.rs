@@ -0,0 +1,241 @@
+//! Runs the `.lox` scripts under `tests/scripts` and checks their output
+//! against the expectations written in their comments, in the format of the
+//! Crafting Interpreters test suite:
+//!
+//! ```text
+//! print 1 + 2; // expect: 3
+//! print -nil;  // expect runtime error: operands must be numeric for operation
+//! var a = ;    // Error at ';': Expected expression.
+//! // [line 7] Error at end: Expect '}' after block.
+//! ```
+//!
+//! Scripts with scan, parse or resolve errors are not run.
+
+use rlox::error::{ErrorReporter, Severity};
+use rlox::interpreter::Interpreter;
+use rlox::optimizer::Optimizer;
+use rlox::parser::Parser;
+use rlox::resolver::Resolver;
+use rlox::scanner::Scanner;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// What a script is expected to do, from its comments.
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    /// Printed lines, in order.
+    output: Vec<String>,
+    /// Line and message of each scan, parse or resolve error.
+    errors: Vec<(usize, String)>,
+    /// Line and message of the runtime error that ends the script.
+    runtime_error: Option<(usize, String)>,
+}
+
+/// What a script did.
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    errors: Vec<(usize, String)>,
+    runtime_error: Option<(usize, String)>,
+}
+
+fn parse_expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+
+        // Markers are searched anywhere in the line, so `//` inside a string
+        // literal before them doesn't matter
+        if let Some(output) = after(text, "// expect: ") {
+            expectations.output.push(output.to_string());
+        } else if let Some(message) = after(text, "// expect runtime error: ") {
+            expectations.runtime_error = Some((line, message.to_string()));
+        } else if let Some(start) = text.find("// Error").or(text.find("// [line ")) {
+            if let Some(error) = parse_error(line, &text[start + 3..]) {
+                expectations.errors.push(error);
+            }
+        }
+    }
+
+    expectations
+}
+
+fn after<'a>(text: &'a str, marker: &str) -> Option<&'a str> {
+    text.find(marker).map(|start| &text[start + marker.len()..])
+}
+
+// Parses `Error at 'x': message`, `Error at end: message` or `Error: message`,
+// optionally prefixed by the line they are reported at: `[line 3] Error...`
+fn parse_error(line: usize, comment: &str) -> Option<(usize, String)> {
+    let (line, error) = match comment.strip_prefix("[line ") {
+        Some(rest) => {
+            let (number, error) = rest.split_once("] ")?;
+            (number.parse().ok()?, error)
+        }
+        None => (line, comment),
+    };
+
+    let rest = error.strip_prefix("Error")?;
+    let message = if let Some(rest) = rest.strip_prefix(" at '") {
+        // The lexeme may itself be a quote or a colon
+        let end = rest[1..].find("': ")? + 1;
+        &rest[end + 3..]
+    } else if let Some(rest) = rest.strip_prefix(" at end: ") {
+        rest
+    } else {
+        rest.strip_prefix(": ")?
+    };
+
+    Some((line, message.to_string()))
+}
+
+fn run(source: &str, optimize: bool) -> Outcome {
+    let mut reporter = ErrorReporter::new();
+    reporter.set_print(false);
+    let mut out = Vec::new();
+    let mut outcome = Outcome::default();
+
+    let mut interpreter = Interpreter::with_writer(&mut out);
+    let mut scanner = Scanner::new(source);
+    scanner.set_error_reporter(&reporter);
+    scanner.set_interner(interpreter.interner());
+    let tokens = scanner.scan_tokens();
+
+    let mut parser = Parser::new(tokens);
+    parser.set_error_reporter(&reporter);
+    let mut statements = parser.parse();
+
+    if !reporter.has_error() {
+        if optimize {
+            Optimizer::new().optimize(&mut statements);
+        }
+
+        let mut resolver = Resolver::new(&mut interpreter);
+        resolver.set_error_reporter(&reporter);
+        resolver.resolve(&statements);
+    }
+
+    if !reporter.has_error() {
+        if let Err(e) = interpreter.interpret(statements) {
+            outcome.runtime_error = Some((e.token.line, e.message));
+        }
+    }
+    drop(interpreter);
+
+    outcome.errors = reporter
+        .diagnostics()
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| (diagnostic.line, diagnostic.message))
+        .collect();
+    outcome.output = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+
+    outcome
+}
+
+/// Describes how `outcome` differs from `expected`, one difference per line.
+fn diff(expected: &Expectations, outcome: &Outcome) -> Vec<String> {
+    let mut differences = vec![];
+    let lines = expected.output.len().max(outcome.output.len());
+
+    for index in 0..lines {
+        match (expected.output.get(index), outcome.output.get(index)) {
+            (Some(e), Some(o)) if e == o => (),
+            (Some(e), Some(o)) => differences.push(format!("expected output {:?}, got {:?}", e, o)),
+            (Some(e), None) => differences.push(format!("missing output {:?}", e)),
+            (None, Some(o)) => differences.push(format!("unexpected output {:?}", o)),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    if expected.errors != outcome.errors {
+        differences.push(format!(
+            "expected errors {:?}, got {:?}",
+            expected.errors, outcome.errors
+        ));
+    }
+
+    if expected.runtime_error != outcome.runtime_error {
+        differences.push(format!(
+            "expected runtime error {:?}, got {:?}",
+            expected.runtime_error, outcome.runtime_error
+        ));
+    }
+
+    differences
+}
+
+// Every `.lox` file under `dir`, recursively
+fn scripts(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut found = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.extend(scripts(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            found.push(path);
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+#[test]
+fn scripts_match_their_expectations() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut failures = vec![];
+    let scripts = scripts(&dir).unwrap();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    for script in scripts {
+        let source = fs::read_to_string(&script).unwrap();
+        let expectations = parse_expectations(&source);
+        // Optimizing a program doesn't change what it does
+        let mut differences = diff(&expectations, &run(&source, false));
+        for difference in diff(&expectations, &run(&source, true)) {
+            differences.push(format!("optimized: {}", difference));
+        }
+
+        if !differences.is_empty() {
+            let name = script.strip_prefix(&dir).unwrap().display();
+            failures.push(format!("{}:\n  {}", name, differences.join("\n  ")));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n"));
+}
+
+#[test]
+fn expectations_are_read_from_comments() {
+    let source = "\
+print 1; // expect: 1
+print \"a // b\"; // expect: a // b
+var a = ; // Error at ';': Expected expression.
+a(':'); // Error at ':': Unexpected.
+// [line 9] Error at end: Expect '}' after block.
+// Error: Unterminated string.
+-nil; // expect runtime error: operands must be numeric for operation
+// A comment that expects nothing
+";
+
+    assert_eq!(
+        parse_expectations(source),
+        Expectations {
+            output: vec!["1".to_string(), "a // b".to_string()],
+            errors: vec![
+                (3, "Expected expression.".to_string()),
+                (4, "Unexpected.".to_string()),
+                (9, "Expect '}' after block.".to_string()),
+                (6, "Unterminated string.".to_string()),
+            ],
+            runtime_error: Some((7, "operands must be numeric for operation".to_string())),
+        }
+    );
+}